@@ -0,0 +1,479 @@
+//! File-based step gates that coordinate a test with its sqlite3 processes.
+
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::sqlite3process::quote_arg;
+
+/// How long each wait on a [`Barrier`] lasts before it fails, by default.
+pub const DEFAULT_BARRIER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often waiting participants check the barrier directory.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Script run by a sqlite3 child at a gate: `gate.sh DIR STEP POLLS`.
+const GATE_SCRIPT: &str = r#"dir=$1 step=$2 polls=$3
+echo "arrived-$step" >> "$dir/log"
+touch "$dir/arrived-$step"
+while [ ! -e "$dir/released-$step" ]; do
+  polls=$((polls - 1))
+  if [ "$polls" -lt 0 ]; then
+    echo "Barrier step $step timed out" >&2
+    exit 1
+  fi
+  sleep 0.01
+done
+echo "passed-$step" >> "$dir/log"
+touch "$dir/passed-$step"
+"#;
+
+/// Step gates for scripting an exact interleaving across processes.
+///
+/// Each named step is a gate that one participant blocks at until the test
+/// releases it. Participants are [`Sqlite3Process`](crate::Sqlite3Process)
+/// children, which block through [`gate_command`](Self::gate_command) or, for
+/// a self-hosted worker, [`worker_gate_command`](Self::worker_gate_command),
+/// and threads or worker processes, which call [`gate`](Self::gate) on a
+/// barrier created for the same directory.
+///
+/// Progress is recorded as marker files and a shared log in the directory, so
+/// every process sees the same state and [`log`](Self::log) can report when each step was
+/// reached, released and passed. Every wait fails after the barrier's timeout
+/// instead of hanging on a stalled interleaving. Each step name can be used
+/// once per directory.
+///
+/// # Example
+///
+/// ```rust
+/// use sqlite_test_utils::{Barrier, Sqlite3Process};
+///
+/// let dir = tempfile::tempdir().unwrap();
+/// let db_path = dir.path().join("test.db");
+/// let barrier = Barrier::new(&dir.path().join("barrier")).unwrap();
+/// let mut a = Sqlite3Process::new(&db_path).unwrap();
+/// let mut b = Sqlite3Process::new(&db_path).unwrap();
+/// a.execute("CREATE TABLE t (x);").unwrap();
+///
+/// // A begins and inserts, then waits at the gate before committing.
+/// let script = format!(
+///     "BEGIN; INSERT INTO t VALUES (1);\n{}\nCOMMIT;",
+///     barrier.gate_command("a_inserted").unwrap()
+/// );
+/// let pending = a.submit(&script).unwrap();
+///
+/// barrier.wait_arrived("a_inserted").unwrap();
+/// assert_eq!(b.execute("SELECT COUNT(*) FROM t;").unwrap(), "0\n");
+/// barrier.release("a_inserted").unwrap();
+/// pending.collect().unwrap();
+/// assert_eq!(b.execute("SELECT COUNT(*) FROM t;").unwrap(), "1\n");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Barrier {
+    dir: PathBuf,
+    timeout: Duration,
+}
+
+/// What happened at a step of a [`Barrier`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BarrierEventKind {
+    /// A participant reached the gate and started waiting.
+    Arrived,
+    /// The test released the gate.
+    Released,
+    /// The participant went through the gate.
+    Passed,
+}
+
+/// One entry of a [`Barrier`] log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BarrierEvent {
+    /// When the event happened.
+    pub at: SystemTime,
+    /// The step name.
+    pub step: String,
+    /// What happened.
+    pub kind: BarrierEventKind,
+}
+
+impl BarrierEventKind {
+    /// Prefix of the marker file recording this event.
+    fn prefix(self) -> &'static str {
+        match self {
+            BarrierEventKind::Arrived => "arrived-",
+            BarrierEventKind::Released => "released-",
+            BarrierEventKind::Passed => "passed-",
+        }
+    }
+}
+
+impl Barrier {
+    /// Creates or joins the barrier stored in `dir`, creating it if needed.
+    ///
+    /// Processes that create a barrier for the same directory share its steps.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory or the gate script cannot be written.
+    ///
+    pub fn new(dir: &Path) -> Result<Self, String> {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create barrier dir: {e}"))?;
+        fs::write(dir.join("gate.sh"), GATE_SCRIPT)
+            .map_err(|e| format!("Failed to write gate script: {e}"))?;
+        Ok(Barrier {
+            dir: dir.to_path_buf(),
+            timeout: DEFAULT_BARRIER_TIMEOUT,
+        })
+    }
+
+    /// Joins the barrier in `dir` as a `.gate` command names it, without
+    /// rewriting the gate script other participants may be running.
+    pub(crate) fn from_gate_args(dir: &Path, polls: u32) -> Self {
+        Barrier {
+            dir: dir.to_path_buf(),
+            timeout: POLL_INTERVAL * polls,
+        }
+    }
+
+    /// Sets how long each wait lasts before it fails.
+    ///
+    /// Defaults to [`DEFAULT_BARRIER_TIMEOUT`].
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Returns the directory holding the barrier's state.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Blocks the calling participant at `step` until the test releases it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `step` is not a valid name or the gate is not
+    /// released within the timeout.
+    ///
+    pub fn gate(&self, step: &str) -> Result<(), String> {
+        self.mark(step, BarrierEventKind::Arrived)?;
+        self.wait_for(step, BarrierEventKind::Released)?;
+        self.mark(step, BarrierEventKind::Passed)
+    }
+
+    /// Returns a dot-command that blocks a sqlite3 process at `step`.
+    ///
+    /// Put it on its own line of the SQL sent to the process. The command runs
+    /// a shell script through `.shell`, so it does not work in safe mode or
+    /// with the self-hosted worker; use
+    /// [`worker_gate_command`](Self::worker_gate_command) there. The script
+    /// polls with `sleep 0.01`, which needs a `sleep` that takes fractional
+    /// seconds, as GNU coreutils, the BSDs and busybox do. If the gate is not
+    /// released within the timeout the script reports an error on stderr and
+    /// the process goes on.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `step` is not a valid name.
+    ///
+    pub fn gate_command(&self, step: &str) -> Result<String, String> {
+        check_step(step)?;
+        Ok(format!(
+            ".shell sh {} {}",
+            quote_arg(&self.dir.join("gate.sh").to_string_lossy()),
+            self.gate_args(step)
+        ))
+    }
+
+    /// Returns a dot-command that blocks a
+    /// [`self_hosted`](crate::Sqlite3ProcessBuilder::self_hosted) worker at `step`.
+    ///
+    /// Put it on its own line of the SQL sent to the worker. If the gate is not
+    /// released within the timeout the worker reports an error on stderr and
+    /// goes on.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `step` is not a valid name.
+    ///
+    pub fn worker_gate_command(&self, step: &str) -> Result<String, String> {
+        check_step(step)?;
+        Ok(format!(".gate {}", self.gate_args(step)))
+    }
+
+    /// Formats the `DIR STEP POLLS` arguments of a gate command.
+    fn gate_args(&self, step: &str) -> String {
+        let polls = self.timeout.as_millis() / POLL_INTERVAL.as_millis();
+        format!("{} {step} {polls}", quote_arg(&self.dir.to_string_lossy()))
+    }
+
+    /// Waits until a participant has arrived at `step`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, including the log so far, if no participant arrives
+    /// within the timeout.
+    ///
+    pub fn wait_arrived(&self, step: &str) -> Result<(), String> {
+        self.wait_for(step, BarrierEventKind::Arrived)
+    }
+
+    /// Releases the participant waiting at `step`, or the one that arrives next.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `step` is not a valid name or the marker cannot be written.
+    ///
+    pub fn release(&self, step: &str) -> Result<(), String> {
+        self.mark(step, BarrierEventKind::Released)
+    }
+
+    /// Waits until a participant has arrived at `step`, then releases it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no participant arrives within the timeout.
+    ///
+    pub fn step(&self, step: &str) -> Result<(), String> {
+        self.wait_arrived(step)?;
+        self.release(step)
+    }
+
+    /// Waits until the participant released at `step` has gone through it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the step is not passed within the timeout.
+    ///
+    pub fn wait_passed(&self, step: &str) -> Result<(), String> {
+        self.wait_for(step, BarrierEventKind::Passed)
+    }
+
+    /// Returns every recorded event, oldest first.
+    pub fn log(&self) -> Vec<BarrierEvent> {
+        // Marker timestamps can be too coarse to order events, so the order
+        // comes from the shared log file that every mark appends to.
+        let log = fs::read_to_string(self.dir.join("log")).unwrap_or_default();
+        log.lines()
+            .filter_map(|name| {
+                let (kind, step) = [
+                    BarrierEventKind::Arrived,
+                    BarrierEventKind::Released,
+                    BarrierEventKind::Passed,
+                ]
+                .into_iter()
+                .find_map(|kind| Some((kind, name.strip_prefix(kind.prefix())?)))?;
+                Some(BarrierEvent {
+                    at: fs::metadata(self.dir.join(name)).ok()?.modified().ok()?,
+                    step: step.to_string(),
+                    kind,
+                })
+            })
+            .collect()
+    }
+
+    /// Records `kind` for `step` in the log, then creates its marker file.
+    ///
+    /// Waiters only see the marker once the log line is written, so the log
+    /// keeps the order in which events happened.
+    fn mark(&self, step: &str, kind: BarrierEventKind) -> Result<(), String> {
+        check_step(step)?;
+        let name = format!("{}{step}", kind.prefix());
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join("log"))
+            .and_then(|mut log| log.write_all(format!("{name}\n").as_bytes()))
+            .and_then(|()| File::create(self.dir.join(&name)))
+            .map_err(|e| format!("Failed to mark barrier step {step}: {e}"))?;
+        Ok(())
+    }
+
+    /// Polls until `kind` is recorded for `step` or the timeout expires.
+    fn wait_for(&self, step: &str, kind: BarrierEventKind) -> Result<(), String> {
+        check_step(step)?;
+        let marker = self.marker(step, kind);
+        let start = Instant::now();
+        while !marker.exists() {
+            if start.elapsed() > self.timeout {
+                return Err(format!(
+                    "Barrier step {step} not {kind:?} within {:?}\n{}",
+                    self.timeout,
+                    BarrierLog(&self.log())
+                ));
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        Ok(())
+    }
+
+    fn marker(&self, step: &str, kind: BarrierEventKind) -> PathBuf {
+        self.dir.join(format!("{}{step}", kind.prefix()))
+    }
+}
+
+/// Renders a barrier log, one event per line, relative to the first event.
+struct BarrierLog<'a>(&'a [BarrierEvent]);
+
+impl fmt::Display for BarrierLog<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "--- barrier log ---")?;
+        let start = self.0.first().map(|event| event.at);
+        for event in self.0 {
+            let offset = start
+                .and_then(|start| event.at.duration_since(start).ok())
+                .unwrap_or_default();
+            writeln!(
+                f,
+                "[+{:.3}s] {}: {:?}",
+                offset.as_secs_f64(),
+                event.step,
+                event.kind
+            )?;
+        }
+        write!(f, "--- end of barrier log ---")
+    }
+}
+
+/// Accepts step names that are safe in file names and shell arguments.
+fn check_step(step: &str) -> Result<(), String> {
+    if !step.is_empty()
+        && step
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        Ok(())
+    } else {
+        Err(format!(
+            "Invalid barrier step {step:?}: use letters, digits, '_' and '-'"
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Sqlite3Process;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_interleaves_processes() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+        let barrier = Barrier::new(&dir.path().join("barrier")).unwrap();
+        let mut a = Sqlite3Process::new(&db_path).unwrap();
+        let mut b = Sqlite3Process::new(&db_path).unwrap();
+        a.execute_checked("CREATE TABLE t (x);").unwrap();
+
+        let script = format!(
+            "BEGIN; INSERT INTO t VALUES (1);\n{}\nCOMMIT;\n{}",
+            barrier.gate_command("a_inserted").unwrap(),
+            barrier.gate_command("a_committed").unwrap()
+        );
+        let mut pending = a.submit(&script).unwrap();
+
+        barrier.wait_arrived("a_inserted").unwrap();
+        assert_eq!(b.execute("SELECT COUNT(*) FROM t;").unwrap(), "0\n");
+        barrier.release("a_inserted").unwrap();
+        barrier.wait_arrived("a_committed").unwrap();
+        assert_eq!(b.execute("SELECT COUNT(*) FROM t;").unwrap(), "1\n");
+        assert!(!pending.is_ready());
+        barrier.release("a_committed").unwrap();
+        assert_eq!(
+            pending.wait(Duration::from_secs(10)),
+            Some(Ok(String::new()))
+        );
+        drop(pending);
+        assert!(a.last_stderr().is_empty(), "{}", a.last_stderr());
+
+        let log: Vec<(String, BarrierEventKind)> = barrier
+            .log()
+            .into_iter()
+            .map(|event| (event.step, event.kind))
+            .collect();
+        assert_eq!(
+            log,
+            [
+                ("a_inserted", BarrierEventKind::Arrived),
+                ("a_inserted", BarrierEventKind::Released),
+                ("a_inserted", BarrierEventKind::Passed),
+                ("a_committed", BarrierEventKind::Arrived),
+                ("a_committed", BarrierEventKind::Released),
+                ("a_committed", BarrierEventKind::Passed),
+            ]
+            .map(|(step, kind)| (step.to_string(), kind))
+        );
+    }
+
+    #[test]
+    fn test_thread_participant() {
+        let dir = tempdir().unwrap();
+        let barrier = Barrier::new(dir.path()).unwrap();
+        let participant = barrier.clone();
+        let worker = thread::spawn(move || participant.gate("ready"));
+
+        barrier.step("ready").unwrap();
+        worker.join().unwrap().unwrap();
+        barrier.wait_passed("ready").unwrap();
+    }
+
+    #[test]
+    fn test_stalled_steps_time_out() {
+        let dir = tempdir().unwrap();
+        let barrier = Barrier::new(dir.path())
+            .unwrap()
+            .timeout(Duration::from_millis(100));
+
+        let error = barrier.wait_arrived("never").unwrap_err();
+        assert!(error.contains("Barrier step never not Arrived"), "{error}");
+        assert!(barrier.gate("unreleased").is_err());
+        assert!(error.contains("--- barrier log ---"));
+
+        let mut process = Sqlite3Process::new(&dir.path().join("test.db")).unwrap();
+        let gate = barrier.gate_command("child_unreleased").unwrap();
+        let error = process.execute_checked(&gate).unwrap_err();
+        assert!(
+            error.contains("Barrier step child_unreleased timed out"),
+            "{error}"
+        );
+
+        assert!(barrier.gate_command("no spaces").is_err());
+    }
+
+    #[test]
+    fn test_self_hosted_participant() {
+        let dir = tempdir().unwrap();
+        let barrier = Barrier::new(&dir.path().join("barrier")).unwrap();
+        let mut process = Sqlite3Process::builder()
+            .self_hosted()
+            .spawn(&dir.path().join("test.db"))
+            .unwrap();
+
+        let script = format!(
+            "CREATE TABLE t (x);\n{}\nSELECT COUNT(*) FROM t;",
+            barrier.worker_gate_command("created").unwrap()
+        );
+        let mut pending = process.submit(&script).unwrap();
+        barrier.wait_arrived("created").unwrap();
+        assert!(!pending.is_ready());
+        barrier.release("created").unwrap();
+        assert_eq!(
+            pending.wait(Duration::from_secs(10)),
+            Some(Ok("0\n".to_string()))
+        );
+        drop(pending);
+        barrier.wait_passed("created").unwrap();
+
+        let barrier = barrier.timeout(Duration::from_millis(100));
+        let gate = barrier.worker_gate_command("unreleased").unwrap();
+        let error = process.execute_checked(&gate).unwrap_err();
+        assert!(
+            error.contains("Barrier step unreleased not Released"),
+            "{error}"
+        );
+    }
+}
@@ -0,0 +1,321 @@
+//! A minimal statement executor shared by in-process and external connections.
+
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection, OptionalExtension, Savepoint, Transaction};
+
+use crate::worker::format_real;
+use crate::Sqlite3Process;
+
+/// Something the test-data helpers can run SQL through.
+///
+/// Implemented for `rusqlite` connections, transactions and savepoints (by
+/// value, `&` or `&mut`) and for [`Sqlite3Process`] (through
+/// `&mut Sqlite3Process`), so [`init_test_db`](crate::init_test_db) and the
+/// other helpers produce the same seeded dataset from either side.
+///
+/// Statements use numbered placeholders (`?1`, `?2`, ...) because the sqlite3
+/// shell cannot bind bare `?` parameters.
+pub trait Executor {
+    /// The error returned when a statement fails.
+    type Error;
+
+    /// Executes one or more statements without parameters.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any statement fails.
+    fn exec_batch(&mut self, sql: &str) -> Result<(), Self::Error>;
+
+    /// Executes a single statement with positional parameters.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the statement fails.
+    fn exec(&mut self, sql: &str, params: &[Value]) -> Result<(), Self::Error>;
+
+    /// Executes a single statement once for each parameter row.
+    ///
+    /// The default runs [`exec`](Self::exec) in a loop; implementations can
+    /// batch the rows instead.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any execution fails.
+    fn exec_many(&mut self, sql: &str, param_rows: &[Vec<Value>]) -> Result<(), Self::Error> {
+        for params in param_rows {
+            self.exec(sql, params)?;
+        }
+        Ok(())
+    }
+
+    /// Runs a single-column query and returns its first row as text.
+    ///
+    /// Returns `None` if the query produced no rows. `NULL` is returned as an
+    /// empty string, as the sqlite3 shell prints it. Both implementations
+    /// return the same text for the same value, including text spanning lines.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query fails.
+    fn query_text(&mut self, sql: &str, params: &[Value]) -> Result<Option<String>, Self::Error>;
+
+    /// Returns the row ID of the most recent successful insert.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the row ID cannot be queried.
+    fn last_insert_rowid(&mut self) -> Result<i64, Self::Error>;
+}
+
+/// Implements [`Executor`] for types that dereference to a [`Connection`].
+///
+/// Listed one by one because a blanket impl over `Deref<Target = Connection>`
+/// would overlap with the one for `&Connection`. `&mut` references are covered
+/// by the impl for `&mut T`.
+macro_rules! connection_executor {
+    ($($connection:ty),*) => {$(
+        impl Executor for $connection {
+            type Error = rusqlite::Error;
+
+            fn exec_batch(&mut self, sql: &str) -> Result<(), Self::Error> {
+                Connection::execute_batch(self, sql)
+            }
+
+            fn exec(&mut self, sql: &str, params: &[Value]) -> Result<(), Self::Error> {
+                Connection::execute(self, sql, params_from_iter(params))?;
+                Ok(())
+            }
+
+            fn exec_many(
+                &mut self,
+                sql: &str,
+                param_rows: &[Vec<Value>],
+            ) -> Result<(), Self::Error> {
+                let mut stmt = self.prepare(sql)?;
+                for params in param_rows {
+                    stmt.execute(params_from_iter(params))?;
+                }
+                Ok(())
+            }
+
+            fn query_text(
+                &mut self,
+                sql: &str,
+                params: &[Value],
+            ) -> Result<Option<String>, Self::Error> {
+                let value: Option<Value> = self
+                    .query_row(sql, params_from_iter(params), |row| row.get(0))
+                    .optional()?;
+                Ok(value.map(value_to_text))
+            }
+
+            fn last_insert_rowid(&mut self) -> Result<i64, Self::Error> {
+                Ok(Connection::last_insert_rowid(self))
+            }
+        }
+    )*};
+}
+
+connection_executor!(
+    Connection,
+    &Connection,
+    Transaction<'_>,
+    &Transaction<'_>,
+    Savepoint<'_>,
+    &Savepoint<'_>
+);
+
+impl Executor for Sqlite3Process {
+    type Error = String;
+
+    fn exec_batch(&mut self, sql: &str) -> Result<(), Self::Error> {
+        self.execute_checked(sql)?;
+        Ok(())
+    }
+
+    fn exec(&mut self, sql: &str, params: &[Value]) -> Result<(), Self::Error> {
+        self.execute_params(sql, params)?;
+        Ok(())
+    }
+
+    fn exec_many(&mut self, sql: &str, param_rows: &[Vec<Value>]) -> Result<(), Self::Error> {
+        // One round trip for all rows instead of one per row.
+        let script = param_rows
+            .iter()
+            .map(|params| Self::params_script(sql, params))
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.execute_checked(&script)?;
+        Ok(())
+    }
+
+    fn query_text(&mut self, sql: &str, params: &[Value]) -> Result<Option<String>, Self::Error> {
+        // List mode cannot tell NULL from empty text and splits text on
+        // newlines, so print the value encoded instead.
+        let sql = sql.trim_end().trim_end_matches(';');
+        let encoded = format!(
+            "WITH q(v) AS ({sql}) SELECT typeof(v), \
+             CASE typeof(v) WHEN 'real' THEN quote(v) ELSE hex(v) END FROM q LIMIT 1"
+        );
+        let output = self.execute_params(&encoded, params)?;
+        if output.is_empty() {
+            return Ok(None);
+        }
+        let value = decode_value(output.trim_end())
+            .ok_or_else(|| format!("Unexpected encoded value {output:?}"))?;
+        Ok(Some(value_to_text(value)))
+    }
+
+    fn last_insert_rowid(&mut self) -> Result<i64, Self::Error> {
+        let output = self.execute_checked("SELECT last_insert_rowid();")?;
+        output
+            .trim()
+            .parse()
+            .map_err(|e| format!("Invalid last_insert_rowid {output:?}: {e}"))
+    }
+}
+
+impl<T: Executor + ?Sized> Executor for &mut T {
+    type Error = T::Error;
+
+    fn exec_batch(&mut self, sql: &str) -> Result<(), Self::Error> {
+        (**self).exec_batch(sql)
+    }
+
+    fn exec(&mut self, sql: &str, params: &[Value]) -> Result<(), Self::Error> {
+        (**self).exec(sql, params)
+    }
+
+    fn exec_many(&mut self, sql: &str, param_rows: &[Vec<Value>]) -> Result<(), Self::Error> {
+        (**self).exec_many(sql, param_rows)
+    }
+
+    fn query_text(&mut self, sql: &str, params: &[Value]) -> Result<Option<String>, Self::Error> {
+        (**self).query_text(sql, params)
+    }
+
+    fn last_insert_rowid(&mut self) -> Result<i64, Self::Error> {
+        (**self).last_insert_rowid()
+    }
+}
+
+/// Renders a value the way the sqlite3 shell prints it in list mode.
+fn value_to_text(value: Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Integer(integer) => integer.to_string(),
+        Value::Real(real) => format_real(real),
+        Value::Text(text) => text,
+        Value::Blob(blob) => String::from_utf8_lossy(&blob).into_owned(),
+    }
+}
+
+/// Decodes a `TYPE|ENCODED` line, where reals are `quote()`d and every other
+/// value is `hex()` of its text or bytes.
+fn decode_value(line: &str) -> Option<Value> {
+    let (kind, encoded) = line.split_once('|')?;
+    if kind == "real" {
+        return encoded.parse().ok().map(Value::Real);
+    }
+    let bytes = (0..encoded.len())
+        .step_by(2)
+        .map(|at| u8::from_str_radix(encoded.get(at..at + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    match kind {
+        "null" => Some(Value::Null),
+        "integer" => String::from_utf8(bytes)
+            .ok()?
+            .parse()
+            .ok()
+            .map(Value::Integer),
+        "text" => String::from_utf8(bytes).ok().map(Value::Text),
+        "blob" => Some(Value::Blob(bytes)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{init_test_db, insert_test_db, read_row, update_test_db};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_process_and_connection_produce_same_dataset() {
+        let dir = tempdir().unwrap();
+        let conn = Connection::open(dir.path().join("conn.db")).unwrap();
+        let mut process = Sqlite3Process::new(&dir.path().join("process.db")).unwrap();
+
+        init_test_db(&conn, "main", 42, 25, 10).unwrap();
+        init_test_db(&mut process, "main", 42, 25, 10).unwrap();
+
+        fastrand::seed(7);
+        let conn_id = insert_test_db(&conn, "main", 10).unwrap();
+        update_test_db(&conn, "main", 3, 10).unwrap();
+        fastrand::seed(7);
+        let process_id = insert_test_db(&mut process, "main", 10).unwrap();
+        update_test_db(&mut process, "main", 3, 10).unwrap();
+        assert_eq!(conn_id, 26);
+        assert_eq!(process_id, 26);
+
+        for id in 1..=26 {
+            assert_eq!(
+                read_row(&conn, "main", id).unwrap(),
+                read_row(&mut process, "main", id).unwrap(),
+                "row {id} differs"
+            );
+        }
+    }
+
+    #[test]
+    fn test_helpers_accept_connection_wrappers() {
+        let dir = tempdir().unwrap();
+        let mut conn = Connection::open(dir.path().join("test.db")).unwrap();
+        init_test_db(&mut conn, "main", 42, 3, 5).unwrap();
+
+        let mut tx = conn.transaction().unwrap();
+        let id = insert_test_db(&tx, "main", 5).unwrap();
+        update_test_db(&mut tx, "main", id, 5).unwrap();
+        let text = read_row(&tx, "main", id).unwrap();
+        tx.commit().unwrap();
+
+        assert_eq!(read_row(&mut conn, "main", id).unwrap(), text);
+    }
+
+    #[test]
+    fn test_process_query_text_matches_connection() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+        let mut conn = Connection::open(&db_path).unwrap();
+        let mut process = Sqlite3Process::new(&db_path).unwrap();
+
+        for value in [
+            "'line one' || char(10) || 'line two, it''s'",
+            "'tab' || char(9) || 'cr' || char(13) || 'back\\slash' || char(1)",
+            "''",
+            "NULL",
+            "-42",
+            "0.1",
+            "X'68690a'",
+            "1e999",
+        ] {
+            let sql = format!("SELECT {value};");
+            assert_eq!(
+                conn.query_text(&sql, &[]).unwrap(),
+                process.query_text(&sql, &[]).unwrap(),
+                "{value}"
+            );
+        }
+        assert_eq!(process.query_text("SELECT 1 WHERE 0", &[]).unwrap(), None);
+    }
+
+    #[test]
+    fn test_process_read_row_missing_is_error() {
+        let dir = tempdir().unwrap();
+        let mut process = Sqlite3Process::new(&dir.path().join("test.db")).unwrap();
+        init_test_db(&mut process, "main", 42, 1, 5).unwrap();
+
+        assert!(read_row(&mut process, "main", 2).is_err());
+        assert!(read_row(&mut process, "missing_schema", 1).is_err());
+    }
+}
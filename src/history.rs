@@ -0,0 +1,504 @@
+//! Recording concurrent histories of `notes` operations and checking them.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+mod graph;
+
+/// One read or write of a `notes` row inside a [`HistoryTransaction`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HistoryOp {
+    /// Read the row's text, or found no row.
+    Read {
+        /// The row ID.
+        id: i64,
+        /// The text read, or `None` if the row did not exist.
+        value: Option<String>,
+    },
+    /// Inserted or updated the row, or deleted it with `None`.
+    Write {
+        /// The row ID.
+        id: i64,
+        /// The text written, or `None` for a delete.
+        value: Option<String>,
+    },
+}
+
+/// Operations that took effect together, with when they were invoked and
+/// when their response came back.
+///
+/// A single autocommit statement is a transaction of one operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryTransaction {
+    /// The process or thread that ran it.
+    pub actor: usize,
+    /// Its reads and writes, in order.
+    pub ops: Vec<HistoryOp>,
+    /// When it was invoked, from the start of the history.
+    pub invoked: Duration,
+    /// When it returned, from the start of the history.
+    pub responded: Duration,
+}
+
+impl fmt::Display for HistoryTransaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "actor {} [{:?}..{:?}]:",
+            self.actor, self.invoked, self.responded
+        )?;
+        for op in &self.ops {
+            let (kind, id, value) = match op {
+                HistoryOp::Read { id, value } => ('r', id, value),
+                HistoryOp::Write { id, value } => ('w', id, value),
+            };
+            match value {
+                Some(value) => write!(f, " {kind}({id})={value:?}")?,
+                None => write!(f, " {kind}({id})=none")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The consistency model [`History::check`] verifies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Consistency {
+    /// The transactions are equivalent to some serial order.
+    Serializable,
+    /// The serial order also respects real time: a transaction that returned
+    /// before another was invoked comes first. For transactions of one
+    /// operation this is linearizability.
+    StrictSerializable,
+}
+
+/// A cycle's anomaly class, after Adya's definitions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anomaly {
+    /// Write dependencies only: a dirty write.
+    G0,
+    /// Write and read dependencies only: circular information flow.
+    G1c,
+    /// Exactly one anti-dependency, such as read skew.
+    GSingle,
+    /// Several anti-dependencies, such as a lost update or write skew.
+    G2,
+    /// Needs real-time order, such as a stale read; serializable, but not
+    /// strictly.
+    RealTime,
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Anomaly::G0 => "G0 (dirty write)",
+            Anomaly::G1c => "G1c (circular information flow)",
+            Anomaly::GSingle => "G-single (read skew)",
+            Anomaly::G2 => "G2 (lost update or write skew)",
+            Anomaly::RealTime => "real-time violation (stale read)",
+        })
+    }
+}
+
+/// Why one transaction must come before another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DependencyKind {
+    /// The later transaction read what the earlier one wrote.
+    WriteRead,
+    /// The later transaction overwrote what the earlier one wrote.
+    WriteWrite,
+    /// The earlier transaction returned before the later one was invoked.
+    RealTime,
+    /// The later transaction overwrote what the earlier one read.
+    ReadWrite,
+}
+
+impl fmt::Display for DependencyKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DependencyKind::WriteRead => "wr",
+            DependencyKind::WriteWrite => "ww",
+            DependencyKind::RealTime => "rt",
+            DependencyKind::ReadWrite => "rw",
+        })
+    }
+}
+
+/// A history that is not consistent, as reported by [`History::check`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// A read returned a value that neither the initial rows nor any
+    /// transaction wrote, or not the transaction's own earlier write.
+    UnexplainedRead {
+        /// The transaction that read it.
+        transaction: HistoryTransaction,
+        /// The row ID.
+        id: i64,
+        /// The value read.
+        value: Option<String>,
+    },
+    /// The shortest cycle of dependencies: each transaction must come before
+    /// the next, and the last before the first.
+    Cycle {
+        /// The kind of anomaly the cycle shows.
+        anomaly: Anomaly,
+        /// Each transaction with the dependency to the next one, and the row
+        /// it is about if any.
+        steps: Vec<(HistoryTransaction, DependencyKind, Option<i64>)>,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::UnexplainedRead {
+                transaction,
+                id,
+                value,
+            } => write!(
+                f,
+                "read of row {id} returned {value:?}, which no transaction wrote: {transaction}"
+            ),
+            Violation::Cycle { anomaly, steps } => {
+                write!(f, "{anomaly}: cycle of {} transactions", steps.len())?;
+                for (transaction, kind, id) in steps {
+                    write!(f, "\n  {transaction}\n    -{kind}")?;
+                    if let Some(id) = id {
+                        write!(f, "({id})")?;
+                    }
+                    write!(f, "->")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A concurrent history of transactions on the `notes` table.
+///
+/// Each actor calls [`invoke`](Self::invoke) before sending a transaction,
+/// records its reads and writes on the returned [`Invocation`], and calls
+/// [`Invocation::respond`] once it committed. Transactions that failed or
+/// rolled back are dropped without responding. The history is shared by
+/// reference between threads.
+///
+/// [`check`](Self::check) then builds the dependency graph between the
+/// transactions and looks for a cycle, which would mean no serial order
+/// explains what they read. Reads are matched to writes by value, so every
+/// write of a row should write a value not written to that row before;
+/// reads of a value written more than once are ignored.
+///
+/// # Example
+///
+/// ```rust
+/// use std::collections::BTreeMap;
+/// use sqlite_test_utils::{Consistency, History};
+///
+/// let history = History::new(&BTreeMap::from([(1, "a".to_string())]));
+/// let mut first = history.invoke(0);
+/// let mut second = history.invoke(1);
+/// // Both read "a" and overwrite it: one update is lost.
+/// first.read(1, Some("a"));
+/// second.read(1, Some("a"));
+/// first.write(1, Some("b"));
+/// second.write(1, Some("c"));
+/// first.respond();
+/// second.respond();
+///
+/// let violation = history.check(Consistency::Serializable).unwrap_err();
+/// println!("{violation}");
+/// ```
+#[derive(Debug)]
+pub struct History {
+    start: Instant,
+    initial: BTreeMap<i64, String>,
+    transactions: Mutex<Vec<HistoryTransaction>>,
+}
+
+impl History {
+    /// Starts an empty history of a table holding `initial` rows.
+    pub fn new(initial: &BTreeMap<i64, String>) -> Self {
+        History {
+            start: Instant::now(),
+            initial: initial.clone(),
+            transactions: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Marks the invocation of a transaction by `actor`.
+    pub fn invoke(&self, actor: usize) -> Invocation<'_> {
+        Invocation {
+            history: self,
+            transaction: HistoryTransaction {
+                actor,
+                ops: Vec::new(),
+                invoked: self.start.elapsed(),
+                responded: Duration::ZERO,
+            },
+        }
+    }
+
+    /// Returns the committed transactions, in the order they responded.
+    pub fn transactions(&self) -> Vec<HistoryTransaction> {
+        self.transactions.lock().unwrap().clone()
+    }
+
+    /// Checks that the history satisfies `consistency`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first unexplained read, or else the shortest dependency
+    /// cycle.
+    ///
+    pub fn check(&self, consistency: Consistency) -> Result<(), Violation> {
+        graph::check(&self.initial, &self.transactions(), consistency)
+    }
+}
+
+/// A transaction in flight, returned by [`History::invoke`].
+#[derive(Debug)]
+pub struct Invocation<'a> {
+    history: &'a History,
+    transaction: HistoryTransaction,
+}
+
+impl Invocation<'_> {
+    /// Records that the transaction read `value` from row `id`.
+    pub fn read(&mut self, id: i64, value: Option<&str>) {
+        self.transaction.ops.push(HistoryOp::Read {
+            id,
+            value: value.map(str::to_string),
+        });
+    }
+
+    /// Records that the transaction wrote `value` to row `id`.
+    pub fn write(&mut self, id: i64, value: Option<&str>) {
+        self.transaction.ops.push(HistoryOp::Write {
+            id,
+            value: value.map(str::to_string),
+        });
+    }
+
+    /// Marks the response of the committed transaction and adds it to the history.
+    pub fn respond(mut self) {
+        self.transaction.responded = self.history.start.elapsed();
+        self.history
+            .transactions
+            .lock()
+            .unwrap()
+            .push(self.transaction);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Sqlite3Process;
+    use std::thread;
+    use tempfile::tempdir;
+
+    fn transaction(
+        actor: usize,
+        ops: Vec<HistoryOp>,
+        invoked: u64,
+        responded: u64,
+    ) -> HistoryTransaction {
+        HistoryTransaction {
+            actor,
+            ops,
+            invoked: Duration::from_millis(invoked),
+            responded: Duration::from_millis(responded),
+        }
+    }
+
+    fn read(id: i64, value: &str) -> HistoryOp {
+        HistoryOp::Read {
+            id,
+            value: Some(value.to_string()),
+        }
+    }
+
+    fn write(id: i64, value: &str) -> HistoryOp {
+        HistoryOp::Write {
+            id,
+            value: Some(value.to_string()),
+        }
+    }
+
+    fn check(
+        transactions: &[HistoryTransaction],
+        consistency: Consistency,
+    ) -> Result<(), Violation> {
+        let initial = BTreeMap::from([(1, "a".to_string()), (2, "x".to_string())]);
+        graph::check(&initial, transactions, consistency)
+    }
+
+    #[test]
+    fn test_anomalies() {
+        // Lost update: both read "a" and overwrite it.
+        let lost_update = [
+            transaction(0, vec![read(1, "a"), write(1, "b")], 0, 10),
+            transaction(1, vec![read(1, "a"), write(1, "c")], 5, 15),
+        ];
+        let Err(Violation::Cycle { anomaly, steps }) =
+            check(&lost_update, Consistency::Serializable)
+        else {
+            panic!("lost update not detected");
+        };
+        assert_eq!(anomaly, Anomaly::G2);
+        assert_eq!(steps.len(), 2);
+        assert!(steps
+            .iter()
+            .all(|(_, kind, id)| (*kind, *id) == (DependencyKind::ReadWrite, Some(1))));
+
+        // Checked strictly, a lost update is reported as such even when the
+        // history also has a stale read.
+        let stale_read_and_lost_update = [
+            transaction(0, vec![write(2, "y")], 0, 10),
+            transaction(1, vec![read(2, "x")], 20, 30),
+            transaction(2, vec![read(1, "a"), write(1, "b")], 40, 50),
+            transaction(3, vec![read(1, "a"), write(1, "c")], 45, 55),
+        ];
+        let violation =
+            check(&stale_read_and_lost_update, Consistency::StrictSerializable).unwrap_err();
+        assert!(
+            matches!(
+                violation,
+                Violation::Cycle {
+                    anomaly: Anomaly::G2,
+                    ..
+                }
+            ),
+            "{violation}"
+        );
+
+        let serial = [
+            transaction(0, vec![read(1, "a"), write(1, "b")], 0, 10),
+            transaction(1, vec![read(1, "b"), write(1, "c")], 5, 15),
+        ];
+        assert_eq!(check(&serial, Consistency::StrictSerializable), Ok(()));
+
+        // Read skew: the reader sees row 1 before and row 2 after a writer
+        // that changed both.
+        let read_skew = [
+            transaction(0, vec![read(1, "a"), write(1, "b"), write(2, "y")], 0, 10),
+            transaction(1, vec![read(1, "a"), read(2, "y")], 0, 10),
+        ];
+        let violation = check(&read_skew, Consistency::Serializable).unwrap_err();
+        assert!(
+            matches!(violation, Violation::Cycle { anomaly: Anomaly::GSingle, ref steps } if steps.len() == 2),
+            "{violation}"
+        );
+
+        // Dirty write: each overwrote the other's write of a different row.
+        let dirty_write = [
+            transaction(0, vec![read(1, "c"), write(1, "b"), write(2, "y")], 0, 10),
+            transaction(1, vec![read(2, "y"), write(2, "z"), write(1, "c")], 0, 10),
+        ];
+        let violation = check(&dirty_write, Consistency::Serializable).unwrap_err();
+        assert!(
+            matches!(
+                violation,
+                Violation::Cycle {
+                    anomaly: Anomaly::G0,
+                    ..
+                }
+            ),
+            "{violation}"
+        );
+
+        let violation = check(
+            &[transaction(0, vec![read(1, "never written")], 0, 1)],
+            Consistency::Serializable,
+        )
+        .unwrap_err();
+        assert!(violation.to_string().starts_with("read of row 1 returned"));
+    }
+
+    #[test]
+    fn test_stale_read_is_only_strictly_wrong() {
+        // The read starts after the write returned, yet sees the old value.
+        let stale = [
+            transaction(0, vec![read(1, "a"), write(1, "b")], 0, 10),
+            transaction(1, vec![read(1, "a")], 20, 30),
+        ];
+        assert_eq!(check(&stale, Consistency::Serializable), Ok(()));
+        let violation = check(&stale, Consistency::StrictSerializable).unwrap_err();
+        let Violation::Cycle { anomaly, ref steps } = violation else {
+            panic!("{violation}");
+        };
+        assert_eq!(anomaly, Anomaly::RealTime);
+        let kinds: Vec<_> = steps.iter().map(|(_, kind, _)| *kind).collect();
+        assert_eq!(kinds, [DependencyKind::RealTime, DependencyKind::ReadWrite]);
+        let rendered = violation.to_string();
+        assert!(
+            rendered.starts_with("real-time violation (stale read): cycle of 2 transactions"),
+            "{rendered}"
+        );
+        assert!(rendered.contains("-rw(1)->"), "{rendered}");
+    }
+
+    #[test]
+    fn test_wal_processes_are_strictly_serializable() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+        let mut setup = Sqlite3Process::new(&db_path).unwrap();
+        setup
+            .execute_checked(
+                "PRAGMA journal_mode = WAL; \
+                 CREATE TABLE notes (id INTEGER PRIMARY KEY, text TEXT NOT NULL); \
+                 INSERT INTO notes VALUES (1, 'v0'), (2, 'w0');",
+            )
+            .unwrap();
+        setup.close().unwrap();
+        let history = History::new(&BTreeMap::from([
+            (1, "v0".to_string()),
+            (2, "w0".to_string()),
+        ]));
+
+        thread::scope(|scope| {
+            for actor in 0..3 {
+                let (history, db_path) = (&history, &db_path);
+                scope.spawn(move || {
+                    let mut process = Sqlite3Process::new(db_path).unwrap();
+                    process.execute_checked(".timeout 10000").unwrap();
+                    for step in 0..15 {
+                        let id = (step % 2) + 1;
+                        let mut invocation = history.invoke(actor);
+                        if actor == 0 {
+                            // A WAL reader, never blocked by the writers.
+                            let output = process
+                                .execute_checked(&format!(
+                                    "SELECT text FROM notes WHERE id = {id};"
+                                ))
+                                .unwrap();
+                            invocation.read(id, Some(output.trim_end()));
+                        } else {
+                            // Read-modify-write under the write lock.
+                            let output = process
+                                .execute_checked(&format!(
+                                    "BEGIN IMMEDIATE; SELECT text FROM notes WHERE id = {id};"
+                                ))
+                                .unwrap();
+                            let value = format!("{actor}-{step}");
+                            process
+                                .execute_checked(&format!(
+                                    "UPDATE notes SET text = '{value}' WHERE id = {id}; COMMIT;"
+                                ))
+                                .unwrap();
+                            invocation.read(id, Some(output.trim_end()));
+                            invocation.write(id, Some(&value));
+                        }
+                        invocation.respond();
+                    }
+                    process.close().unwrap();
+                });
+            }
+        });
+
+        assert_eq!(history.transactions().len(), 45);
+        assert_eq!(history.check(Consistency::StrictSerializable), Ok(()));
+    }
+}
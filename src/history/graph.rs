@@ -0,0 +1,359 @@
+//! The dependency graph of a history and its shortest cycle.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use super::{Anomaly, Consistency, DependencyKind, HistoryOp, HistoryTransaction, Violation};
+
+/// Node of the initial rows; transaction `i` is node `i + 1`.
+const INITIAL: usize = 0;
+
+/// A dependency edge: its kind and the row it is about.
+type Edge = (DependencyKind, Option<i64>);
+
+/// Checks `transactions`, run on a table holding `initial`, against `consistency`.
+pub(super) fn check(
+    initial: &BTreeMap<i64, String>,
+    transactions: &[HistoryTransaction],
+    consistency: Consistency,
+) -> Result<(), Violation> {
+    let strict = consistency == Consistency::StrictSerializable;
+    if strict {
+        // Report a serializability anomaly as such, not as a real-time one.
+        check(initial, transactions, Consistency::Serializable)?;
+    }
+    let nodes = transactions.len() + 1;
+    let writes = last_writes(initial, transactions);
+    // Every kind of dependency between each pair, with the row of the first
+    // edge of that kind.
+    let mut edges: BTreeMap<(usize, usize), BTreeMap<DependencyKind, Option<i64>>> =
+        BTreeMap::new();
+    let mut add = |from: usize, to: usize, (kind, id): Edge| {
+        if from != to {
+            edges
+                .entry((from, to))
+                .or_default()
+                .entry(kind)
+                .or_insert(id);
+        }
+    };
+
+    // Which write each read saw, as (reader, row, writer).
+    let mut reads_from = Vec::new();
+    for (index, transaction) in transactions.iter().enumerate() {
+        let node = index + 1;
+        let mut own: BTreeMap<i64, &Option<String>> = BTreeMap::new();
+        for op in &transaction.ops {
+            match op {
+                HistoryOp::Write { id, value } => {
+                    own.insert(*id, value);
+                }
+                HistoryOp::Read { id, value } => {
+                    let unexplained = || Violation::UnexplainedRead {
+                        transaction: transaction.clone(),
+                        id: *id,
+                        value: value.clone(),
+                    };
+                    if let Some(written) = own.get(id) {
+                        if *written != value {
+                            return Err(unexplained());
+                        }
+                        continue;
+                    }
+                    let writers: Vec<usize> = writes[id]
+                        .iter()
+                        .filter(|(writer, written)| *writer != node && written == value)
+                        .map(|(writer, _)| *writer)
+                        .collect();
+                    match writers[..] {
+                        [] => return Err(unexplained()),
+                        [writer] => {
+                            add(writer, node, (DependencyKind::WriteRead, Some(*id)));
+                            reads_from.push((node, *id, writer));
+                        }
+                        // Written more than once, so it cannot be placed.
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    // The known order of each row's versions, transitively closed.
+    let mut version_orders = BTreeMap::new();
+    for (id, writers) in &writes {
+        let mut before: BTreeSet<(usize, usize)> = BTreeSet::new();
+        for (writer, _) in writers.iter().filter(|(writer, _)| *writer != INITIAL) {
+            before.insert((INITIAL, *writer));
+        }
+        for (reader, read_id, writer) in &reads_from {
+            if read_id == id && writers.iter().any(|(other, _)| other == reader) {
+                before.insert((*writer, *reader));
+            }
+        }
+        if strict {
+            for (first, _) in writers {
+                for (second, _) in writers {
+                    if *first != INITIAL
+                        && *second != INITIAL
+                        && transactions[first - 1].responded < transactions[second - 1].invoked
+                    {
+                        before.insert((*first, *second));
+                    }
+                }
+            }
+        }
+        let closed = transitive_closure(&before);
+        for (first, second) in &closed {
+            add(*first, *second, (DependencyKind::WriteWrite, Some(*id)));
+        }
+        version_orders.insert(*id, closed);
+    }
+
+    // A reader comes before everyone overwriting the version it read.
+    for (reader, id, writer) in &reads_from {
+        for (first, later) in &version_orders[id] {
+            if first == writer && later != reader {
+                add(*reader, *later, (DependencyKind::ReadWrite, Some(*id)));
+            }
+        }
+    }
+
+    if strict {
+        for (from, to) in real_time_order(transactions) {
+            add(from + 1, to + 1, (DependencyKind::RealTime, None));
+        }
+    }
+
+    let mut adjacency: Vec<Vec<(usize, Edge)>> = vec![Vec::new(); nodes];
+    for ((from, to), kinds) in &edges {
+        // Report the most telling kind when several apply.
+        let (kind, id) = kinds.first_key_value().expect("added with a kind");
+        adjacency[*from].push((*to, (*kind, *id)));
+    }
+    let Some(cycle) = shortest_cycle(&adjacency) else {
+        return Ok(());
+    };
+
+    // Classify by every kind of each step, not only the reported one.
+    let step_kinds: Vec<_> = cycle
+        .iter()
+        .zip(cycle.iter().cycle().skip(1))
+        .map(|((from, _), (to, _))| &edges[&(*from, *to)])
+        .collect();
+    let has = |kinds: &BTreeMap<DependencyKind, Option<i64>>, kind| kinds.contains_key(&kind);
+    let anti_dependencies = step_kinds
+        .iter()
+        .filter(|kinds| has(kinds, DependencyKind::ReadWrite))
+        .count();
+    let anomaly = if strict {
+        // The history is serializable, so only real time closes the cycle.
+        Anomaly::RealTime
+    } else {
+        match anti_dependencies {
+            0 if step_kinds
+                .iter()
+                .all(|kinds| has(kinds, DependencyKind::WriteWrite)) =>
+            {
+                Anomaly::G0
+            }
+            0 => Anomaly::G1c,
+            1 => Anomaly::GSingle,
+            _ => Anomaly::G2,
+        }
+    };
+    Err(Violation::Cycle {
+        anomaly,
+        steps: cycle
+            .into_iter()
+            .map(|(node, (kind, id))| (transactions[node - 1].clone(), kind, id))
+            .collect(),
+    })
+}
+
+/// Returns, for every row, each writer's last value, starting with the initial one.
+fn last_writes(
+    initial: &BTreeMap<i64, String>,
+    transactions: &[HistoryTransaction],
+) -> BTreeMap<i64, Vec<(usize, Option<String>)>> {
+    let mut writes: BTreeMap<i64, Vec<(usize, Option<String>)>> = BTreeMap::new();
+    for (id, text) in initial {
+        writes.insert(*id, vec![(INITIAL, Some(text.clone()))]);
+    }
+    for (index, transaction) in transactions.iter().enumerate() {
+        let mut last = BTreeMap::new();
+        for op in &transaction.ops {
+            let (HistoryOp::Read { id, .. } | HistoryOp::Write { id, .. }) = op;
+            // Rows missing initially start out absent.
+            writes.entry(*id).or_insert_with(|| vec![(INITIAL, None)]);
+            if let HistoryOp::Write { id, value } = op {
+                last.insert(*id, value.clone());
+            }
+        }
+        for (id, value) in last {
+            writes.entry(id).or_default().push((index + 1, value));
+        }
+    }
+    writes
+}
+
+/// Returns the pairs of `relation` plus every pair they imply.
+fn transitive_closure(relation: &BTreeSet<(usize, usize)>) -> BTreeSet<(usize, usize)> {
+    let mut successors: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (first, second) in relation {
+        successors.entry(*first).or_default().push(*second);
+    }
+    let mut closed = BTreeSet::new();
+    for start in successors.keys() {
+        let mut stack = successors[start].clone();
+        let mut seen = BTreeSet::new();
+        while let Some(node) = stack.pop() {
+            if seen.insert(node) {
+                closed.insert((*start, node));
+                stack.extend(successors.get(&node).into_iter().flatten());
+            }
+        }
+    }
+    closed
+}
+
+/// Returns pairs of transaction indexes whose transitive closure is the
+/// real-time order, without the pairs it implies anyway.
+///
+/// `first` precedes `second` if it returned before `second` was invoked. It
+/// is left out when some third transaction fits entirely in between.
+fn real_time_order(transactions: &[HistoryTransaction]) -> Vec<(usize, usize)> {
+    let mut by_response: Vec<usize> = (0..transactions.len()).collect();
+    by_response.sort_by_key(|&index| transactions[index].responded);
+    // Latest invocation among the first n transactions to respond.
+    let latest_invocation: Vec<_> = by_response
+        .iter()
+        .scan(None, |latest, &index| {
+            *latest = (*latest).max(Some(transactions[index].invoked));
+            Some(*latest)
+        })
+        .collect();
+
+    let mut pairs = Vec::new();
+    for (second, transaction) in transactions.iter().enumerate() {
+        let before = by_response
+            .partition_point(|&index| transactions[index].responded < transaction.invoked);
+        let Some(Some(latest)) = before.checked_sub(1).map(|last| latest_invocation[last]) else {
+            continue;
+        };
+        for &first in by_response[..before].iter().rev() {
+            if transactions[first].responded < latest {
+                break;
+            }
+            pairs.push((first, second));
+        }
+    }
+    pairs
+}
+
+/// Finds a shortest cycle, as each node with the edge to the next, starting
+/// at its lowest node.
+fn shortest_cycle(adjacency: &[Vec<(usize, Edge)>]) -> Option<Vec<(usize, Edge)>> {
+    let components = strongly_connected_components(adjacency);
+    let mut sizes = vec![0; adjacency.len()];
+    for component in &components {
+        sizes[*component] += 1;
+    }
+    let mut shortest: Option<Vec<(usize, Edge)>> = None;
+    for start in 0..adjacency.len() {
+        let component = components[start];
+        if sizes[component] < 2 {
+            continue;
+        }
+        // Breadth-first from `start` back to itself, inside its component.
+        let mut previous: Vec<Option<(usize, Edge)>> = vec![None; adjacency.len()];
+        let mut queue = VecDeque::from([(start, 0)]);
+        let mut length = None;
+        'search: while let Some((node, depth)) = queue.pop_front() {
+            if shortest
+                .as_ref()
+                .is_some_and(|cycle| depth + 1 >= cycle.len())
+            {
+                break;
+            }
+            for &(next, edge) in &adjacency[node] {
+                if components[next] != component {
+                    continue;
+                }
+                if next == start {
+                    previous[start] = Some((node, edge));
+                    length = Some(depth + 1);
+                    break 'search;
+                }
+                if previous[next].is_none() {
+                    previous[next] = Some((node, edge));
+                    queue.push_back((next, depth + 1));
+                }
+            }
+        }
+        if length.is_some() {
+            let mut cycle = Vec::new();
+            let mut node = start;
+            loop {
+                let (before, edge) = previous[node].expect("on the path");
+                cycle.push((before, edge));
+                node = before;
+                if node == start {
+                    break;
+                }
+            }
+            cycle.reverse();
+            shortest = Some(cycle);
+        }
+    }
+    shortest
+}
+
+/// Labels each node with its strongly connected component (Kosaraju).
+fn strongly_connected_components(adjacency: &[Vec<(usize, Edge)>]) -> Vec<usize> {
+    let nodes = adjacency.len();
+    // Order nodes by when their depth-first search finishes.
+    let mut finished = Vec::with_capacity(nodes);
+    let mut visited = vec![false; nodes];
+    for root in 0..nodes {
+        if visited[root] {
+            continue;
+        }
+        visited[root] = true;
+        let mut stack = vec![(root, 0)];
+        while let Some((node, next_edge)) = stack.pop() {
+            if let Some(&(next, _)) = adjacency[node].get(next_edge) {
+                stack.push((node, next_edge + 1));
+                if !visited[next] {
+                    visited[next] = true;
+                    stack.push((next, 0));
+                }
+            } else {
+                finished.push(node);
+            }
+        }
+    }
+
+    let mut reverse = vec![Vec::new(); nodes];
+    for (node, edges) in adjacency.iter().enumerate() {
+        for &(next, _) in edges {
+            reverse[next].push(node);
+        }
+    }
+    let mut components = vec![usize::MAX; nodes];
+    for (component, &root) in finished.iter().rev().enumerate() {
+        if components[root] != usize::MAX {
+            continue;
+        }
+        components[root] = component;
+        let mut stack = vec![root];
+        while let Some(node) = stack.pop() {
+            for &next in &reverse[node] {
+                if components[next] == usize::MAX {
+                    components[next] = component;
+                    stack.push(next);
+                }
+            }
+        }
+    }
+    components
+}
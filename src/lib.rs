@@ -36,10 +36,48 @@
 
 use std::error::Error as StdError;
 
-use rusqlite::{params, Connection};
-
+use rusqlite::types::Value;
+use rusqlite::Connection;
+
+mod barrier;
+pub use barrier::{Barrier, BarrierEvent, BarrierEventKind, DEFAULT_BARRIER_TIMEOUT};
+mod executor;
+pub use executor::Executor;
+mod history;
+pub use history::{
+    Anomaly, Consistency, DependencyKind, History, HistoryOp, HistoryTransaction, Invocation,
+    Violation,
+};
+mod model;
+pub use model::{assert_matches_model, NotesModel};
+
+mod scenario;
+pub use scenario::{
+    dirty_read_scenario, immediate_writer_scenario, isolation_scenarios, snapshot_read_scenario,
+    write_upgrade_scenario, ActorKind, Expect, Scenario, ScenarioReport, Step, StepRecord,
+    DEFAULT_SETTLE_TIME, DEFAULT_STEP_TIMEOUT, JOURNAL_MODES,
+};
 mod sqlite3process;
-pub use sqlite3process::Sqlite3Process;
+mod worker;
+mod workload;
+#[cfg(target_os = "linux")]
+pub use sqlite3process::ResourceUsage;
+#[cfg(unix)]
+pub use sqlite3process::SuspendGuard;
+pub use sqlite3process::{
+    sql_literal, DbInfo, DummyData, DummyValues, ExitReport, HangBehavior, LeftoverFiles,
+    PendingResult, ProcessPool, ProcessPoolBuilder, Sqlite3Info, Sqlite3Process,
+    Sqlite3ProcessBuilder, Transcript, TranscriptEntry, DEFAULT_GRACE_PERIOD, SQLITE3_BIN_ENV,
+};
+#[cfg(feature = "tokio")]
+pub use sqlite3process::{AsyncLockGuard, AsyncSqlite3Process, LockMode};
+#[doc(hidden)]
+pub use worker::run_worker_if_requested;
+pub use worker::WORKER_DB_ENV;
+pub use workload::{
+    BusyStrategy, CommittedOp, LatencyHistogram, OpKind, OpStats, RecordedOp, ReplayMode, Workload,
+    WorkloadRecording, WorkloadReport,
+};
 
 /// Latin words used for generating random test data.
 const WORDS: [&str; 58] = [
@@ -106,10 +144,12 @@ const WORDS: [&str; 58] = [
 /// Initializes an existing database connection with test data.
 ///
 /// Creates a `notes` table in the specified schema and populates it with random data.
+/// Works with a `&rusqlite::Connection` or a `&mut Sqlite3Process`; the same seed
+/// produces the same rows either way.
 ///
 /// # Arguments
 ///
-/// * `sqlite_connection` - An open database connection
+/// * `sqlite_connection` - An open database connection or sqlite3 process
 /// * `schema` - The schema name (e.g., "main" for the default schema)
 /// * `seed` - Random seed for reproducible data generation
 /// * `row_count` - Number of rows to insert into the `notes` table
@@ -118,40 +158,36 @@ const WORDS: [&str; 58] = [
 /// # Errors
 ///
 /// Returns an error if table creation or data insertion fails.
-pub fn init_test_db<S: AsRef<str>>(
-    sqlite_connection: &Connection,
+pub fn init_test_db<E: Executor, S: AsRef<str>>(
+    mut sqlite_connection: E,
     schema: S,
     seed: u64,
     row_count: usize,
     note_word_count: usize,
-) -> Result<(), rusqlite::Error> {
+) -> Result<(), E::Error> {
     fastrand::seed(seed);
     let schema = schema.as_ref();
 
     // Create the table
-    sqlite_connection.execute(
-        &format!("CREATE TABLE {schema}.notes (id INTEGER PRIMARY KEY, text TEXT NOT NULL)"),
-        [],
-    )?;
-
-    // Use a prepared statement for inserts
-    let mut stmt =
-        sqlite_connection.prepare(&format!("INSERT INTO {schema}.notes (text) VALUES (?)"))?;
+    sqlite_connection.exec_batch(&format!(
+        "CREATE TABLE {schema}.notes (id INTEGER PRIMARY KEY, text TEXT NOT NULL)"
+    ))?;
 
     // Insert all rows in a transaction
-    sqlite_connection.execute("BEGIN", [])?;
-    for _ in 0..row_count {
-        let note = create_note(note_word_count);
-        stmt.execute(params![note])?;
-    }
-    sqlite_connection.execute("COMMIT", [])?;
+    let notes: Vec<Vec<Value>> = (0..row_count)
+        .map(|_| vec![Value::Text(create_note(note_word_count))])
+        .collect();
+    sqlite_connection.exec_batch("BEGIN")?;
+    sqlite_connection.exec_many(
+        &format!("INSERT INTO {schema}.notes (text) VALUES (?1)"),
+        &notes,
+    )?;
+    sqlite_connection.exec_batch("COMMIT")?;
 
     // Verify rows were inserted
-    let count: i64 = sqlite_connection.query_row(
-        &format!("SELECT COUNT(*) FROM {schema}.notes"),
-        [],
-        |row| row.get(0),
-    )?;
+    let count = sqlite_connection
+        .query_text(&format!("SELECT COUNT(*) FROM {schema}.notes"), &[])?
+        .unwrap_or_default();
     eprintln!("Row count after init_test_db: {count}");
 
     Ok(())
@@ -195,7 +231,7 @@ pub fn set_journal_mode<S: AsRef<str>>(
 ///
 /// # Arguments
 ///
-/// * `sqlite_connection` - An open database connection
+/// * `sqlite_connection` - An open database connection or sqlite3 process
 /// * `schema` - The schema name (e.g., "main" for the default schema)
 /// * `row_id` - The ID of the row to update
 /// * `word_count` - Maximum number of words for the new note content
@@ -203,26 +239,56 @@ pub fn set_journal_mode<S: AsRef<str>>(
 /// # Errors
 ///
 /// Returns an error if the update fails.
-pub fn update_test_db<S: AsRef<str>>(
-    sqlite_connection: &Connection,
+pub fn update_test_db<E, S>(
+    mut sqlite_connection: E,
     schema: S,
     row_id: i64,
     word_count: usize,
-) -> Result<(), Box<dyn StdError>> {
+) -> Result<(), Box<dyn StdError>>
+where
+    E: Executor,
+    E::Error: Into<Box<dyn StdError>>,
+    S: AsRef<str>,
+{
     let note = create_note(word_count);
-    let schema = schema.as_ref();
-    let sql = format!("UPDATE {schema}.notes SET text = ? WHERE id = ?");
-
-    sqlite_connection.execute(&sql, params![note, row_id])?;
+    update_note(&mut sqlite_connection, schema.as_ref(), row_id, &note).map_err(Into::into)
+}
 
-    Ok(())
+/// Deletes a row from a test database using an existing connection.
+///
+/// Deleting a row that does not exist is not an error.
+///
+/// # Arguments
+///
+/// * `sqlite_connection` - An open database connection or sqlite3 process
+/// * `schema` - The schema name (e.g., "main" for the default schema)
+/// * `row_id` - The ID of the row to delete
+///
+/// # Errors
+///
+/// Returns an error if the delete fails.
+pub fn delete_test_db<E, S>(
+    mut sqlite_connection: E,
+    schema: S,
+    row_id: i64,
+) -> Result<(), Box<dyn StdError>>
+where
+    E: Executor,
+    E::Error: Into<Box<dyn StdError>>,
+    S: AsRef<str>,
+{
+    let schema = schema.as_ref();
+    let sql = format!("DELETE FROM {schema}.notes WHERE id = ?1");
+    sqlite_connection
+        .exec(&sql, &[Value::Integer(row_id)])
+        .map_err(Into::into)
 }
 
 /// Inserts a new row with random data into a test database.
 ///
 /// # Arguments
 ///
-/// * `sqlite_connection` - An open database connection
+/// * `sqlite_connection` - An open database connection or sqlite3 process
 /// * `schema` - The schema name (e.g., "main" for the default schema)
 /// * `word_count` - Maximum number of words for the note content
 ///
@@ -233,22 +299,47 @@ pub fn update_test_db<S: AsRef<str>>(
 /// # Errors
 ///
 /// Returns an error if the insert fails.
-pub fn insert_test_db<S: AsRef<str>>(
-    sqlite_connection: &Connection,
+pub fn insert_test_db<E, S>(
+    mut sqlite_connection: E,
     schema: S,
     word_count: usize,
-) -> Result<i64, Box<dyn StdError>> {
-    let schema = schema.as_ref();
+) -> Result<i64, Box<dyn StdError>>
+where
+    E: Executor,
+    E::Error: Into<Box<dyn StdError>>,
+    S: AsRef<str>,
+{
     let note = create_note(word_count);
-    let sql = format!("INSERT INTO {schema}.notes (text) values (?)");
-    sqlite_connection.execute(&sql, params![note])?;
-    let row_id = sqlite_connection.last_insert_rowid();
+    insert_note(&mut sqlite_connection, schema.as_ref(), &note).map_err(Into::into)
+}
 
-    Ok(row_id)
+/// Inserts `note` into `schema.notes`, returning its row ID.
+pub(crate) fn insert_note<E: Executor>(
+    sqlite_connection: &mut E,
+    schema: &str,
+    note: &str,
+) -> Result<i64, E::Error> {
+    let sql = format!("INSERT INTO {schema}.notes (text) values (?1)");
+    sqlite_connection.exec(&sql, &[Value::Text(note.to_string())])?;
+    sqlite_connection.last_insert_rowid()
+}
+
+/// Replaces the text of row `row_id` of `schema.notes` with `note`.
+pub(crate) fn update_note<E: Executor>(
+    sqlite_connection: &mut E,
+    schema: &str,
+    row_id: i64,
+    note: &str,
+) -> Result<(), E::Error> {
+    let sql = format!("UPDATE {schema}.notes SET text = ?1 WHERE id = ?2");
+    sqlite_connection.exec(
+        &sql,
+        &[Value::Text(note.to_string()), Value::Integer(row_id)],
+    )
 }
 
 /// Creates a random note string with up to the specified number of words.
-fn create_note(word_count: usize) -> String {
+pub(crate) fn create_note(word_count: usize) -> String {
     let mut note = String::new();
     let words_len = WORDS.len();
     let words_for_note = fastrand::usize(..word_count);
@@ -263,7 +354,7 @@ fn create_note(word_count: usize) -> String {
 ///
 /// # Arguments
 ///
-/// * `conn` - An open database connection
+/// * `conn` - An open database connection or sqlite3 process
 /// * `schema` - The schema name (e.g., "main" for the default schema)
 /// * `row_id` - The ID of the row to read
 ///
@@ -274,17 +365,20 @@ fn create_note(word_count: usize) -> String {
 /// # Errors
 ///
 /// Returns an error if the row is not found or the query fails.
-pub fn read_row<S: AsRef<str>>(
-    conn: &Connection,
-    schema: S,
-    row_id: i64,
-) -> Result<String, Box<dyn std::error::Error>> {
+pub fn read_row<E, S>(mut conn: E, schema: S, row_id: i64) -> Result<String, Box<dyn StdError>>
+where
+    E: Executor,
+    E::Error: Into<Box<dyn StdError>>,
+    S: AsRef<str>,
+{
     let schema = schema.as_ref();
-    let data = conn.query_row_and_then(
-        &format!("SELECT text FROM {schema}.notes WHERE id = ?1"),
-        params![row_id],
-        |row| row.get(0),
-    )?;
+    let data = conn
+        .query_text(
+            &format!("SELECT text FROM {schema}.notes WHERE id = ?1"),
+            &[Value::Integer(row_id)],
+        )
+        .map_err(Into::into)?
+        .ok_or_else(|| format!("No row with id {row_id} in {schema}.notes"))?;
     Ok(data)
 }
 
@@ -293,6 +387,8 @@ mod tests {
     use super::*;
     use tempfile::tempdir;
 
+    sqlite_test_worker!();
+
     fn new_test_conn() -> (Connection, tempfile::TempDir) {
         let dir = tempdir().unwrap();
         let db_path = dir.path().join("test.db");
@@ -0,0 +1,437 @@
+//! An in-memory oracle of what the `notes` table should contain.
+
+use std::collections::BTreeMap;
+use std::error::Error as StdError;
+
+use crate::{create_note, delete_test_db, init_test_db, insert_note, update_note, Executor};
+
+/// Stops listing differences after this many.
+const MAX_DIFFERENCES: usize = 20;
+
+/// What the `notes` table should contain, kept in step with the CRUD helpers.
+///
+/// Each method runs its operation through the given connection, like the
+/// matching helper ([`insert_test_db`](crate::insert_test_db),
+/// [`update_test_db`](crate::update_test_db),
+/// [`delete_test_db`](crate::delete_test_db)), and applies it to the model once
+/// it succeeded. Inside [`begin`](Self::begin), changes are pending until
+/// [`commit`](Self::commit); [`rollback`](Self::rollback) and
+/// [`crashed`](Self::crashed) discard them, since a crash loses an open
+/// transaction. [`assert_matches_model`] compares a table with the committed
+/// rows.
+///
+/// # Example
+///
+/// ```rust
+/// use rusqlite::Connection;
+/// use sqlite_test_utils::{assert_matches_model, NotesModel};
+///
+/// let dir = tempfile::tempdir().unwrap();
+/// let conn = Connection::open(dir.path().join("test.db")).unwrap();
+/// let mut model = NotesModel::init(&conn, "main", 42, 10, 5).unwrap();
+///
+/// let id = model.insert(&conn, 5).unwrap();
+/// model.update(&conn, 3, 5).unwrap();
+/// model.delete(&conn, id).unwrap();
+/// assert_matches_model(&conn, &model);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotesModel {
+    schema: String,
+    committed: BTreeMap<i64, String>,
+    pending: Option<BTreeMap<i64, String>>,
+}
+
+impl NotesModel {
+    /// Creates a model of an empty `notes` table in `schema`.
+    pub fn new(schema: &str) -> Self {
+        NotesModel {
+            schema: schema.to_string(),
+            committed: BTreeMap::new(),
+            pending: None,
+        }
+    }
+
+    /// Runs [`init_test_db`] and models the rows it creates.
+    ///
+    /// The rows are derived from `seed` independently of the database, so a
+    /// wrong initialization shows up in the first check.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if [`init_test_db`] fails.
+    ///
+    pub fn init<E: Executor>(
+        sqlite_connection: E,
+        schema: &str,
+        seed: u64,
+        row_count: usize,
+        note_word_count: usize,
+    ) -> Result<Self, E::Error> {
+        init_test_db(sqlite_connection, schema, seed, row_count, note_word_count)?;
+        // `init_test_db` draws its notes in this order from the same seed.
+        fastrand::seed(seed);
+        let mut model = Self::new(schema);
+        model.committed = (1..=row_count as i64)
+            .map(|id| (id, create_note(note_word_count)))
+            .collect();
+        Ok(model)
+    }
+
+    /// Creates a model of the rows the table holds now.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the table cannot be read.
+    ///
+    pub fn load<E>(mut sqlite_connection: E, schema: &str) -> Result<Self, Box<dyn StdError>>
+    where
+        E: Executor,
+        E::Error: Into<Box<dyn StdError>>,
+    {
+        let mut model = Self::new(schema);
+        model.committed = read_notes(&mut sqlite_connection, schema)?;
+        Ok(model)
+    }
+
+    /// Returns the committed rows by ID.
+    pub fn committed(&self) -> &BTreeMap<i64, String> {
+        &self.committed
+    }
+
+    /// Returns the rows as the connection running the model's operations
+    /// sees them, including pending changes.
+    pub fn rows(&self) -> &BTreeMap<i64, String> {
+        self.pending.as_ref().unwrap_or(&self.committed)
+    }
+
+    /// Returns `true` between [`begin`](Self::begin) and its end.
+    pub fn in_transaction(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Inserts a random note, like [`insert_test_db`](crate::insert_test_db).
+    ///
+    /// # Returns
+    ///
+    /// Returns the row ID of the new row.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the insert fails; the model is left unchanged.
+    ///
+    pub fn insert<E>(
+        &mut self,
+        mut sqlite_connection: E,
+        word_count: usize,
+    ) -> Result<i64, Box<dyn StdError>>
+    where
+        E: Executor,
+        E::Error: Into<Box<dyn StdError>>,
+    {
+        let note = create_note(word_count);
+        let row_id =
+            insert_note(&mut sqlite_connection, &self.schema, &note).map_err(Into::into)?;
+        self.rows_mut().insert(row_id, note);
+        Ok(row_id)
+    }
+
+    /// Replaces a row's text with a random note, like
+    /// [`update_test_db`](crate::update_test_db).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the update fails; the model is left unchanged.
+    ///
+    pub fn update<E>(
+        &mut self,
+        mut sqlite_connection: E,
+        row_id: i64,
+        word_count: usize,
+    ) -> Result<(), Box<dyn StdError>>
+    where
+        E: Executor,
+        E::Error: Into<Box<dyn StdError>>,
+    {
+        let note = create_note(word_count);
+        update_note(&mut sqlite_connection, &self.schema, row_id, &note).map_err(Into::into)?;
+        if let Some(text) = self.rows_mut().get_mut(&row_id) {
+            *text = note;
+        }
+        Ok(())
+    }
+
+    /// Deletes a row, like [`delete_test_db`](crate::delete_test_db).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the delete fails; the model is left unchanged.
+    ///
+    pub fn delete<E>(&mut self, sqlite_connection: E, row_id: i64) -> Result<(), Box<dyn StdError>>
+    where
+        E: Executor,
+        E::Error: Into<Box<dyn StdError>>,
+    {
+        delete_test_db(sqlite_connection, &self.schema, row_id)?;
+        self.rows_mut().remove(&row_id);
+        Ok(())
+    }
+
+    /// Starts a transaction; later changes are pending until it ends.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `BEGIN` fails or a transaction is already open.
+    ///
+    pub fn begin<E>(&mut self, mut sqlite_connection: E) -> Result<(), Box<dyn StdError>>
+    where
+        E: Executor,
+        E::Error: Into<Box<dyn StdError>>,
+    {
+        if self.in_transaction() {
+            return Err("The model already has an open transaction".into());
+        }
+        sqlite_connection.exec_batch("BEGIN").map_err(Into::into)?;
+        self.pending = Some(self.committed.clone());
+        Ok(())
+    }
+
+    /// Commits the open transaction, making its changes committed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `COMMIT` fails or no transaction is open. The
+    /// transaction stays open if `COMMIT` fails.
+    ///
+    pub fn commit<E>(&mut self, mut sqlite_connection: E) -> Result<(), Box<dyn StdError>>
+    where
+        E: Executor,
+        E::Error: Into<Box<dyn StdError>>,
+    {
+        if !self.in_transaction() {
+            return Err("The model has no open transaction to commit".into());
+        }
+        sqlite_connection.exec_batch("COMMIT").map_err(Into::into)?;
+        self.committed = self.pending.take().unwrap_or_default();
+        Ok(())
+    }
+
+    /// Rolls back the open transaction, discarding its changes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `ROLLBACK` fails or no transaction is open.
+    ///
+    pub fn rollback<E>(&mut self, mut sqlite_connection: E) -> Result<(), Box<dyn StdError>>
+    where
+        E: Executor,
+        E::Error: Into<Box<dyn StdError>>,
+    {
+        if !self.in_transaction() {
+            return Err("The model has no open transaction to roll back".into());
+        }
+        sqlite_connection
+            .exec_batch("ROLLBACK")
+            .map_err(Into::into)?;
+        self.pending = None;
+        Ok(())
+    }
+
+    /// Records that the connection running the model's operations died, for
+    /// example through [`Sqlite3Process::kill_now`](crate::Sqlite3Process::kill_now),
+    /// which loses its open transaction.
+    pub fn crashed(&mut self) {
+        self.pending = None;
+    }
+
+    /// Compares the table as `sqlite_connection` sees it with the committed rows.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error listing every missing, unexpected or different row,
+    /// or why the table could not be read.
+    ///
+    pub fn check<E>(&self, mut sqlite_connection: E) -> Result<(), String>
+    where
+        E: Executor,
+        E::Error: Into<Box<dyn StdError>>,
+    {
+        let actual = read_notes(&mut sqlite_connection, &self.schema)
+            .map_err(|e| format!("Failed to read {}.notes: {e}", self.schema))?;
+        let mut differences = Vec::new();
+        for (id, expected) in &self.committed {
+            match actual.get(id) {
+                None => differences.push(format!("row {id} is missing, expected {expected:?}")),
+                Some(text) if text != expected => {
+                    differences.push(format!("row {id} is {text:?}, expected {expected:?}"));
+                }
+                Some(_) => {}
+            }
+        }
+        for (id, text) in &actual {
+            if !self.committed.contains_key(id) {
+                differences.push(format!("row {id} is {text:?}, expected no row"));
+            }
+        }
+        if differences.is_empty() {
+            return Ok(());
+        }
+        let count = differences.len();
+        differences.truncate(MAX_DIFFERENCES);
+        Err(format!(
+            "{}.notes differs from the model in {count} rows:\n{}",
+            self.schema,
+            differences.join("\n")
+        ))
+    }
+
+    /// Returns the rows the model's own operations change.
+    fn rows_mut(&mut self) -> &mut BTreeMap<i64, String> {
+        self.pending.as_mut().unwrap_or(&mut self.committed)
+    }
+}
+
+/// Asserts that the `notes` table, as `sqlite_connection` sees it, holds
+/// exactly the committed rows of `model`.
+///
+/// Call it from a connection other than the one running an open transaction,
+/// or while none is open.
+///
+/// # Panics
+///
+/// Panics listing the rows that differ, or if the table cannot be read.
+///
+pub fn assert_matches_model<E>(sqlite_connection: E, model: &NotesModel)
+where
+    E: Executor,
+    E::Error: Into<Box<dyn StdError>>,
+{
+    if let Err(e) = model.check(sqlite_connection) {
+        panic!("{e}");
+    }
+}
+
+/// Reads every row of `schema.notes` in one query.
+fn read_notes<E>(
+    sqlite_connection: &mut E,
+    schema: &str,
+) -> Result<BTreeMap<i64, String>, Box<dyn StdError>>
+where
+    E: Executor,
+    E::Error: Into<Box<dyn StdError>>,
+{
+    // Hex keeps separators out of the text, and one query keeps a sqlite3
+    // process to one round trip.
+    let listing = sqlite_connection
+        .query_text(
+            &format!(
+                "SELECT group_concat(id || ':' || hex(text), ',') \
+                 FROM (SELECT id, text FROM {schema}.notes ORDER BY id)"
+            ),
+            &[],
+        )
+        .map_err(Into::into)?
+        .unwrap_or_default();
+    let mut rows = BTreeMap::new();
+    for row in listing.split(',').filter(|row| !row.is_empty()) {
+        let invalid = || format!("Invalid row listing {row:?}");
+        let (id, hex) = row.split_once(':').ok_or_else(invalid)?;
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|at| u8::from_str_radix(hex.get(at..at + 2).unwrap_or("?"), 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| invalid())?;
+        rows.insert(
+            id.parse().map_err(|_| invalid())?,
+            String::from_utf8(bytes).map_err(|_| invalid())?,
+        );
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Sqlite3Process;
+    use rusqlite::Connection;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_model_tracks_operations_and_transactions() {
+        let dir = tempdir().unwrap();
+        let conn = Connection::open(dir.path().join("test.db")).unwrap();
+        let mut model = NotesModel::init(&conn, "main", 7, 5, 6).unwrap();
+        assert_eq!(model, NotesModel::load(&conn, "main").unwrap());
+
+        let id = model.insert(&conn, 6).unwrap();
+        model.update(&conn, 2, 6).unwrap();
+        model.update(&conn, 99, 6).unwrap();
+        model.delete(&conn, 1).unwrap();
+        assert_matches_model(&conn, &model);
+        assert_eq!(
+            model.committed().keys().copied().collect::<Vec<_>>(),
+            [2, 3, 4, 5, id]
+        );
+
+        model.begin(&conn).unwrap();
+        model.delete(&conn, 2).unwrap();
+        assert!(!model.rows().contains_key(&2));
+        assert!(model.committed().contains_key(&2));
+        model.rollback(&conn).unwrap();
+        assert_matches_model(&conn, &model);
+        model.begin(&conn).unwrap();
+        model.insert(&conn, 6).unwrap();
+        model.commit(&conn).unwrap();
+        assert_matches_model(&conn, &model);
+        assert!(model.commit(&conn).is_err());
+
+        conn.execute_batch(
+            "UPDATE notes SET text = 'changed' WHERE id = 3; DELETE FROM notes WHERE id = 4;",
+        )
+        .unwrap();
+        let error = model.check(&conn).unwrap_err();
+        assert!(
+            error.starts_with("main.notes differs from the model in 2 rows"),
+            "{error}"
+        );
+        assert!(error.contains("row 3 is \"changed\", expected"), "{error}");
+        assert!(error.contains("row 4 is missing"), "{error}");
+    }
+
+    #[test]
+    fn test_random_workload_across_crashes() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+        let checker = Connection::open(&db_path).unwrap();
+        let mut model = NotesModel::init(&checker, "main", 3, 20, 8).unwrap();
+        crate::set_journal_mode(&checker, "WAL", "main").unwrap();
+
+        let mut rng = fastrand::Rng::with_seed(3);
+        let mut process = Sqlite3Process::new(&db_path).unwrap();
+        let mut crashes = 0;
+        for _ in 0..150 {
+            let ids: Vec<i64> = model.rows().keys().copied().collect();
+            let id = ids.get(rng.usize(..ids.len().max(1))).copied().unwrap_or(1);
+            match rng.u32(..20) {
+                0..=5 => model.insert(&mut process, 8).map(|_| ()),
+                6..=10 => model.update(&mut process, id, 8),
+                11..=13 => model.delete(&mut process, id),
+                14..=15 if !model.in_transaction() => model.begin(&mut process),
+                16 if model.in_transaction() => model.commit(&mut process),
+                17 if model.in_transaction() => model.rollback(&mut process),
+                18 => {
+                    process.kill_now().unwrap();
+                    model.crashed();
+                    crashes += 1;
+                    process = Sqlite3Process::new(&db_path).unwrap();
+                    Ok(())
+                }
+                _ => Ok(()),
+            }
+            .unwrap();
+            assert_matches_model(&checker, &model);
+        }
+        assert!(crashes > 0);
+        process.close().unwrap();
+    }
+}
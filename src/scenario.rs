@@ -0,0 +1,710 @@
+//! Declarative multi-process locking scenarios and their runner.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use rusqlite::Connection;
+
+use crate::worker::query_list_mode;
+use crate::{Sqlite3Process, Sqlite3ProcessBuilder, Transcript};
+
+mod isolation;
+mod text;
+
+pub use isolation::{
+    dirty_read_scenario, immediate_writer_scenario, isolation_scenarios, snapshot_read_scenario,
+    write_upgrade_scenario, JOURNAL_MODES,
+};
+
+/// How long a step waits before it counts as blocked, by default.
+pub const DEFAULT_SETTLE_TIME: Duration = Duration::from_millis(200);
+
+/// How long a step may take before the scenario fails, by default.
+pub const DEFAULT_STEP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A connection taking part in a [`Scenario`].
+#[derive(Debug, Clone)]
+pub enum ActorKind {
+    /// A sqlite3 process spawned by the given builder.
+    Process(Sqlite3ProcessBuilder),
+    /// An in-process rusqlite connection with a busy timeout of zero, like the
+    /// sqlite3 shell's default.
+    Connection,
+}
+
+/// The outcome a [`Scenario`] step must have.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expect {
+    /// The step succeeds, whatever it prints.
+    Ok,
+    /// The step succeeds and prints exactly these rows in list mode.
+    Rows(Vec<String>),
+    /// The step fails with `SQLITE_BUSY` ("database is locked").
+    Busy,
+    /// The step fails with an error containing the text.
+    Error(String),
+    /// The step does not finish until the given step (numbered from 1) has
+    /// finished, and then has the inner outcome.
+    BlocksUntil(usize, Box<Expect>),
+}
+
+impl Expect {
+    /// Expects the step to print exactly `rows`, each `|`-separated.
+    pub fn rows<I, S>(rows: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Expect::Rows(rows.into_iter().map(Into::into).collect())
+    }
+
+    /// Expects the step to block until step `step` finishes, then to have `then`.
+    pub fn blocks_until(step: usize, then: Expect) -> Self {
+        Expect::BlocksUntil(step, Box::new(then))
+    }
+
+    /// Checks a step's output, or its error, against the expectation.
+    fn check(&self, outcome: &Result<String, String>) -> Result<(), String> {
+        let matches = match (self, outcome) {
+            (Expect::Ok, Ok(_)) => true,
+            (Expect::Rows(rows), Ok(output)) => output.lines().eq(rows.iter().map(String::as_str)),
+            (Expect::Busy, Err(error)) => error.contains("database is locked"),
+            (Expect::Error(text), Err(error)) => error.contains(text.as_str()),
+            (Expect::BlocksUntil(_, then), outcome) => return then.check(outcome),
+            _ => false,
+        };
+        if matches {
+            Ok(())
+        } else {
+            Err(format!("expected {self}, got {}", Outcome(outcome)))
+        }
+    }
+}
+
+impl fmt::Display for Expect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expect::Ok => write!(f, "ok"),
+            Expect::Rows(rows) => write!(f, "rows {rows:?}"),
+            Expect::Busy => write!(f, "busy"),
+            Expect::Error(text) => write!(f, "error {text}"),
+            Expect::BlocksUntil(step, then) => write!(f, "blocks until {step} then {then}"),
+        }
+    }
+}
+
+/// One step of a [`Scenario`]: SQL run by an actor and its expected outcome.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    /// Name of the actor that runs the step.
+    pub actor: String,
+    /// The SQL, or a dot-command for process actors.
+    pub sql: String,
+    /// The outcome the step must have.
+    pub expect: Expect,
+}
+
+/// An ordered locking interleaving across named processes and connections.
+///
+/// Steps run one after another, each on its actor, and must have their
+/// expected outcome. A step expected to block is left running while later
+/// steps go ahead, and must finish only once the step it waits for has. Steps
+/// are numbered from 1 in the order they are added.
+///
+/// Scenarios can be built in code or parsed from text with
+/// [`parse`](Self::parse):
+///
+/// ```text
+/// scenario a reserved lock blocks other writers
+/// process a
+/// connection b
+///
+/// a: .timeout 5000 -> ok
+/// a: CREATE TABLE t (x); -> ok
+/// a: BEGIN IMMEDIATE; -> ok
+/// b: INSERT INTO t VALUES (1); -> busy
+/// b: PRAGMA busy_timeout = 5000; -> rows
+/// = 5000
+/// b: INSERT INTO t VALUES (2); -> blocks until 7 then ok
+/// a: COMMIT; -> ok
+/// a: SELECT x FROM t; -> rows
+/// = 2
+/// ```
+///
+/// Lines starting with `#` are comments. `process NAME` and `connection NAME`
+/// declare actors and `NAME: SQL -> EXPECTATION` adds a step, where the
+/// expectation is `ok`, `busy`, `error TEXT`, `rows` followed by one `= ROW`
+/// line per expected row, or `blocks until N then EXPECTATION`.
+///
+/// # Example
+///
+/// ```rust
+/// use sqlite_test_utils::{Expect, Scenario};
+///
+/// let dir = tempfile::tempdir().unwrap();
+/// let report = Scenario::new("readers see committed rows only")
+///     .process("writer")
+///     .connection("reader")
+///     .step("writer", "CREATE TABLE t (x);", Expect::Ok)
+///     .step("writer", "BEGIN; INSERT INTO t VALUES (1);", Expect::Ok)
+///     .step("reader", "SELECT COUNT(*) FROM t;", Expect::rows(["0"]))
+///     .step("writer", "COMMIT;", Expect::Ok)
+///     .step("reader", "SELECT COUNT(*) FROM t;", Expect::rows(["1"]))
+///     .run(&dir.path().join("test.db"))
+///     .unwrap();
+/// assert_eq!(report.steps().len(), 5);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Scenario {
+    name: String,
+    actors: Vec<(String, ActorKind)>,
+    steps: Vec<Step>,
+    settle_time: Duration,
+    step_timeout: Duration,
+}
+
+impl Scenario {
+    /// Creates an empty scenario described by `name`.
+    pub fn new(name: &str) -> Self {
+        Scenario {
+            name: name.to_string(),
+            actors: Vec::new(),
+            steps: Vec::new(),
+            settle_time: DEFAULT_SETTLE_TIME,
+            step_timeout: DEFAULT_STEP_TIMEOUT,
+        }
+    }
+
+    /// Parses a scenario from its text format.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the line that cannot be parsed, or describing
+    /// why the scenario is inconsistent.
+    ///
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let scenario = text::parse(text)?;
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    /// Reads and parses a scenario file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or parsed.
+    ///
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        Self::parse(&text).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    /// Adds an actor backed by a default sqlite3 process.
+    #[must_use]
+    pub fn process(self, name: &str) -> Self {
+        self.actor(name, ActorKind::Process(Sqlite3ProcessBuilder::new()))
+    }
+
+    /// Adds an actor backed by a sqlite3 process spawned by `builder`.
+    #[must_use]
+    pub fn process_with(self, name: &str, builder: Sqlite3ProcessBuilder) -> Self {
+        self.actor(name, ActorKind::Process(builder))
+    }
+
+    /// Adds an actor backed by an in-process connection.
+    #[must_use]
+    pub fn connection(self, name: &str) -> Self {
+        self.actor(name, ActorKind::Connection)
+    }
+
+    /// Adds an actor of the given kind.
+    #[must_use]
+    pub fn actor(mut self, name: &str, kind: ActorKind) -> Self {
+        self.actors.push((name.to_string(), kind));
+        self
+    }
+
+    /// Adds a step in which `actor` runs `sql` with the `expect`ed outcome.
+    #[must_use]
+    pub fn step(mut self, actor: &str, sql: &str, expect: Expect) -> Self {
+        self.steps.push(Step {
+            actor: actor.to_string(),
+            sql: sql.to_string(),
+            expect,
+        });
+        self
+    }
+
+    /// Sets how long a step must stay unfinished to count as blocked.
+    ///
+    /// Defaults to [`DEFAULT_SETTLE_TIME`].
+    #[must_use]
+    pub fn settle_time(mut self, settle_time: Duration) -> Self {
+        self.settle_time = settle_time;
+        self
+    }
+
+    /// Sets how long any step may take before the scenario fails.
+    ///
+    /// Defaults to [`DEFAULT_STEP_TIMEOUT`].
+    #[must_use]
+    pub fn step_timeout(mut self, step_timeout: Duration) -> Self {
+        self.step_timeout = step_timeout;
+        self
+    }
+
+    /// Returns the scenario's description.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the steps in order.
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// Runs the scenario against the database at `db_path`.
+    ///
+    /// Every actor is opened before the first step and closed after the last.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the scenario is inconsistent, an actor cannot be
+    /// opened, or a step does not have its expected outcome. The error ends
+    /// with the report of every step run so far and the transcript of every
+    /// process.
+    ///
+    pub fn run(&self, db_path: &Path) -> Result<ScenarioReport, String> {
+        self.validate()?;
+        let (reply_tx, replies) = mpsc::channel();
+        let mut actors = HashMap::new();
+        let mut handles = Vec::new();
+        for (name, kind) in &self.actors {
+            let actor = Actor::open(kind, db_path)
+                .map_err(|e| format!("Scenario {}: failed to open {name}: {e}", self.name))?;
+            let (tx, handle) = actor.spawn(reply_tx.clone());
+            actors.insert(name.as_str(), tx);
+            handles.push((name.clone(), handle));
+        }
+
+        let mut run = Run {
+            scenario: self,
+            actors,
+            replies,
+            started: Instant::now(),
+            report: ScenarioReport {
+                name: self.name.clone(),
+                steps: Vec::new(),
+                transcripts: Vec::new(),
+            },
+        };
+        let result = run.steps();
+        let Run {
+            actors, mut report, ..
+        } = run;
+        // Closing the channels ends the actor threads once their step finishes.
+        drop(actors);
+        for (name, handle) in handles {
+            if let Ok(Some(transcript)) = handle.join() {
+                report.transcripts.push((name, transcript));
+            }
+        }
+        match result {
+            Ok(()) => Ok(report),
+            Err(e) => Err(format!("Scenario {} failed: {e}\n{report}", self.name)),
+        }
+    }
+
+    /// Checks that steps name known actors and blocking steps can be run.
+    fn validate(&self) -> Result<(), String> {
+        for (index, (name, _)) in self.actors.iter().enumerate() {
+            if name.is_empty() || name.contains(char::is_whitespace) || name.contains(':') {
+                return Err(format!("Invalid actor name {name:?}"));
+            }
+            if self.actors[..index].iter().any(|(other, _)| other == name) {
+                return Err(format!("Actor {name} is declared twice"));
+            }
+        }
+        for (index, step) in self.steps.iter().enumerate() {
+            let number = index + 1;
+            if !self.actors.iter().any(|(name, _)| *name == step.actor) {
+                return Err(format!("Step {number}: unknown actor {}", step.actor));
+            }
+            let Expect::BlocksUntil(until, then) = &step.expect else {
+                continue;
+            };
+            if *until <= number || *until > self.steps.len() {
+                return Err(format!(
+                    "Step {number}: blocks until step {until}, which is not a later step"
+                ));
+            }
+            if matches!(**then, Expect::BlocksUntil(..)) {
+                return Err(format!("Step {number}: nested blocks until"));
+            }
+            if matches!(self.steps[until - 1].expect, Expect::BlocksUntil(..)) {
+                return Err(format!(
+                    "Step {number}: blocks until step {until}, which blocks itself"
+                ));
+            }
+            if let Some(offset) = self.steps[number..*until]
+                .iter()
+                .position(|other| other.actor == step.actor)
+            {
+                return Err(format!(
+                    "Step {}: {} is still blocked in step {number}",
+                    number + offset + 1,
+                    step.actor
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What happened at each step of a [`Scenario`] run.
+///
+/// Rendered with [`Display`](fmt::Display) as a log of the steps followed by
+/// the transcript of every process actor.
+#[derive(Debug, Clone)]
+pub struct ScenarioReport {
+    name: String,
+    steps: Vec<StepRecord>,
+    transcripts: Vec<(String, Transcript)>,
+}
+
+/// A step that was started during a [`Scenario`] run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepRecord {
+    /// The step number, from 1.
+    pub number: usize,
+    /// The step as declared.
+    pub step: Step,
+    /// Time between the first step and sending this one.
+    pub at: Duration,
+    /// How long the step ran, once it has finished.
+    pub duration: Option<Duration>,
+    /// The step's output or error, once it has finished.
+    pub outcome: Option<Result<String, String>>,
+}
+
+impl ScenarioReport {
+    /// Returns the steps started, in order.
+    pub fn steps(&self) -> &[StepRecord] {
+        &self.steps
+    }
+
+    /// Returns the transcript of each process actor, by actor name.
+    pub fn transcripts(&self) -> &[(String, Transcript)] {
+        &self.transcripts
+    }
+}
+
+impl fmt::Display for ScenarioReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "--- scenario {} ---", self.name)?;
+        for record in &self.steps {
+            let step = &record.step;
+            writeln!(
+                f,
+                "[{}] [+{:.3}s] {}: {} -> {}",
+                record.number,
+                record.at.as_secs_f64(),
+                step.actor,
+                step.sql,
+                step.expect
+            )?;
+            match (&record.outcome, record.duration) {
+                (Some(outcome), Some(duration)) => writeln!(
+                    f,
+                    "    {} ({:.1}ms)",
+                    Outcome(outcome),
+                    duration.as_secs_f64() * 1000.0
+                )?,
+                _ => writeln!(f, "    unfinished")?,
+            }
+        }
+        write!(f, "--- end of scenario ({} steps) ---", self.steps.len())?;
+        for (name, transcript) in &self.transcripts {
+            write!(f, "\n{name}: {transcript}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Renders a step outcome on one line.
+struct Outcome<'a>(&'a Result<String, String>);
+
+impl fmt::Display for Outcome<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Ok(output) => write!(f, "ok {:?}", output.lines().collect::<Vec<_>>()),
+            Err(error) => write!(f, "error {:?}", error.trim_end()),
+        }
+    }
+}
+
+/// An opened actor, run on its own thread so that steps can block.
+enum Actor {
+    Process(Box<Sqlite3Process>),
+    Connection(Connection),
+}
+
+/// A finished step: its index, outcome and duration.
+type Reply = (usize, Result<String, String>, Duration);
+
+impl Actor {
+    fn open(kind: &ActorKind, db_path: &Path) -> Result<Self, String> {
+        match kind {
+            ActorKind::Process(builder) => Ok(Actor::Process(Box::new(builder.spawn(db_path)?))),
+            ActorKind::Connection => {
+                let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+                conn.busy_timeout(Duration::ZERO)
+                    .map_err(|e| e.to_string())?;
+                Ok(Actor::Connection(conn))
+            }
+        }
+    }
+
+    /// Runs steps sent to the returned channel until it closes, then returns
+    /// the process transcript.
+    fn spawn(
+        mut self,
+        replies: Sender<Reply>,
+    ) -> (Sender<(usize, String)>, JoinHandle<Option<Transcript>>) {
+        let (tx, steps) = mpsc::channel::<(usize, String)>();
+        let handle = thread::spawn(move || {
+            for (index, sql) in steps {
+                let started = Instant::now();
+                let outcome = match self {
+                    Actor::Process(ref mut process) => process.execute_checked(&sql),
+                    Actor::Connection(ref conn) => query_list_mode(conn, &sql),
+                };
+                if replies.send((index, outcome, started.elapsed())).is_err() {
+                    break;
+                }
+            }
+            match self {
+                Actor::Process(process) => Some(process.transcript().clone()),
+                Actor::Connection(_) => None,
+            }
+        });
+        (tx, handle)
+    }
+}
+
+/// State of a scenario run in progress.
+struct Run<'a> {
+    scenario: &'a Scenario,
+    actors: HashMap<&'a str, Sender<(usize, String)>>,
+    replies: Receiver<Reply>,
+    started: Instant,
+    report: ScenarioReport,
+}
+
+impl Run<'_> {
+    fn steps(&mut self) -> Result<(), String> {
+        for (index, step) in self.scenario.steps.iter().enumerate() {
+            self.report.steps.push(StepRecord {
+                number: index + 1,
+                step: step.clone(),
+                at: self.started.elapsed(),
+                duration: None,
+                outcome: None,
+            });
+            self.actors[step.actor.as_str()]
+                .send((index, step.sql.clone()))
+                .map_err(|_| format!("step {}: {} has stopped", index + 1, step.actor))?;
+
+            if let Expect::BlocksUntil(until, _) = step.expect {
+                if self.finishes_within(index, self.scenario.settle_time)? {
+                    return Err(format!("step {} finished before step {until}", index + 1));
+                }
+                continue;
+            }
+
+            self.wait(index)?;
+            self.check(index)?;
+            for blocked in self.blocked_until(index + 1) {
+                self.wait(blocked)?;
+                self.check(blocked)?;
+            }
+            let early = self
+                .report
+                .steps
+                .iter()
+                .find_map(|record| match record.step.expect {
+                    Expect::BlocksUntil(until, _)
+                        if until > index + 1 && record.outcome.is_some() =>
+                    {
+                        Some((record.number, until))
+                    }
+                    _ => None,
+                });
+            if let Some((number, until)) = early {
+                return Err(format!("step {number} finished before step {until}"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the indexes of the steps that block until step `number`.
+    fn blocked_until(&self, number: usize) -> Vec<usize> {
+        self.scenario
+            .steps
+            .iter()
+            .enumerate()
+            .filter(
+                |(_, step)| matches!(step.expect, Expect::BlocksUntil(until, _) if until == number),
+            )
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Waits for step `index` to finish.
+    fn wait(&mut self, index: usize) -> Result<(), String> {
+        if self.finishes_within(index, self.scenario.step_timeout)? {
+            Ok(())
+        } else {
+            Err(format!(
+                "step {} did not finish within {:?}",
+                index + 1,
+                self.scenario.step_timeout
+            ))
+        }
+    }
+
+    /// Records replies until step `index` finishes, or `timeout` expires.
+    ///
+    /// Returns `true` if the step has finished.
+    fn finishes_within(&mut self, index: usize, timeout: Duration) -> Result<bool, String> {
+        let deadline = Instant::now() + timeout;
+        while self.report.steps[index].outcome.is_none() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.replies.recv_timeout(remaining) {
+                Ok((finished, outcome, duration)) => {
+                    let record = &mut self.report.steps[finished];
+                    record.outcome = Some(outcome);
+                    record.duration = Some(duration);
+                }
+                Err(RecvTimeoutError::Timeout) => return Ok(false),
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err("every actor has stopped".to_string())
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Checks the outcome of finished step `index`.
+    fn check(&self, index: usize) -> Result<(), String> {
+        let record = &self.report.steps[index];
+        let outcome = record.outcome.as_ref().expect("step has finished");
+        record
+            .step
+            .expect
+            .check(outcome)
+            .map_err(|e| format!("step {} ({}): {e}", record.number, record.step.actor))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn writer_scenario() -> Scenario {
+        Scenario::new("reserved lock blocks writers")
+            .process("a")
+            .connection("b")
+            .step("a", ".timeout 5000", Expect::Ok)
+            .step("a", "CREATE TABLE t (x);", Expect::Ok)
+            .step(
+                "a",
+                "BEGIN IMMEDIATE; INSERT INTO t VALUES (1);",
+                Expect::Ok,
+            )
+            .step("b", "INSERT INTO t VALUES (2);", Expect::Busy)
+            .step("b", "SELECT COUNT(*) FROM t;", Expect::rows(["0"]))
+            .step("b", "PRAGMA busy_timeout = 5000;", Expect::rows(["5000"]))
+            .step(
+                "b",
+                "INSERT INTO t VALUES (2);",
+                Expect::blocks_until(8, Expect::Ok),
+            )
+            .step("a", "COMMIT;", Expect::Ok)
+            .step("a", "SELECT x FROM t ORDER BY x;", Expect::rows(["1", "2"]))
+    }
+
+    #[test]
+    fn test_run_blocking_scenario() {
+        let dir = tempdir().unwrap();
+        let report = writer_scenario().run(&dir.path().join("test.db")).unwrap();
+
+        assert_eq!(report.steps().len(), 9);
+        assert!(report.steps().iter().all(|record| record.outcome.is_some()));
+        let blocked = report.steps()[6].duration.unwrap();
+        assert!(blocked >= DEFAULT_SETTLE_TIME, "{blocked:?}");
+        assert_eq!(report.transcripts().len(), 1);
+        let rendered = report.to_string();
+        assert!(rendered.contains("[4] [+"), "{rendered}");
+        assert!(rendered.contains("b: INSERT INTO t VALUES (2); -> busy"));
+        assert!(rendered.contains("sqlite> COMMIT;"));
+    }
+
+    #[test]
+    fn test_failures_name_the_step() {
+        let dir = tempdir().unwrap();
+        let error = Scenario::new("wrong expectation")
+            .process("a")
+            .step("a", "SELECT 1;", Expect::rows(["2"]))
+            .run(&dir.path().join("test.db"))
+            .unwrap_err();
+        assert!(
+            error.contains("step 1 (a): expected rows [\"2\"], got ok [\"1\"]"),
+            "{error}"
+        );
+        assert!(error.contains("sqlite> SELECT 1;"), "{error}");
+
+        let error = Scenario::new("does not block")
+            .process("a")
+            .process("b")
+            .step("a", "SELECT 1;", Expect::blocks_until(2, Expect::Ok))
+            .step("b", "SELECT 2;", Expect::Ok)
+            .run(&dir.path().join("test.db"))
+            .unwrap_err();
+        assert!(error.contains("step 1 finished before step 2"), "{error}");
+    }
+
+    #[test]
+    fn test_validate() {
+        let invalid = [
+            Scenario::new("unknown actor").step("a", "SELECT 1;", Expect::Ok),
+            Scenario::new("twice").process("a").connection("a"),
+            Scenario::new("backwards").process("a").step(
+                "a",
+                "SELECT 1;",
+                Expect::blocks_until(1, Expect::Ok),
+            ),
+            Scenario::new("still blocked")
+                .process("a")
+                .step("a", "SELECT 1;", Expect::blocks_until(2, Expect::Ok))
+                .step("a", "SELECT 2;", Expect::Ok),
+            Scenario::new("blocks on a blocked step")
+                .process("a")
+                .process("b")
+                .process("c")
+                .step("a", "SELECT 1;", Expect::blocks_until(2, Expect::Ok))
+                .step("b", "SELECT 2;", Expect::blocks_until(3, Expect::Ok))
+                .step("c", "SELECT 3;", Expect::Ok),
+        ];
+        for scenario in invalid {
+            assert!(scenario.validate().is_err(), "{}", scenario.name());
+        }
+        assert!(writer_scenario().validate().is_ok());
+    }
+}
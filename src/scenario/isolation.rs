@@ -0,0 +1,250 @@
+//! Reusable scenarios for SQLite's transaction isolation guarantees.
+
+use super::{ActorKind, Expect, Scenario};
+
+/// Every journal mode the isolation scenarios can run in.
+pub const JOURNAL_MODES: [&str; 6] = ["DELETE", "TRUNCATE", "PERSIST", "MEMORY", "WAL", "OFF"];
+
+/// A reader inside a transaction keeps seeing the same snapshot while a
+/// writer changes the table.
+///
+/// In WAL mode the writer commits and the reader sees the change once its
+/// transaction ends. In the rollback journal modes the reader's shared lock
+/// makes the writer's commit fail with `SQLITE_BUSY` instead.
+///
+/// # Arguments
+///
+/// * `journal_mode` - One of [`JOURNAL_MODES`]
+/// * `reader` - The actor holding the read transaction
+/// * `writer` - The actor changing the table
+///
+pub fn snapshot_read_scenario(
+    journal_mode: &str,
+    reader: ActorKind,
+    writer: ActorKind,
+) -> Scenario {
+    let wal = is_wal(journal_mode);
+    let committed = if wal { "2" } else { "1" };
+    setup(
+        "snapshot read",
+        journal_mode,
+        ("reader", reader),
+        ("writer", writer),
+    )
+    .step("reader", "BEGIN; SELECT x FROM t;", Expect::rows(["1"]))
+    .step(
+        "writer",
+        "UPDATE t SET x = 2;",
+        if wal { Expect::Ok } else { Expect::Busy },
+    )
+    .step("reader", "SELECT x FROM t;", Expect::rows(["1"]))
+    .step("reader", "COMMIT;", Expect::Ok)
+    .step("reader", "SELECT x FROM t;", Expect::rows([committed]))
+}
+
+/// A reader never sees a writer's uncommitted change, only the committed one.
+///
+/// # Arguments
+///
+/// * `journal_mode` - One of [`JOURNAL_MODES`]
+/// * `reader` - The actor reading the table
+/// * `writer` - The actor changing it inside a transaction
+///
+pub fn dirty_read_scenario(journal_mode: &str, reader: ActorKind, writer: ActorKind) -> Scenario {
+    setup(
+        "no dirty read",
+        journal_mode,
+        ("reader", reader),
+        ("writer", writer),
+    )
+    .step("writer", "BEGIN; UPDATE t SET x = 2;", Expect::Ok)
+    .step("reader", "SELECT x FROM t;", Expect::rows(["1"]))
+    .step("writer", "SELECT x FROM t;", Expect::rows(["2"]))
+    .step("writer", "COMMIT;", Expect::Ok)
+    .step("reader", "SELECT x FROM t;", Expect::rows(["2"]))
+}
+
+/// `BEGIN IMMEDIATE` admits one writer at a time, while others can still
+/// read.
+///
+/// # Arguments
+///
+/// * `journal_mode` - One of [`JOURNAL_MODES`]
+/// * `first` - The actor that gets the write lock
+/// * `second` - The actor that is refused it until the first commits
+///
+pub fn immediate_writer_scenario(
+    journal_mode: &str,
+    first: ActorKind,
+    second: ActorKind,
+) -> Scenario {
+    setup(
+        "BEGIN IMMEDIATE writer exclusion",
+        journal_mode,
+        ("first", first),
+        ("second", second),
+    )
+    .step("first", "BEGIN IMMEDIATE;", Expect::Ok)
+    .step("second", "BEGIN IMMEDIATE;", Expect::Busy)
+    .step("second", "SELECT x FROM t;", Expect::rows(["1"]))
+    .step("first", "UPDATE t SET x = 2; COMMIT;", Expect::Ok)
+    .step("second", "BEGIN IMMEDIATE;", Expect::Ok)
+    .step("second", "SELECT x FROM t; COMMIT;", Expect::rows(["2"]))
+}
+
+/// Two readers that both try to upgrade to writers cannot both succeed:
+/// the second gets `SQLITE_BUSY` and must roll back.
+///
+/// In the rollback journal modes the first writer's commit is also busy
+/// until the second rolls back and drops its shared lock. In WAL mode the
+/// first commits at once.
+///
+/// # Arguments
+///
+/// * `journal_mode` - One of [`JOURNAL_MODES`]
+/// * `first` - The actor that upgrades first and commits
+/// * `second` - The actor whose upgrade fails
+///
+pub fn write_upgrade_scenario(journal_mode: &str, first: ActorKind, second: ActorKind) -> Scenario {
+    let wal = is_wal(journal_mode);
+    let mut scenario = setup(
+        "read-to-write upgrade",
+        journal_mode,
+        ("first", first),
+        ("second", second),
+    )
+    .step("first", "BEGIN; SELECT x FROM t;", Expect::rows(["1"]))
+    .step("second", "BEGIN; SELECT x FROM t;", Expect::rows(["1"]))
+    .step("first", "UPDATE t SET x = 2;", Expect::Ok)
+    .step("second", "UPDATE t SET x = 3;", Expect::Busy);
+    if !wal {
+        scenario = scenario.step("first", "COMMIT;", Expect::Busy);
+    }
+    scenario
+        .step("second", "ROLLBACK;", Expect::Ok)
+        .step("first", "COMMIT;", Expect::Ok)
+        .step("second", "SELECT x FROM t;", Expect::rows(["2"]))
+}
+
+/// Returns every isolation scenario for one journal mode and pair of actors.
+///
+/// Each scenario creates its own table, so run each on a fresh database.
+///
+/// # Example
+///
+/// ```rust
+/// use sqlite_test_utils::{isolation_scenarios, ActorKind, Sqlite3ProcessBuilder, JOURNAL_MODES};
+///
+/// let dir = tempfile::tempdir().unwrap();
+/// for mode in JOURNAL_MODES {
+///     let process = ActorKind::Process(Sqlite3ProcessBuilder::new());
+///     for (index, scenario) in isolation_scenarios(mode, process, ActorKind::Connection)
+///         .iter()
+///         .enumerate()
+///     {
+///         scenario.run(&dir.path().join(format!("{mode}-{index}.db"))).unwrap();
+///     }
+/// }
+/// ```
+pub fn isolation_scenarios(
+    journal_mode: &str,
+    first: ActorKind,
+    second: ActorKind,
+) -> Vec<Scenario> {
+    vec![
+        snapshot_read_scenario(journal_mode, first.clone(), second.clone()),
+        dirty_read_scenario(journal_mode, first.clone(), second.clone()),
+        immediate_writer_scenario(journal_mode, first.clone(), second.clone()),
+        write_upgrade_scenario(journal_mode, first, second),
+    ]
+}
+
+/// Returns `true` for WAL mode, in any case.
+fn is_wal(journal_mode: &str) -> bool {
+    journal_mode.eq_ignore_ascii_case("wal")
+}
+
+/// Starts a scenario with both actors in `journal_mode` and a table `t`
+/// holding one row with `x = 1`.
+fn setup(
+    name: &str,
+    journal_mode: &str,
+    (first, first_kind): (&str, ActorKind),
+    (second, second_kind): (&str, ActorKind),
+) -> Scenario {
+    let pragma = format!("PRAGMA journal_mode = {journal_mode};");
+    let mode = journal_mode.to_lowercase();
+    let (first_kind, second_kind) = if mode == "off" {
+        (
+            allow_journal_off(first_kind),
+            allow_journal_off(second_kind),
+        )
+    } else {
+        (first_kind, second_kind)
+    };
+    Scenario::new(&format!(
+        "{name} ({mode}, {} {first}, {} {second})",
+        kind_name(&first_kind),
+        kind_name(&second_kind)
+    ))
+    .actor(first, first_kind)
+    .actor(second, second_kind)
+    .step(first, &pragma, Expect::rows([mode.as_str()]))
+    .step(
+        first,
+        "CREATE TABLE t (x); INSERT INTO t VALUES (1);",
+        Expect::Ok,
+    )
+    .step(second, &pragma, Expect::rows([mode.as_str()]))
+}
+
+/// Turns off the defensive mode the sqlite3 shell starts in, which keeps
+/// `PRAGMA journal_mode = OFF` from taking effect.
+fn allow_journal_off(kind: ActorKind) -> ActorKind {
+    match kind {
+        ActorKind::Process(builder) => ActorKind::Process(builder.cmd(".dbconfig defensive off")),
+        ActorKind::Connection => ActorKind::Connection,
+    }
+}
+
+/// Names an actor kind for scenario descriptions.
+fn kind_name(kind: &ActorKind) -> &'static str {
+    match kind {
+        ActorKind::Process(_) => "process",
+        ActorKind::Connection => "connection",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Sqlite3ProcessBuilder;
+    use tempfile::tempdir;
+
+    fn run_all(first: &ActorKind, second: &ActorKind) {
+        let dir = tempdir().unwrap();
+        for mode in JOURNAL_MODES {
+            for (index, scenario) in isolation_scenarios(mode, first.clone(), second.clone())
+                .iter()
+                .enumerate()
+            {
+                scenario
+                    .run(&dir.path().join(format!("{mode}-{index}.db")))
+                    .unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_processes_in_every_journal_mode() {
+        let process = ActorKind::Process(Sqlite3ProcessBuilder::new());
+        run_all(&process, &process);
+    }
+
+    #[test]
+    fn test_processes_and_connections_in_every_journal_mode() {
+        let process = ActorKind::Process(Sqlite3ProcessBuilder::new());
+        run_all(&process, &ActorKind::Connection);
+        run_all(&ActorKind::Connection, &process);
+    }
+}
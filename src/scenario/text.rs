@@ -0,0 +1,145 @@
+//! Parser for the text format of [`Scenario`].
+
+use super::{Expect, Scenario};
+
+/// Parses the lines of a scenario file, without checking its consistency.
+pub(super) fn parse(text: &str) -> Result<Scenario, String> {
+    let mut scenario = Scenario::new("");
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(row) = line.strip_prefix('=') {
+            let Some(Expect::Rows(rows)) =
+                scenario.steps.last_mut().map(|step| match step.expect {
+                    Expect::BlocksUntil(_, ref mut then) => then.as_mut(),
+                    ref mut expect => expect,
+                })
+            else {
+                return Err(format!("line {number}: row without a preceding rows step"));
+            };
+            rows.push(row.trim_start().to_string());
+        } else if let Some(name) = line.strip_prefix("scenario ") {
+            scenario.name = name.trim().to_string();
+        } else if let Some(name) = line.strip_prefix("process ") {
+            scenario = scenario.process(name.trim());
+        } else if let Some(name) = line.strip_prefix("connection ") {
+            scenario = scenario.connection(name.trim());
+        } else {
+            let (actor, rest) = line
+                .split_once(':')
+                .ok_or_else(|| format!("line {number}: expected `ACTOR: SQL -> EXPECTATION`"))?;
+            let (sql, expect) = rest
+                .rsplit_once(" -> ")
+                .ok_or_else(|| format!("line {number}: missing ` -> EXPECTATION`"))?;
+            let expect = parse_expect(expect.trim()).map_err(|e| format!("line {number}: {e}"))?;
+            scenario = scenario.step(actor.trim(), sql.trim(), expect);
+        }
+    }
+    Ok(scenario)
+}
+
+/// Parses `ok`, `busy`, `rows`, `error TEXT` or `blocks until N then EXPECTATION`.
+fn parse_expect(text: &str) -> Result<Expect, String> {
+    match text {
+        "ok" => return Ok(Expect::Ok),
+        "busy" => return Ok(Expect::Busy),
+        "rows" => return Ok(Expect::Rows(Vec::new())),
+        _ => {}
+    }
+    if let Some(error) = text.strip_prefix("error ") {
+        return Ok(Expect::Error(error.trim().to_string()));
+    }
+    if let Some(rest) = text.strip_prefix("blocks until ") {
+        let (step, then) = rest
+            .split_once(" then ")
+            .ok_or("expected `blocks until N then EXPECTATION`")?;
+        let step = step
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid step number {step:?}"))?;
+        return Ok(Expect::blocks_until(step, parse_expect(then.trim())?));
+    }
+    Err(format!("unknown expectation {text:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const TEXT: &str = "\
+# Readers are not blocked by a reserved lock.
+scenario reserved lock
+process a
+connection b
+
+a: .timeout 5000 -> ok
+a: CREATE TABLE t (x, y); INSERT INTO t VALUES (1, 'one'); -> ok
+a: BEGIN IMMEDIATE; -> ok
+b: SELECT x, y FROM t; -> rows
+= 1|one
+b: SELECT z ->> '$' FROM t; -> error no such column
+b: DELETE FROM t; -> busy
+b: PRAGMA busy_timeout = 5000; -> rows
+= 5000
+b: DELETE FROM t; -> blocks until 9 then ok
+a: COMMIT; -> ok
+a: SELECT COUNT(*) FROM t; -> rows
+= 0
+";
+
+    #[test]
+    fn test_parse_and_run() {
+        let scenario = Scenario::parse(TEXT).unwrap();
+        assert_eq!(scenario.name(), "reserved lock");
+        assert_eq!(scenario.steps().len(), 10);
+        assert_eq!(scenario.steps()[3].expect, Expect::rows(["1|one"]));
+        assert_eq!(
+            scenario.steps()[4].sql,
+            "SELECT z ->> '$' FROM t;",
+            "splits at the last arrow"
+        );
+        assert_eq!(
+            scenario.steps()[7].expect,
+            Expect::blocks_until(9, Expect::Ok)
+        );
+
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("reserved.scenario");
+        std::fs::write(&file, TEXT).unwrap();
+        Scenario::from_file(&file)
+            .unwrap()
+            .run(&dir.path().join("test.db"))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for (text, error) in [
+            (
+                "process a\na SELECT 1; -> ok",
+                "line 2: expected `ACTOR: SQL",
+            ),
+            (
+                "process a\na: SELECT 1;",
+                "line 2: missing ` -> EXPECTATION`",
+            ),
+            (
+                "process a\na: SELECT 1; -> fine",
+                "unknown expectation \"fine\"",
+            ),
+            ("= 1", "line 1: row without a preceding rows step"),
+            (
+                "process a\na: SELECT 1; -> blocks until x then ok",
+                "invalid step number",
+            ),
+            ("process a\nb: SELECT 1; -> ok", "Step 1: unknown actor b"),
+        ] {
+            let parsed = Scenario::parse(text).unwrap_err();
+            assert!(parsed.contains(error), "{parsed}");
+        }
+    }
+}
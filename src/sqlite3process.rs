@@ -2,17 +2,99 @@
 
 #![allow(clippy::doc_markdown)] // SQLite is a proper noun, not code
 
-use std::io::{BufRead, BufReader, Read, Write};
+use std::io::{self, BufRead, BufReader, Read, Write};
 use std::path::{Path, PathBuf};
-use std::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command, Stdio};
+use std::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant as StdInstant};
+
+#[cfg(feature = "tokio")]
+mod async_process;
+mod builder;
+mod databases;
+mod dot_commands;
+mod dummy_data;
+mod params;
+mod pending;
+mod pool;
+mod resources;
+mod transcript;
+#[cfg(feature = "tokio")]
+pub use async_process::{AsyncLockGuard, AsyncSqlite3Process, LockMode};
+use builder::IsolatedHome;
+pub use builder::{Sqlite3Info, Sqlite3ProcessBuilder, DEFAULT_GRACE_PERIOD, SQLITE3_BIN_ENV};
+pub(crate) use dot_commands::quote_arg;
+pub use dot_commands::DbInfo;
+pub use dummy_data::{DummyData, DummyValues};
+pub use params::sql_literal;
+pub use pending::PendingResult;
+pub use pool::{ProcessPool, ProcessPoolBuilder};
+#[cfg(target_os = "linux")]
+pub use resources::ResourceUsage;
+pub use transcript::{Transcript, TranscriptEntry};
 
 #[cfg(test)]
 use mock_instant::global::Instant;
 #[cfg(not(test))]
 use std::time::Instant;
 
+/// Dot-commands run at startup so that `execute` output does not depend on
+/// `~/.sqliterc`, an `-init` file or `-cmd` options.
+const PINNED_OUTPUT_SETTINGS: &str = "\
+.mode list
+.headers off
+.separator \"|\" \"\\n\"
+.nullvalue \"\"
+.timer off
+.echo off
+.changes off
+.stats off
+.eqp off";
+
+/// Sent after every command; its output tells the reader the command is done.
+///
+/// The leading `;` terminates the command if it lacks a semicolon; otherwise the
+/// shell would append the marker to it and never print the marker.
+const MARKER_QUERY: &str = ";SELECT 'MARKER_END';";
+
+/// Files left next to a database by a process that did not shut down cleanly.
+///
+/// Each field is `Some` only if the corresponding file exists on disk at the
+/// moment the process was killed.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LeftoverFiles {
+    /// The rollback journal (`<db>-journal`), a hot journal after a crash.
+    pub journal: Option<PathBuf>,
+    /// The write-ahead log (`<db>-wal`).
+    pub wal: Option<PathBuf>,
+    /// The WAL shared-memory index (`<db>-shm`).
+    pub shm: Option<PathBuf>,
+}
+
+impl LeftoverFiles {
+    /// Collects the journal, WAL and shared-memory files that exist for `db_path`.
+    pub fn for_db(db_path: &Path) -> Self {
+        let existing = |suffix: &str| {
+            let mut path = db_path.as_os_str().to_owned();
+            path.push(suffix);
+            let path = PathBuf::from(path);
+            path.exists().then_some(path)
+        };
+
+        LeftoverFiles {
+            journal: existing("-journal"),
+            wal: existing("-wal"),
+            shm: existing("-shm"),
+        }
+    }
+
+    /// Returns `true` if no journal, WAL or shared-memory file was left behind.
+    pub fn is_empty(&self) -> bool {
+        self.journal.is_none() && self.wal.is_none() && self.shm.is_none()
+    }
+}
+
 /// Wrapper for controlling an interactive sqlite3 process.
 ///
 /// This struct spawns and interacts with an external `sqlite3` command-line process,
@@ -39,14 +121,28 @@ use std::time::Instant;
 pub struct Sqlite3Process {
     child: Option<Child>,
     stdin: Option<ChildStdin>,
-    stdout: Option<BufReader<ChildStdout>>,
+    stdout: Option<Receiver<io::Result<String>>>,
+    pending: Option<PendingCommand>,
     pub(crate) stderr: Option<ChildStderr>,
     db_path: PathBuf,
+    suspended: bool,
+    stderr_log: String,
+    last_stderr: String,
+    grace_period: Duration,
+    on_hang: HangBehavior,
+    transcript: Transcript,
+    dump_transcript_on_panic: bool,
+    // Declared last so it is removed only after the child has been reaped.
+    _home: Option<IsolatedHome>,
 }
 
 impl Sqlite3Process {
     /// Creates a new `Sqlite3Process` connected to the specified database.
     ///
+    /// Runs the binary named by `SQLITE3_BIN`, or `sqlite3` from `PATH`, isolated
+    /// from the user's `~/.sqliterc` and environment. Use [`builder`](Self::builder)
+    /// to customize the process.
+    ///
     /// # Arguments
     ///
     /// * `db_path` - Path to the SQLite database file
@@ -57,8 +153,24 @@ impl Sqlite3Process {
     /// the I/O handles cannot be obtained.
     ///
     pub fn new(db_path: &Path) -> Result<Self, String> {
-        let mut child = Command::new("sqlite3")
-            .arg(db_path)
+        Sqlite3ProcessBuilder::new().spawn(db_path)
+    }
+
+    /// Returns a builder for choosing the sqlite3 binary and its command-line options.
+    pub fn builder() -> Sqlite3ProcessBuilder {
+        Sqlite3ProcessBuilder::new()
+    }
+
+    /// Spawns `command` with piped I/O and wraps it.
+    fn spawn(
+        mut command: Command,
+        db_path: &Path,
+        home: Option<IsolatedHome>,
+        grace_period: Duration,
+        on_hang: HangBehavior,
+        dump_transcript_on_panic: bool,
+    ) -> Result<Self, String> {
+        let mut child = command
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
@@ -68,14 +180,32 @@ impl Sqlite3Process {
         let stdin = child.stdin.take().ok_or("Failed to get stdin handle")?;
         let stdout = child.stdout.take().ok_or("Failed to get stdout handle")?;
         let stderr = child.stderr.take();
+        #[cfg(unix)]
+        if let Some(ref stderr) = stderr {
+            // Lets `execute` collect the errors of each command without blocking.
+            set_nonblocking(stderr, true)?;
+        }
 
-        Ok(Sqlite3Process {
+        let mut process = Sqlite3Process {
             child: Some(child),
             stdin: Some(stdin),
-            stdout: Some(BufReader::new(stdout)),
+            stdout: Some(spawn_line_reader(stdout)),
+            pending: None,
             stderr,
             db_path: db_path.to_path_buf(),
-        })
+            suspended: false,
+            stderr_log: String::new(),
+            last_stderr: String::new(),
+            grace_period,
+            on_hang,
+            transcript: Transcript::new(db_path.display().to_string()),
+            dump_transcript_on_panic,
+            _home: home,
+        };
+
+        // Also discards anything startup scripts printed before the first command.
+        process.execute(PINNED_OUTPUT_SETTINGS)?;
+        Ok(process)
     }
 
     /// Enables WAL (Write-Ahead Logging) journal mode.
@@ -115,64 +245,597 @@ impl Sqlite3Process {
     /// or the output marker is not found.
     ///
     pub fn execute(&mut self, sql: &str) -> Result<String, String> {
+        self.begin_command(sql)?;
+        self.complete_command(None)
+            .unwrap_or_else(|| Err("No command is pending".to_string()))
+    }
+
+    /// Returns every command this process has run, with output and timings.
+    pub fn transcript(&self) -> &Transcript {
+        &self.transcript
+    }
+
+    /// Returns the transcript `Drop` prints, if any, given whether the thread
+    /// is panicking.
+    fn transcript_to_dump(&self, panicking: bool) -> Option<&Transcript> {
+        (self.dump_transcript_on_panic && panicking).then_some(&self.transcript)
+    }
+
+    /// Sends `sql` followed by the end marker without waiting for the output.
+    ///
+    /// The output of a command that was never completed is read and discarded
+    /// first, so it cannot be mistaken for the output of `sql`.
+    fn begin_command(&mut self, sql: &str) -> Result<(), String> {
+        if self.pending.is_some() {
+            let _ = self.complete_command(None);
+        }
+        self.last_stderr.clear();
+
+        let start = self.transcript.start();
+        if let Err(e) = self.write_command(sql) {
+            self.transcript.record(start, sql, &Err(e.clone()), "");
+            return Err(e);
+        }
+        self.pending = Some(PendingCommand {
+            sql: sql.to_string(),
+            start,
+            output: String::new(),
+        });
+        Ok(())
+    }
+
+    /// Writes `sql` and the end marker to the process's stdin.
+    fn write_command(&mut self, sql: &str) -> Result<(), String> {
         let stdin = self.stdin.as_mut().ok_or("stdin is not available")?;
-        let stdout = self.stdout.as_mut().ok_or("stdout is not available")?;
 
         writeln!(stdin, "{sql}").map_err(|e| format!("Failed to write: {e}"))?;
         stdin.flush().map_err(|e| format!("Failed to flush: {e}"))?;
 
-        writeln!(stdin, "SELECT 'MARKER_END';")
-            .map_err(|e| format!("Failed to write marker: {e}"))?;
+        writeln!(stdin, "{MARKER_QUERY}").map_err(|e| format!("Failed to write marker: {e}"))?;
         stdin.flush().map_err(|e| format!("Failed to flush: {e}"))?;
+        Ok(())
+    }
 
-        let mut output = String::new();
-        let mut found_marker = false;
-
-        loop {
-            let mut line = String::new();
-            match stdout.read_line(&mut line) {
-                Ok(0) => break,
-                Ok(_) => {
-                    if line.contains("MARKER_END") {
-                        found_marker = true;
-                        break;
+    /// Reads the pending command's output until the end marker arrives.
+    ///
+    /// Returns `None` if there is no pending command, or if `deadline` passes
+    /// before the output is complete; the command then stays pending.
+    fn complete_command(&mut self, deadline: Option<StdInstant>) -> Option<Result<String, String>> {
+        let pending = self.pending.as_mut()?;
+        let result = match self.stdout {
+            Some(ref stdout) => loop {
+                let line = match deadline {
+                    None => stdout.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                    Some(deadline) => {
+                        stdout.recv_timeout(deadline.saturating_duration_since(StdInstant::now()))
                     }
-                    if !line.contains("SELECT 'MARKER_END'") {
-                        output.push_str(&line);
+                };
+                match line {
+                    Ok(Ok(line)) => {
+                        if line.contains("MARKER_END") {
+                            break Ok(());
+                        }
+                        if !line.contains("SELECT 'MARKER_END'") {
+                            pending.output.push_str(&line);
+                        }
+                    }
+                    Ok(Err(e)) => break Err(format!("Failed to read: {e}")),
+                    Err(RecvTimeoutError::Timeout) => return None,
+                    Err(RecvTimeoutError::Disconnected) => {
+                        break Err("Failed to read complete output".to_string())
                     }
                 }
-                Err(e) => return Err(format!("Failed to read: {e}")),
+            },
+            None => Err("stdout is not available".to_string()),
+        };
+
+        let pending = self.pending.take()?;
+        let result = result.map(|()| pending.output);
+        if result.is_ok() {
+            self.last_stderr = self.drain_stderr();
+            self.stderr_log.push_str(&self.last_stderr);
+        }
+        self.transcript
+            .record(pending.start, &pending.sql, &result, &self.last_stderr);
+        Some(result)
+    }
+
+    /// Executes a SQL statement and fails if it wrote anything to stderr.
+    ///
+    /// The sqlite3 shell reports errors on stderr and keeps going, so
+    /// [`execute`](Self::execute) succeeds even when the statement failed.
+    /// This method turns such errors into an `Err`. Errors are only detected on
+    /// Unix; elsewhere this behaves like `execute`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `execute` fails or the statement reported an error.
+    ///
+    pub fn execute_checked(&mut self, sql: &str) -> Result<String, String> {
+        let output = self.execute(sql)?;
+        if self.last_stderr.is_empty() {
+            Ok(output)
+        } else {
+            Err(self.last_stderr.trim_end().to_string())
+        }
+    }
+
+    /// Returns what the last [`execute`](Self::execute) call wrote to stderr.
+    ///
+    /// Always empty on platforms other than Unix.
+    pub fn last_stderr(&self) -> &str {
+        &self.last_stderr
+    }
+
+    /// Reads whatever is currently buffered on the child's stderr.
+    ///
+    /// Every byte written before the end marker on stdout is already in the
+    /// pipe, so this sees all errors of the command that just finished.
+    #[cfg(unix)]
+    fn drain_stderr(&mut self) -> String {
+        self.stderr.as_mut().map(drain_pipe).unwrap_or_default()
+    }
+
+    #[cfg(not(unix))]
+    fn drain_stderr(&mut self) -> String {
+        String::new()
+    }
+
+    /// Returns the result of `sqlite_version()` in the sqlite3 process.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query fails.
+    ///
+    pub fn sqlite_version(&mut self) -> Result<String, String> {
+        Ok(self.execute("SELECT sqlite_version();")?.trim().to_string())
+    }
+
+    /// Returns the rows of `PRAGMA compile_options` in the sqlite3 process.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query fails.
+    ///
+    pub fn compile_options(&mut self) -> Result<Vec<String>, String> {
+        Ok(self
+            .execute("PRAGMA compile_options;")?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// Returns the version and compile options of the sqlite3 process.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either query fails.
+    ///
+    pub fn info(&mut self) -> Result<Sqlite3Info, String> {
+        Ok(Sqlite3Info {
+            version: self.sqlite_version()?,
+            compile_options: self.compile_options()?,
+        })
+    }
+
+    /// Kills the sqlite3 process with SIGKILL, simulating a crash.
+    ///
+    /// The process gets no chance to commit, roll back or checkpoint, so an
+    /// open transaction leaves a hot journal and un-checkpointed frames stay in
+    /// the WAL. Dropping the killed process does not wait for it to exit.
+    ///
+    /// # Returns
+    ///
+    /// Returns the journal, WAL and shared-memory files left next to the database.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the process cannot be killed or reaped.
+    ///
+    pub fn kill_now(mut self) -> Result<LeftoverFiles, String> {
+        // Kill before closing stdin: on EOF sqlite3 would exit cleanly, rolling
+        // back or checkpointing before the signal arrives.
+        if let Some(mut child) = self.child.take() {
+            child
+                .kill()
+                .map_err(|e| format!("Failed to kill sqlite3: {e}"))?;
+            child
+                .wait()
+                .map_err(|e| format!("Failed to reap sqlite3: {e}"))?;
+        }
+
+        // Release the pipes so `Drop` neither sends `.exit` nor waits.
+        self.stdin.take();
+        self.stdout.take();
+        self.stderr.take();
+
+        Ok(LeftoverFiles::for_db(&self.db_path))
+    }
+
+    /// Executes a SQL statement, then kills the process with SIGKILL.
+    ///
+    /// This is [`execute`](Self::execute) followed by [`kill_now`](Self::kill_now):
+    /// the statement runs to completion and the process dies immediately afterwards,
+    /// for example with a transaction still open.
+    ///
+    /// # Arguments
+    ///
+    /// * `sql` - The last SQL statement to execute before the crash
+    ///
+    /// # Returns
+    ///
+    /// Returns the journal, WAL and shared-memory files left next to the database.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the statement cannot be executed or the process
+    /// cannot be killed.
+    ///
+    pub fn crash_after(mut self, sql: &str) -> Result<LeftoverFiles, String> {
+        self.execute(sql)?;
+        self.kill_now()
+    }
+
+    /// Sends a SQL statement and kills the process with SIGKILL `delay` later,
+    /// while the statement may still be running.
+    ///
+    /// Unlike [`crash_after`](Self::crash_after) this does not wait for the
+    /// statement to finish, so it can crash a process in the middle of a long
+    /// write or a checkpoint such as `PRAGMA wal_checkpoint(TRUNCATE);`. If the
+    /// statement finishes within `delay`, the process is killed afterwards.
+    ///
+    /// # Arguments
+    ///
+    /// * `sql` - The SQL statement to interrupt
+    /// * `delay` - How long to let the statement run before the kill
+    ///
+    /// # Returns
+    ///
+    /// Returns the journal, WAL and shared-memory files left next to the database.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the statement cannot be sent or the process cannot
+    /// be killed.
+    ///
+    pub fn crash_during(mut self, sql: &str, delay: Duration) -> Result<LeftoverFiles, String> {
+        self.begin_command(sql)?;
+        thread::sleep(delay);
+        self.kill_now()
+    }
+
+    /// Stops the sqlite3 process with SIGSTOP and waits until it has stopped.
+    ///
+    /// The process keeps every lock it currently holds until it is resumed, which
+    /// makes it possible to freeze a lock holder at a precise point. Do not call
+    /// [`execute`](Self::execute) while suspended; it would block forever.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the process has already exited or the signal cannot be sent.
+    ///
+    #[cfg(unix)]
+    pub fn suspend(&mut self) -> Result<(), String> {
+        self.signal(libc::SIGSTOP)?;
+        self.suspended = true;
+        self.wait_until_stopped()
+    }
+
+    /// Continues a process stopped by [`suspend`](Self::suspend) with SIGCONT.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the process has already exited or the signal cannot be sent.
+    ///
+    #[cfg(unix)]
+    pub fn resume(&mut self) -> Result<(), String> {
+        self.signal(libc::SIGCONT)?;
+        self.suspended = false;
+        Ok(())
+    }
+
+    /// Suspends the process until the returned guard is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the process cannot be suspended.
+    ///
+    #[cfg(unix)]
+    pub fn suspend_guard(&mut self) -> Result<SuspendGuard<'_>, String> {
+        self.suspend()?;
+        Ok(SuspendGuard { process: self })
+    }
+
+    /// Returns `true` if the process is currently stopped by [`suspend`](Self::suspend).
+    pub fn is_suspended(&self) -> bool {
+        self.suspended
+    }
+
+    /// Sends a signal to the sqlite3 process.
+    #[cfg(unix)]
+    fn signal(&self, signal: libc::c_int) -> Result<(), String> {
+        let child = self.child.as_ref().ok_or("sqlite3 process has exited")?;
+        let pid = libc::pid_t::try_from(child.id()).map_err(|e| format!("Invalid pid: {e}"))?;
+        // SAFETY: `kill` has no memory-safety preconditions; the child has not
+        // been reaped yet, so its pid cannot have been reused.
+        if unsafe { libc::kill(pid, signal) } == 0 {
+            Ok(())
+        } else {
+            Err(format!(
+                "Failed to signal sqlite3: {}",
+                std::io::Error::last_os_error()
+            ))
+        }
+    }
+
+    /// Blocks until the sqlite3 process reports that it has stopped.
+    #[cfg(unix)]
+    fn wait_until_stopped(&self) -> Result<(), String> {
+        let child = self.child.as_ref().ok_or("sqlite3 process has exited")?;
+        let pid = libc::pid_t::try_from(child.id()).map_err(|e| format!("Invalid pid: {e}"))?;
+        let mut status = 0;
+        // SAFETY: `status` is a valid out-pointer. WUNTRACED only consumes the
+        // stop notification, so `Child` can still reap the process later.
+        let result = unsafe { libc::waitpid(pid, &mut status, libc::WUNTRACED) };
+        if result == -1 {
+            return Err(format!(
+                "Failed to wait for sqlite3 to stop: {}",
+                std::io::Error::last_os_error()
+            ));
+        }
+        if libc::WIFSTOPPED(status) {
+            Ok(())
+        } else {
+            Err("sqlite3 process exited instead of stopping".to_string())
+        }
+    }
+
+    /// Asks the sqlite3 process to exit and waits for it.
+    ///
+    /// Unlike dropping the process, this reports how it exited instead of only
+    /// logging failures. A process that does not exit within the grace period
+    /// (see [`Sqlite3ProcessBuilder::grace_period`]) is killed.
+    ///
+    /// # Returns
+    ///
+    /// Returns the exit status, all stderr output and how long shutdown took.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the process did not exit within the grace period or
+    /// could not be waited for.
+    ///
+    pub fn close(mut self) -> Result<ExitReport, String> {
+        self.shutdown().map_err(|e| match e {
+            ShutdownError::TimedOut { stderr } => format!(
+                "sqlite3 process for {} did not exit within {:?} and was killed; stderr: {stderr}",
+                self.db_path.display(),
+                self.grace_period
+            ),
+            ShutdownError::Wait(e) => format!("Error waiting for sqlite3: {e}"),
+        })
+    }
+
+    /// Sends `.exit` and waits up to the grace period for the child to exit.
+    ///
+    /// Leaves nothing for a later call to do, so `Drop` after `close` is a no-op.
+    fn shutdown(&mut self) -> Result<ExitReport, ShutdownError> {
+        // A stopped child would never read `.exit`, so continue it first.
+        #[cfg(unix)]
+        if self.suspended {
+            if let Err(e) = self.resume() {
+                eprintln!("Failed to resume sqlite3 process: {e}");
             }
         }
 
-        if !found_marker {
-            return Err("Failed to read complete output".to_string());
+        if let Some(mut stdin) = self.stdin.take() {
+            let _ = writeln!(stdin, ".exit");
+            let _ = stdin.flush();
         }
+        self.stdout.take();
 
-        Ok(output)
+        // Read stderr on another thread so a hung child cannot block the timeout.
+        let stderr_reader = self.stderr.take().map(|mut stderr| {
+            #[cfg(unix)]
+            let _ = set_nonblocking(&stderr, false);
+            thread::spawn(move || {
+                let mut buffer = Vec::new();
+                let _ = stderr.read_to_end(&mut buffer);
+                String::from_utf8_lossy(&buffer).to_string()
+            })
+        });
+        let earlier_stderr = std::mem::take(&mut self.stderr_log);
+        let collect_stderr = |reader: Option<thread::JoinHandle<String>>| {
+            let remaining = reader
+                .and_then(|reader| reader.join().ok())
+                .unwrap_or_default();
+            earlier_stderr + &remaining
+        };
+
+        let Some(mut child) = self.child.take() else {
+            return Err(ShutdownError::Wait(
+                "sqlite3 process has exited".to_string(),
+            ));
+        };
+        // The grace period follows the mock clock in tests, which other tests
+        // may move backwards, so it is measured saturating. The reported
+        // duration always uses the real clock.
+        let start = Instant::now();
+        let measured_from = StdInstant::now();
+        loop {
+            match child.try_wait() {
+                Ok(Some(status)) => {
+                    return Ok(ExitReport {
+                        status,
+                        stderr: collect_stderr(stderr_reader),
+                        duration: measured_from.elapsed(),
+                    });
+                }
+                Ok(None) => {
+                    let waited = Instant::now().saturating_duration_since(start);
+                    if is_timed_out(waited, self.grace_period) {
+                        let _ = child.kill();
+                        let _ = child.wait();
+                        return Err(ShutdownError::TimedOut {
+                            stderr: collect_stderr(stderr_reader),
+                        });
+                    }
+                    thread::sleep(Duration::from_millis(100));
+                }
+                Err(e) => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(ShutdownError::Wait(e.to_string()));
+                }
+            }
+        }
     }
 
     /// Creates a test table with dummy data.
     ///
     /// Creates a `test` table with `id` and `value` columns, then inserts
-    /// 999 rows of test data.
+    /// 999 rows of test data. See [`create_dummy_data_with`](Self::create_dummy_data_with)
+    /// for other tables, row counts and values.
     ///
     /// # Panics
     ///
     /// Panics if any SQL command fails.
     pub fn create_dummy_data(&mut self) {
-        self.execute("CREATE TABLE test (id INTEGER PRIMARY KEY, value TEXT);")
-            .unwrap();
+        self.create_dummy_data_with(&DummyData::default()).unwrap();
+    }
+}
 
-        for number in 1..1000 {
-            self.execute(&format!(
-                "INSERT INTO test (value) VALUES ('Hello, World! {number}');"
-            ))
-            .unwrap();
+/// A command that was sent to the process but whose output is not complete yet.
+struct PendingCommand {
+    sql: String,
+    start: (Duration, StdInstant),
+    output: String,
+}
+
+/// Reads stdout on a background thread so callers can wait with a timeout.
+///
+/// The channel disconnects when the process closes its stdout.
+fn spawn_line_reader(stdout: ChildStdout) -> Receiver<io::Result<String>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut stdout = BufReader::new(stdout);
+        loop {
+            let mut line = String::new();
+            let result = match stdout.read_line(&mut line) {
+                Ok(0) => break,
+                Ok(_) => Ok(line),
+                Err(e) => Err(e),
+            };
+            let failed = result.is_err();
+            if sender.send(result).is_err() || failed {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Reads everything currently buffered in a non-blocking pipe.
+#[cfg(unix)]
+fn drain_pipe(pipe: &mut impl Read) -> String {
+    let mut drained = Vec::new();
+    let mut buffer = [0; 4096];
+    loop {
+        match pipe.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => drained.extend_from_slice(&buffer[..n]),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(_) => break,
+        }
+    }
+    String::from_utf8_lossy(&drained).into_owned()
+}
+
+/// Switches a child pipe between blocking and non-blocking reads.
+#[cfg(unix)]
+fn set_nonblocking(pipe: &impl std::os::fd::AsRawFd, nonblocking: bool) -> Result<(), String> {
+    let fd = pipe.as_raw_fd();
+    // SAFETY: `fd` is an open descriptor owned by `pipe` for the duration of the calls.
+    let result = unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        if flags == -1 {
+            -1
+        } else if nonblocking {
+            libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK)
+        } else {
+            libc::fcntl(fd, libc::F_SETFL, flags & !libc::O_NONBLOCK)
+        }
+    };
+    if result == -1 {
+        Err(format!(
+            "Failed to configure pipe: {}",
+            std::io::Error::last_os_error()
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Keeps a [`Sqlite3Process`] suspended and resumes it when dropped.
+///
+/// Created by [`Sqlite3Process::suspend_guard`].
+#[cfg(unix)]
+pub struct SuspendGuard<'a> {
+    process: &'a mut Sqlite3Process,
+}
+
+#[cfg(unix)]
+impl Drop for SuspendGuard<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.process.resume() {
+            eprintln!("Failed to resume sqlite3 process: {e}");
         }
     }
 }
 
+/// What dropping a [`Sqlite3Process`] does when the child does not exit within
+/// its grace period. The child is killed in every case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum HangBehavior {
+    /// Kill the child without any output.
+    KillQuietly,
+    /// Kill the child and print a diagnostic to stderr.
+    Warn,
+    /// Kill the child, print a diagnostic and panic.
+    ///
+    /// Falls back to [`Warn`](Self::Warn) if the thread is already panicking.
+    #[default]
+    Panic,
+}
+
+/// How a sqlite3 process exited, as returned by [`Sqlite3Process::close`].
+#[derive(Debug, Clone)]
+pub struct ExitReport {
+    /// The exit status of the process.
+    pub status: ExitStatus,
+    /// Everything the process wrote to stderr.
+    pub stderr: String,
+    /// Time from sending `.exit` until the process exited.
+    pub duration: Duration,
+}
+
+impl ExitReport {
+    /// Returns `true` if the process exited with status zero.
+    pub fn success(&self) -> bool {
+        self.status.success()
+    }
+}
+
+/// Why [`Sqlite3Process::shutdown`] could not collect an exit status.
+enum ShutdownError {
+    /// The child did not exit within the grace period and was killed.
+    TimedOut { stderr: String },
+    /// Waiting for the child failed; it was killed.
+    Wait(String),
+}
+
 /// Checks if elapsed time has exceeded the timeout.
 fn is_timed_out(elapsed: Duration, timeout: Duration) -> bool {
     elapsed > timeout
@@ -185,46 +848,44 @@ fn should_log_error(success: bool, stderr_empty: bool) -> bool {
 
 impl Drop for Sqlite3Process {
     fn drop(&mut self) {
-        if let Some(ref mut stdin) = self.stdin {
-            let _ = writeln!(stdin, ".exit");
-            let _ = stdin.flush();
+        if let Some(transcript) = self.transcript_to_dump(thread::panicking()) {
+            eprintln!("{transcript}");
         }
 
-        let mut stderr_output = String::new();
-        if let Some(mut stderr) = self.stderr.take() {
-            let mut buffer = Vec::new();
-            let _ = stderr.read_to_end(&mut buffer);
-            stderr_output = String::from_utf8_lossy(&buffer).to_string();
+        if self.child.is_none() {
+            // Already closed or killed.
+            return;
         }
 
-        let start = Instant::now();
-        let timeout = Duration::from_secs(60);
-        if let Some(ref mut child) = self.child {
-            loop {
-                match child.try_wait() {
-                    Ok(Some(status)) => {
-                        if should_log_error(status.success(), stderr_output.is_empty()) {
-                            eprintln!("sqlite3 process exited with error: {status}");
-                            eprintln!("stderr: {stderr_output}");
-                        }
-                        return;
-                    }
-                    Ok(None) => {
-                        if is_timed_out(start.elapsed(), timeout) {
-                            eprintln!("sqlite3 process failed to exit within 60 seconds!");
-                            eprintln!("Database path: {}", self.db_path.display());
-                            let _ = child.kill();
-                            panic!("sqlite3 process hung for {}", self.db_path.display());
-                        }
-                        thread::sleep(Duration::from_millis(100));
-                    }
-                    Err(e) => {
-                        eprintln!("Error waiting for sqlite3: {e}");
-                        let _ = child.kill();
-                        return;
+        match self.shutdown() {
+            Ok(report) => {
+                if should_log_error(report.success(), report.stderr.is_empty()) {
+                    eprintln!("sqlite3 process exited with error: {}", report.status);
+                    eprintln!("stderr: {}", report.stderr);
+                }
+            }
+            Err(ShutdownError::TimedOut { stderr }) => {
+                let behavior = if thread::panicking() {
+                    // A second panic would abort the test binary and hide the first one.
+                    self.on_hang.min(HangBehavior::Warn)
+                } else {
+                    self.on_hang
+                };
+                if behavior >= HangBehavior::Warn {
+                    eprintln!(
+                        "sqlite3 process failed to exit within {:?}!",
+                        self.grace_period
+                    );
+                    eprintln!("Database path: {}", self.db_path.display());
+                    if !stderr.is_empty() {
+                        eprintln!("stderr: {stderr}");
                     }
                 }
+                if behavior == HangBehavior::Panic {
+                    panic!("sqlite3 process hung for {}", self.db_path.display());
+                }
             }
+            Err(ShutdownError::Wait(e)) => eprintln!("Error waiting for sqlite3: {e}"),
         }
     }
 }
@@ -327,6 +988,256 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_execute_without_trailing_semicolon() {
+        let (mut process, _dir) = new_test_process();
+
+        assert_eq!(process.execute("SELECT 1").unwrap(), "1\n");
+        assert_eq!(process.execute("SELECT 2;").unwrap(), "2\n");
+    }
+
+    #[test]
+    fn test_execute_checked_reports_errors() {
+        let (mut process, _dir) = new_test_process();
+
+        assert_eq!(process.execute_checked("SELECT 1;").unwrap(), "1\n");
+        assert!(process.last_stderr().is_empty());
+
+        let error = process
+            .execute_checked("SELECT * FROM missing;")
+            .unwrap_err();
+        assert!(error.contains("no such table: missing"), "got: {error}");
+
+        // Errors are attributed to the command that caused them only.
+        assert_eq!(process.execute_checked("SELECT 2;").unwrap(), "2\n");
+    }
+
+    #[test]
+    fn test_crash_after_leaves_hot_journal() {
+        let (mut process, dir) = new_test_process();
+        process.execute("CREATE TABLE t (x INTEGER);").unwrap();
+
+        let leftovers = process
+            .crash_after("BEGIN; INSERT INTO t VALUES (1);")
+            .unwrap();
+
+        assert_eq!(leftovers.journal, Some(dir.path().join("test.db-journal")));
+        assert!(leftovers.wal.is_none());
+
+        // The hot journal is rolled back by the next connection.
+        let conn = rusqlite::Connection::open(dir.path().join("test.db")).unwrap();
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM t", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn test_crash_during_interrupts_statement() {
+        let (mut process, dir) = new_test_process();
+        process.execute("CREATE TABLE t (x INTEGER);").unwrap();
+
+        let leftovers = process
+            .crash_during(
+                "BEGIN; WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n) \
+                 INSERT INTO t SELECT i FROM n LIMIT 100000000;",
+                Duration::from_millis(300),
+            )
+            .unwrap();
+        assert_eq!(leftovers.journal, Some(dir.path().join("test.db-journal")));
+
+        let conn = rusqlite::Connection::open(dir.path().join("test.db")).unwrap();
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM t", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn test_kill_now_leaves_wal() {
+        let (mut process, dir) = new_test_process();
+        process.enable_wal_mode();
+        process.disable_wal_checkpointing();
+        process.create_dummy_data();
+
+        let leftovers = process.kill_now().unwrap();
+
+        assert_eq!(leftovers.wal, Some(dir.path().join("test.db-wal")));
+        assert_eq!(leftovers.shm, Some(dir.path().join("test.db-shm")));
+        assert!(leftovers.journal.is_none());
+    }
+
+    #[cfg(target_os = "linux")]
+    fn process_state(process: &Sqlite3Process) -> char {
+        let pid = process.child.as_ref().unwrap().id();
+        let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).unwrap();
+        // The state follows the parenthesised command name.
+        let after_comm = &stat[stat.rfind(')').unwrap() + 2..];
+        after_comm.chars().next().unwrap()
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_suspend_guard_freezes_lock_holder() {
+        let (mut process, dir) = new_test_process();
+        process.execute("CREATE TABLE t (x INTEGER);").unwrap();
+        process
+            .execute("BEGIN IMMEDIATE; INSERT INTO t VALUES (1);")
+            .unwrap();
+
+        {
+            let guard = process.suspend_guard().unwrap();
+            assert!(guard.process.is_suspended());
+            assert_eq!(process_state(guard.process), 'T');
+
+            // The frozen process still holds the write lock.
+            let conn = rusqlite::Connection::open(dir.path().join("test.db")).unwrap();
+            conn.busy_timeout(Duration::ZERO).unwrap();
+            let result = conn.execute("INSERT INTO t VALUES (2)", []);
+            assert!(
+                result.is_err(),
+                "write should be blocked by the frozen holder"
+            );
+        }
+
+        assert!(!process.is_suspended());
+        process.execute("COMMIT;").unwrap();
+        let count = process.execute("SELECT COUNT(*) FROM t;").unwrap();
+        assert_eq!(count.trim(), "1");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_drop_resumes_suspended_process() {
+        let (mut process, _dir) = new_test_process();
+        process.execute("SELECT 1;").unwrap();
+        process.suspend().unwrap();
+
+        // Drop must continue the child so it can read `.exit` and terminate.
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            drop(process);
+            tx.send(()).unwrap();
+        });
+        rx.recv_timeout(Duration::from_secs(5))
+            .expect("dropping a suspended process should not hang");
+    }
+
+    /// Spawns a process that ignores `.exit` because its stdin is held elsewhere.
+    fn new_hung_process(
+        dir: &tempfile::TempDir,
+        on_hang: HangBehavior,
+    ) -> (Sqlite3Process, Option<ChildStdin>) {
+        let mut process = Sqlite3Process::builder()
+            .grace_period(Duration::from_secs(1))
+            .on_hang(on_hang)
+            .spawn(&dir.path().join("test.db"))
+            .unwrap();
+        let stdin = process.stdin.take();
+        (process, stdin)
+    }
+
+    /// Advances the mock clock past a one-second grace period shortly after the call.
+    fn advance_clock_past_grace_period() -> std::thread::JoinHandle<()> {
+        std::thread::spawn(|| {
+            std::thread::sleep(Duration::from_millis(50));
+            MockClock::advance(Duration::from_millis(1_001));
+        })
+    }
+
+    #[test]
+    fn test_close_reports_exit_status_and_stderr() {
+        let (mut process, _dir) = new_test_process();
+        process.execute("SELECT 1;").unwrap();
+        let report = process.close().unwrap();
+        assert!(report.success(), "unexpected status: {}", report.status);
+        assert!(report.stderr.is_empty());
+
+        // sqlite3 exits non-zero once any statement has failed.
+        let (mut process, _dir) = new_test_process();
+        process.execute("SELECT * FROM missing_table;").unwrap();
+        let report = process.close().unwrap();
+        assert!(!report.success());
+        assert!(
+            report.stderr.contains("missing_table"),
+            "stderr should contain the error, got: {}",
+            report.stderr
+        );
+    }
+
+    #[test]
+    fn test_close_kills_hung_process() {
+        let dir = tempdir().unwrap();
+        let (process, _stdin) = new_hung_process(&dir, HangBehavior::Panic);
+
+        let advance_handle = advance_clock_past_grace_period();
+        let result = process.close();
+        advance_handle.join().unwrap();
+
+        let error = result.unwrap_err();
+        assert!(error.contains("did not exit"), "unexpected error: {error}");
+    }
+
+    #[test]
+    fn test_drop_does_not_panic_while_panicking() {
+        let dir = tempdir().unwrap();
+        let (process, stdin) = new_hung_process(&dir, HangBehavior::Panic);
+
+        // Locals drop in reverse order, so stdin stays open until the process
+        // has been dropped and `Drop` has to wait out the grace period.
+        let handle = std::thread::spawn(move || {
+            let _stdin = stdin;
+            let _process = process;
+            let _advance_handle = advance_clock_past_grace_period();
+            panic!("original failure");
+        });
+
+        // A second panic from `Drop` would abort instead of returning this payload.
+        let payload = handle.join().unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"original failure"));
+    }
+
+    #[test]
+    fn test_dump_transcript_on_panic() {
+        let (process, _dir) = new_test_process();
+        assert!(process.transcript_to_dump(true).is_none());
+
+        let dir = tempdir().unwrap();
+        let mut process = Sqlite3Process::builder()
+            .dump_transcript_on_panic(true)
+            .spawn(&dir.path().join("test.db"))
+            .unwrap();
+        process.execute("SELECT 'dumped';").unwrap();
+        assert!(process.transcript_to_dump(false).is_none());
+        let dumped = process.transcript_to_dump(true).unwrap().to_string();
+        assert!(dumped.contains("SELECT 'dumped';"), "{dumped}");
+
+        // Dropping it while panicking prints the transcript and exits cleanly.
+        let handle = std::thread::spawn(move || {
+            let _process = process;
+            panic!("assertion failed");
+        });
+        let payload = handle.join().unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"assertion failed"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_drop_kills_hung_process_without_panicking() {
+        for on_hang in [HangBehavior::KillQuietly, HangBehavior::Warn] {
+            let dir = tempdir().unwrap();
+            let (process, _stdin) = new_hung_process(&dir, on_hang);
+            let pid = libc::pid_t::try_from(process.child.as_ref().unwrap().id()).unwrap();
+
+            let advance_handle = advance_clock_past_grace_period();
+            drop(process);
+            advance_handle.join().unwrap();
+
+            // The child was killed and reaped, so its pid no longer exists.
+            assert_eq!(unsafe { libc::kill(pid, 0) }, -1, "{on_hang:?}");
+        }
+    }
+
     #[test]
     #[should_panic(expected = "sqlite3 process hung")]
     fn test_drop_timeout_panics() {
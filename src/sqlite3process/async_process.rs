@@ -0,0 +1,556 @@
+//! An async sqlite3 process for tests running on tokio.
+
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout};
+use tokio::runtime::Handle;
+use tokio::time::{timeout, Instant};
+
+use super::builder::IsolatedHome;
+#[cfg(unix)]
+use super::{drain_pipe, set_nonblocking};
+use super::{
+    ExitReport, HangBehavior, ShutdownError, Sqlite3ProcessBuilder, MARKER_QUERY,
+    PINNED_OUTPUT_SETTINGS,
+};
+
+/// Async counterpart of [`Sqlite3Process`](super::Sqlite3Process), built on
+/// `tokio::process`.
+///
+/// Requires the `tokio` feature. Spawn it with [`AsyncSqlite3Process::new`] or
+/// [`Sqlite3ProcessBuilder::spawn_async`]; the builder's isolation, arguments,
+/// grace period and hang behavior apply as for the blocking process.
+///
+/// Dropping the process never blocks the runtime: the child's stdin is closed
+/// and a background task waits for it to exit, killing it after the grace
+/// period. Since that task cannot fail the test, [`HangBehavior::Panic`] only
+/// warns there; use [`close`](Self::close) to observe how the process exited.
+///
+/// # Example
+///
+/// ```rust
+/// use sqlite_test_utils::AsyncSqlite3Process;
+///
+/// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+/// let dir = tempfile::tempdir().unwrap();
+/// let mut process = AsyncSqlite3Process::new(&dir.path().join("test.db"))
+///     .await
+///     .unwrap();
+///
+/// assert_eq!(process.execute("SELECT 1 + 1;").await.unwrap(), "2\n");
+/// assert!(process.close().await.unwrap().success());
+/// # });
+/// ```
+pub struct AsyncSqlite3Process {
+    child: Option<Child>,
+    stdin: Option<ChildStdin>,
+    stdout: Option<BufReader<ChildStdout>>,
+    #[cfg(unix)]
+    stderr: Option<std::fs::File>,
+    pending: Option<PendingOutput>,
+    rollback_pending: bool,
+    db_path: PathBuf,
+    stderr_log: String,
+    last_stderr: String,
+    grace_period: Duration,
+    on_hang: HangBehavior,
+    // Declared last so it is removed only after the child has been reaped.
+    _home: Option<IsolatedHome>,
+}
+
+/// Output read so far for a command whose end marker has not arrived yet.
+///
+/// Kept on the process rather than in the future so an `execute` that is
+/// cancelled, e.g. by a timeout, can be finished by the next command.
+struct PendingOutput {
+    output: String,
+    line: Vec<u8>,
+}
+
+/// The database lock taken by [`AsyncSqlite3Process::lock`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    /// A read transaction (`BEGIN` followed by a read), which blocks writers
+    /// from committing in rollback-journal modes.
+    Shared,
+    /// A write transaction (`BEGIN IMMEDIATE`), which blocks other writers.
+    Reserved,
+    /// An exclusive transaction (`BEGIN EXCLUSIVE`), which blocks readers in
+    /// rollback-journal modes.
+    Exclusive,
+}
+
+impl LockMode {
+    /// Returns the statements that open a transaction holding this lock.
+    fn begin_sql(self) -> &'static str {
+        match self {
+            LockMode::Shared => "BEGIN; SELECT COUNT(*) FROM sqlite_schema;",
+            LockMode::Reserved => "BEGIN IMMEDIATE;",
+            LockMode::Exclusive => "BEGIN EXCLUSIVE;",
+        }
+    }
+}
+
+impl AsyncSqlite3Process {
+    /// Creates a new `AsyncSqlite3Process` connected to the specified database.
+    ///
+    /// # Arguments
+    ///
+    /// * `db_path` - Path to the SQLite database file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sqlite3 process cannot be spawned or if
+    /// the I/O handles cannot be obtained.
+    ///
+    pub async fn new(db_path: &Path) -> Result<Self, String> {
+        Sqlite3ProcessBuilder::new().spawn_async(db_path).await
+    }
+
+    /// Spawns `command` with piped I/O and wraps it.
+    pub(super) async fn spawn(
+        command: Command,
+        db_path: &Path,
+        home: Option<IsolatedHome>,
+        grace_period: Duration,
+        on_hang: HangBehavior,
+    ) -> Result<Self, String> {
+        let mut command = tokio::process::Command::from(command);
+        command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(if cfg!(unix) {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .kill_on_drop(true);
+        let mut child = command
+            .spawn()
+            .map_err(|e| format!("Failed to spawn sqlite3: {e}"))?;
+
+        let stdin = child.stdin.take().ok_or("Failed to get stdin handle")?;
+        let stdout = child.stdout.take().ok_or("Failed to get stdout handle")?;
+        // Read as a plain non-blocking pipe, so errors can be drained after
+        // each command exactly like the blocking process does.
+        #[cfg(unix)]
+        let stderr = match child.stderr.take() {
+            Some(stderr) => {
+                let stderr = stderr
+                    .into_owned_fd()
+                    .map(std::fs::File::from)
+                    .map_err(|e| format!("Failed to get stderr handle: {e}"))?;
+                set_nonblocking(&stderr, true)?;
+                Some(stderr)
+            }
+            None => None,
+        };
+
+        let mut process = AsyncSqlite3Process {
+            child: Some(child),
+            stdin: Some(stdin),
+            stdout: Some(BufReader::new(stdout)),
+            #[cfg(unix)]
+            stderr,
+            pending: None,
+            rollback_pending: false,
+            db_path: db_path.to_path_buf(),
+            stderr_log: String::new(),
+            last_stderr: String::new(),
+            grace_period,
+            on_hang,
+            _home: home,
+        };
+
+        // Also discards anything startup scripts printed before the first command.
+        process.execute(PINNED_OUTPUT_SETTINGS).await?;
+        Ok(process)
+    }
+
+    /// Executes a SQL statement and returns its output.
+    ///
+    /// The returned future may be cancelled while it waits for output; the
+    /// next command then waits for the abandoned one first and discards its
+    /// output.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing to stdin fails, reading from stdout fails,
+    /// or the output marker is not found.
+    ///
+    pub async fn execute(&mut self, sql: &str) -> Result<String, String> {
+        self.begin_command(sql).await?;
+        self.complete_command().await
+    }
+
+    /// Executes a SQL statement, giving up if it does not finish within `limit`.
+    ///
+    /// A statement that times out keeps running in the process, e.g. while it
+    /// waits for a lock; the next command waits for it to finish first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `execute` fails or the statement does not finish in time.
+    ///
+    pub async fn execute_timeout(&mut self, sql: &str, limit: Duration) -> Result<String, String> {
+        self.begin_command(sql).await?;
+        timeout(limit, self.complete_command())
+            .await
+            .map_err(|_| format!("sqlite3 did not finish {sql:?} within {limit:?}"))?
+    }
+
+    /// Executes a SQL statement and fails if it wrote anything to stderr.
+    ///
+    /// See [`Sqlite3Process::execute_checked`](super::Sqlite3Process::execute_checked).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `execute` fails or the statement reported an error.
+    ///
+    pub async fn execute_checked(&mut self, sql: &str) -> Result<String, String> {
+        let output = self.execute(sql).await?;
+        if self.last_stderr.is_empty() {
+            Ok(output)
+        } else {
+            Err(self.last_stderr.trim_end().to_string())
+        }
+    }
+
+    /// Returns what the last completed command wrote to stderr.
+    ///
+    /// Always empty on platforms other than Unix.
+    pub fn last_stderr(&self) -> &str {
+        &self.last_stderr
+    }
+
+    /// Opens a transaction that holds `mode` until the guard is released.
+    ///
+    /// Statements can be run through the guard while the lock is held.
+    /// [`release`](AsyncLockGuard::release) commits the transaction; a guard
+    /// dropped without it has its transaction rolled back before the next
+    /// command this process runs, or when the process exits.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the lock cannot be taken, e.g. because the database
+    /// is busy.
+    ///
+    pub async fn lock(&mut self, mode: LockMode) -> Result<AsyncLockGuard<'_>, String> {
+        if let Err(e) = self.execute_checked(mode.begin_sql()).await {
+            let _ = self.execute("ROLLBACK;").await;
+            return Err(e);
+        }
+        Ok(AsyncLockGuard { process: self })
+    }
+
+    /// Sends `sql` followed by the end marker without waiting for the output.
+    async fn begin_command(&mut self, sql: &str) -> Result<(), String> {
+        if self.pending.is_some() {
+            let _ = self.complete_command().await;
+        }
+        if self.rollback_pending {
+            self.rollback_pending = false;
+            self.write_command("ROLLBACK;").await?;
+            let _ = self.complete_command().await;
+        }
+        self.last_stderr.clear();
+        self.write_command(sql).await
+    }
+
+    /// Writes `sql` and the end marker to the process's stdin.
+    async fn write_command(&mut self, sql: &str) -> Result<(), String> {
+        let stdin = self.stdin.as_mut().ok_or("stdin is not available")?;
+        stdin
+            .write_all(format!("{sql}\n{MARKER_QUERY}\n").as_bytes())
+            .await
+            .map_err(|e| format!("Failed to write: {e}"))?;
+        stdin
+            .flush()
+            .await
+            .map_err(|e| format!("Failed to flush: {e}"))?;
+        self.pending = Some(PendingOutput {
+            output: String::new(),
+            line: Vec::new(),
+        });
+        Ok(())
+    }
+
+    /// Reads the pending command's output until the end marker arrives.
+    async fn complete_command(&mut self) -> Result<String, String> {
+        let result = self.read_until_marker().await;
+        let pending = self.pending.take();
+        let output = result.and_then(|()| pending.ok_or("No command is pending".to_string()))?;
+        #[cfg(unix)]
+        {
+            self.last_stderr = self.stderr.as_mut().map(drain_pipe).unwrap_or_default();
+            self.stderr_log.push_str(&self.last_stderr);
+        }
+        Ok(output.output)
+    }
+
+    /// Reads lines into the pending output; safe to cancel and call again.
+    async fn read_until_marker(&mut self) -> Result<(), String> {
+        let stdout = self.stdout.as_mut().ok_or("stdout is not available")?;
+        let pending = self.pending.as_mut().ok_or("No command is pending")?;
+        loop {
+            // `read_until` keeps partially read bytes in `pending.line`.
+            stdout
+                .read_until(b'\n', &mut pending.line)
+                .await
+                .map_err(|e| format!("Failed to read: {e}"))?;
+            if !pending.line.ends_with(b"\n") {
+                return Err("Failed to read complete output".to_string());
+            }
+            let line = String::from_utf8_lossy(&pending.line).into_owned();
+            pending.line.clear();
+            if line.contains("MARKER_END") {
+                return Ok(());
+            }
+            if !line.contains("SELECT 'MARKER_END'") {
+                pending.output.push_str(&line);
+            }
+        }
+    }
+
+    /// Asks the sqlite3 process to exit and waits for it without blocking the runtime.
+    ///
+    /// A process that does not exit within the grace period (see
+    /// [`Sqlite3ProcessBuilder::grace_period`]) is killed.
+    ///
+    /// # Returns
+    ///
+    /// Returns the exit status, all stderr output and how long shutdown took.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the process did not exit within the grace period or
+    /// could not be waited for.
+    ///
+    pub async fn close(mut self) -> Result<ExitReport, String> {
+        self.shutdown().await.map_err(|e| match e {
+            ShutdownError::TimedOut { stderr } => format!(
+                "sqlite3 process for {} did not exit within {:?} and was killed; stderr: {stderr}",
+                self.db_path.display(),
+                self.grace_period
+            ),
+            ShutdownError::Wait(e) => format!("Error waiting for sqlite3: {e}"),
+        })
+    }
+
+    /// Sends `.exit` and waits up to the grace period for the child to exit.
+    async fn shutdown(&mut self) -> Result<ExitReport, ShutdownError> {
+        if let Some(mut stdin) = self.stdin.take() {
+            let _ = stdin.write_all(b".exit\n").await;
+            let _ = stdin.flush().await;
+        }
+        self.stdout.take();
+
+        let Some(mut child) = self.child.take() else {
+            return Err(ShutdownError::Wait(
+                "sqlite3 process has exited".to_string(),
+            ));
+        };
+        let start = Instant::now();
+        let waited = timeout(self.grace_period, child.wait()).await;
+        if waited.is_err() {
+            let _ = child.kill().await;
+        }
+        // The child has exited, so everything it wrote is in the pipe.
+        #[cfg(unix)]
+        let remaining = self
+            .stderr
+            .take()
+            .as_mut()
+            .map(drain_pipe)
+            .unwrap_or_default();
+        #[cfg(not(unix))]
+        let remaining = String::new();
+        let stderr = std::mem::take(&mut self.stderr_log) + &remaining;
+
+        match waited {
+            Ok(Ok(status)) => Ok(ExitReport {
+                status,
+                stderr,
+                duration: start.elapsed(),
+            }),
+            Ok(Err(e)) => Err(ShutdownError::Wait(e.to_string())),
+            Err(_) => Err(ShutdownError::TimedOut { stderr }),
+        }
+    }
+}
+
+impl Drop for AsyncSqlite3Process {
+    fn drop(&mut self) {
+        let Some(mut child) = self.child.take() else {
+            // Already closed.
+            return;
+        };
+        // Closing stdin makes the shell exit as if it had read `.exit`.
+        self.stdin.take();
+        let home = self._home.take();
+
+        let Ok(runtime) = Handle::try_current() else {
+            let _ = child.start_kill();
+            return;
+        };
+        let grace_period = self.grace_period;
+        let on_hang = self.on_hang;
+        let db_path = self.db_path.clone();
+        runtime.spawn(async move {
+            let _home = home;
+            if timeout(grace_period, child.wait()).await.is_err() {
+                let _ = child.kill().await;
+                if on_hang >= HangBehavior::Warn {
+                    eprintln!("sqlite3 process failed to exit within {grace_period:?}!");
+                    eprintln!("Database path: {}", db_path.display());
+                }
+            }
+        });
+    }
+}
+
+/// A transaction holding a database lock, created by [`AsyncSqlite3Process::lock`].
+///
+/// Dereferences to the process so statements can run inside the transaction.
+pub struct AsyncLockGuard<'a> {
+    process: &'a mut AsyncSqlite3Process,
+}
+
+impl AsyncLockGuard<'_> {
+    /// Commits the transaction, releasing the lock.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the commit fails; the transaction is then rolled
+    /// back before the next command.
+    ///
+    pub async fn release(self) -> Result<(), String> {
+        self.process.execute_checked("COMMIT;").await?;
+        std::mem::forget(self);
+        Ok(())
+    }
+}
+
+impl Deref for AsyncLockGuard<'_> {
+    type Target = AsyncSqlite3Process;
+
+    fn deref(&self) -> &Self::Target {
+        self.process
+    }
+}
+
+impl DerefMut for AsyncLockGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.process
+    }
+}
+
+impl Drop for AsyncLockGuard<'_> {
+    fn drop(&mut self) {
+        self.process.rollback_pending = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_execute_and_close() {
+        let dir = tempdir().unwrap();
+        let mut process = AsyncSqlite3Process::new(&dir.path().join("test.db"))
+            .await
+            .unwrap();
+
+        process
+            .execute_checked("CREATE TABLE t (x INTEGER); INSERT INTO t VALUES (1), (2)")
+            .await
+            .unwrap();
+        assert_eq!(
+            process.execute("SELECT SUM(x) FROM t;").await.unwrap(),
+            "3\n"
+        );
+        assert!(process
+            .execute_checked("SELECT * FROM missing;")
+            .await
+            .is_err());
+        assert!(process.last_stderr().contains("no such table: missing"));
+
+        let report = process.close().await.unwrap();
+        assert!(report.stderr.contains("no such table: missing"));
+    }
+
+    #[tokio::test]
+    async fn test_lock_guard_blocks_writer_until_released() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+        let mut holder = AsyncSqlite3Process::new(&db_path).await.unwrap();
+        let mut writer = AsyncSqlite3Process::new(&db_path).await.unwrap();
+        holder.execute("CREATE TABLE t (x INTEGER);").await.unwrap();
+        writer.execute(".timeout 10000").await.unwrap();
+
+        let mut guard = holder.lock(LockMode::Reserved).await.unwrap();
+        guard.execute("INSERT INTO t VALUES (1);").await.unwrap();
+        let blocked = writer
+            .execute_timeout("INSERT INTO t VALUES (2);", Duration::from_millis(300))
+            .await;
+        assert!(blocked.unwrap_err().contains("did not finish"));
+
+        guard.release().await.unwrap();
+        // Finishes the timed-out insert before counting.
+        assert_eq!(
+            writer.execute("SELECT COUNT(*) FROM t;").await.unwrap(),
+            "2\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dropped_lock_guard_rolls_back() {
+        let dir = tempdir().unwrap();
+        let mut process = AsyncSqlite3Process::new(&dir.path().join("test.db"))
+            .await
+            .unwrap();
+        process
+            .execute("CREATE TABLE t (x INTEGER);")
+            .await
+            .unwrap();
+
+        let mut guard = process.lock(LockMode::Exclusive).await.unwrap();
+        guard.execute("INSERT INTO t VALUES (1);").await.unwrap();
+        drop(guard);
+
+        assert_eq!(
+            process.execute("SELECT COUNT(*) FROM t;").await.unwrap(),
+            "0\n"
+        );
+        process.execute_checked("BEGIN; COMMIT;").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_close_kills_hung_process() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+        let mut holder = AsyncSqlite3Process::new(&db_path).await.unwrap();
+        holder.execute("CREATE TABLE t (x INTEGER);").await.unwrap();
+        let _guard = holder.lock(LockMode::Reserved).await.unwrap();
+
+        // Stuck waiting for the lock, so it never reads `.exit`.
+        let mut waiter = Sqlite3ProcessBuilder::new()
+            .grace_period(Duration::from_millis(200))
+            .spawn_async(&db_path)
+            .await
+            .unwrap();
+        waiter.execute(".timeout 60000").await.unwrap();
+        let _ = waiter
+            .execute_timeout("INSERT INTO t VALUES (1);", Duration::from_millis(100))
+            .await;
+
+        let error = waiter.close().await.unwrap_err();
+        assert!(error.contains("did not exit within"), "{error}");
+    }
+}
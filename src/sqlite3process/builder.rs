@@ -0,0 +1,582 @@
+//! Builder for configuring how the sqlite3 process is spawned.
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+#[cfg(feature = "tokio")]
+use super::AsyncSqlite3Process;
+use super::{HangBehavior, Sqlite3Process};
+use crate::worker::{WORKER_DB_ENV, WORKER_TEST_NAME};
+
+/// Environment variable that selects the sqlite3 binary when none is set explicitly.
+pub const SQLITE3_BIN_ENV: &str = "SQLITE3_BIN";
+
+/// How long a sqlite3 process may take to exit after `.exit` before it is killed.
+pub const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(60);
+
+/// Environment variables passed through to an isolated sqlite3 process.
+const PASSTHROUGH_ENV: [&str; 6] = [
+    "PATH",
+    "SYSTEMROOT",
+    "TMPDIR",
+    "TEMP",
+    "TMP",
+    "SQLITE_TMPDIR",
+];
+
+/// Builder for a [`Sqlite3Process`] with a custom binary and command-line options.
+///
+/// The binary is taken from [`binary`](Self::binary) if set, otherwise from the
+/// `SQLITE3_BIN` environment variable, otherwise `sqlite3` is looked up on `PATH`.
+///
+/// By default the process is isolated from the developer's machine: it reads an
+/// empty init file instead of `~/.sqliterc`, gets a fresh empty `HOME` and only
+/// inherits a handful of environment variables such as `PATH`. The output mode
+/// is always pinned to `list` without headers, regardless of isolation.
+///
+/// # Example
+///
+/// ```rust
+/// use sqlite_test_utils::Sqlite3Process;
+///
+/// let dir = tempfile::tempdir().unwrap();
+/// let db_path = dir.path().join("test.db");
+///
+/// let info = Sqlite3Process::builder().probe().unwrap();
+/// if !info.version_at_least(3, 7, 0) {
+///     return; // WAL mode needs SQLite 3.7.0
+/// }
+///
+/// let mut process = Sqlite3Process::builder()
+///     .bail()
+///     .spawn(&db_path)
+///     .unwrap();
+/// assert!(process.execute("SELECT 1;").unwrap().contains('1'));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Sqlite3ProcessBuilder {
+    binary: Option<PathBuf>,
+    args: Vec<OsString>,
+    isolated: bool,
+    env: Vec<(OsString, OsString)>,
+    grace_period: Duration,
+    on_hang: HangBehavior,
+    dump_transcript_on_panic: bool,
+    self_hosted: bool,
+}
+
+impl Default for Sqlite3ProcessBuilder {
+    fn default() -> Self {
+        Sqlite3ProcessBuilder {
+            binary: None,
+            args: Vec::new(),
+            isolated: true,
+            env: Vec::new(),
+            grace_period: DEFAULT_GRACE_PERIOD,
+            on_hang: HangBehavior::default(),
+            dump_transcript_on_panic: false,
+            self_hosted: false,
+        }
+    }
+}
+
+impl Sqlite3ProcessBuilder {
+    /// Creates a builder with no extra command-line options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Uses the sqlite3 binary at `path` instead of `SQLITE3_BIN` or `PATH`.
+    #[must_use]
+    pub fn binary<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.binary = Some(path.into());
+        self
+    }
+
+    /// Opens the database read-only (`-readonly`).
+    #[must_use]
+    pub fn readonly(self) -> Self {
+        self.arg("-readonly")
+    }
+
+    /// Stops processing input after the first error (`-bail`).
+    ///
+    /// With this option a failing statement makes the process exit, so later
+    /// calls to [`Sqlite3Process::execute`] return an error.
+    #[must_use]
+    pub fn bail(self) -> Self {
+        self.arg("-bail")
+    }
+
+    /// Runs `command` before reading any input (`-cmd COMMAND`).
+    ///
+    /// May be called multiple times; commands run in the order given.
+    #[must_use]
+    pub fn cmd<S: Into<OsString>>(self, command: S) -> Self {
+        self.arg("-cmd").arg(command)
+    }
+
+    /// Reads and processes `path` at startup (`-init FILENAME`).
+    ///
+    /// Passing `/dev/null` skips the user's `~/.sqliterc`.
+    #[must_use]
+    pub fn init<P: AsRef<Path>>(self, path: P) -> Self {
+        self.arg("-init").arg(path.as_ref())
+    }
+
+    /// Refuses to open the database through a symbolic link (`-nofollow`).
+    #[must_use]
+    pub fn nofollow(self) -> Self {
+        self.arg("-nofollow")
+    }
+
+    /// Enables safe mode (`-safe`), which blocks commands that touch other files.
+    #[must_use]
+    pub fn safe(self) -> Self {
+        self.arg("-safe")
+    }
+
+    /// Controls isolation from the user's `~/.sqliterc`, `HOME` and environment.
+    ///
+    /// Isolation is on by default. Turning it off inherits the full environment
+    /// of the test process and lets sqlite3 read `~/.sqliterc`.
+    #[must_use]
+    pub fn isolated(mut self, isolated: bool) -> Self {
+        self.isolated = isolated;
+        self
+    }
+
+    /// Sets an environment variable for the process, overriding isolation defaults.
+    #[must_use]
+    pub fn env<K: Into<OsString>, V: Into<OsString>>(mut self, key: K, value: V) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    /// Sets how long the process may take to exit on close or drop before it is
+    /// killed. Defaults to [`DEFAULT_GRACE_PERIOD`].
+    #[must_use]
+    pub fn grace_period(mut self, grace_period: Duration) -> Self {
+        self.grace_period = grace_period;
+        self
+    }
+
+    /// Sets what dropping the process does if it does not exit within the
+    /// grace period. Defaults to [`HangBehavior::Panic`].
+    #[must_use]
+    pub fn on_hang(mut self, on_hang: HangBehavior) -> Self {
+        self.on_hang = on_hang;
+        self
+    }
+
+    /// Prints the process's [`Transcript`](super::Transcript) to stderr if it is
+    /// dropped while the thread is panicking, e.g. because an assertion failed.
+    #[must_use]
+    pub fn dump_transcript_on_panic(mut self, dump: bool) -> Self {
+        self.dump_transcript_on_panic = dump;
+        self
+    }
+
+    /// Runs a worker built into the current test binary instead of the sqlite3 CLI.
+    ///
+    /// The test binary is re-executed and serves the database through the
+    /// SQLite library linked into rusqlite, so locking tests exercise exactly
+    /// the SQLite the code under test uses. The binary must declare the worker
+    /// with [`sqlite_test_worker!`](crate::sqlite_test_worker).
+    ///
+    /// The worker understands SQL, `.open`, `.parameter`, `.timeout`, `.exit`
+    /// and the `.gate` command of
+    /// [`Barrier::worker_gate_command`](crate::Barrier::worker_gate_command);
+    /// other dot-commands, and command-line options such as
+    /// [`bail`](Self::bail), are not supported.
+    #[must_use]
+    pub fn self_hosted(mut self) -> Self {
+        self.self_hosted = true;
+        self
+    }
+
+    /// Appends a raw command-line argument, placed before the database path.
+    #[must_use]
+    pub fn arg<S: Into<OsString>>(mut self, arg: S) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Returns the sqlite3 binary this builder will run.
+    ///
+    /// For a [`self_hosted`](Self::self_hosted) worker this is the current executable.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the worker is self-hosted and the current
+    /// executable cannot be determined.
+    ///
+    pub fn resolved_binary(&self) -> Result<PathBuf, String> {
+        if self.self_hosted {
+            return env::current_exe().map_err(|e| {
+                format!("Failed to find the test binary for the self-hosted worker: {e}")
+            });
+        }
+        Ok(resolve_binary(
+            self.binary.as_deref(),
+            env::var_os(SQLITE3_BIN_ENV),
+        ))
+    }
+
+    /// Spawns a sqlite3 process connected to the specified database.
+    ///
+    /// # Arguments
+    ///
+    /// * `db_path` - Path to the SQLite database file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sqlite3 process cannot be spawned or if
+    /// the I/O handles cannot be obtained.
+    ///
+    pub fn spawn(&self, db_path: &Path) -> Result<Sqlite3Process, String> {
+        let (command, home) = self.command(db_path)?;
+        Sqlite3Process::spawn(
+            command,
+            db_path,
+            home,
+            self.grace_period,
+            self.on_hang,
+            self.dump_transcript_on_panic,
+        )
+        .map_err(|e| self.spawn_error(e))
+    }
+
+    /// Spawns an [`AsyncSqlite3Process`](super::AsyncSqlite3Process) connected to
+    /// the specified database.
+    ///
+    /// The transcript options do not apply to the async process.
+    ///
+    /// # Arguments
+    ///
+    /// * `db_path` - Path to the SQLite database file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sqlite3 process cannot be spawned or if
+    /// the I/O handles cannot be obtained.
+    ///
+    #[cfg(feature = "tokio")]
+    pub async fn spawn_async(&self, db_path: &Path) -> Result<AsyncSqlite3Process, String> {
+        let (command, home) = self.command(db_path)?;
+        AsyncSqlite3Process::spawn(command, db_path, home, self.grace_period, self.on_hang)
+            .await
+            .map_err(|e| self.spawn_error(e))
+    }
+
+    /// Builds the command line and environment for a process on `db_path`.
+    fn command(&self, db_path: &Path) -> Result<(Command, Option<IsolatedHome>), String> {
+        if self.self_hosted && !self.args.is_empty() {
+            return Err("The self-hosted worker does not accept command-line options".to_string());
+        }
+        let mut command = Command::new(self.resolved_binary()?);
+
+        let home = if self.isolated {
+            let home = IsolatedHome::create()?;
+            command
+                .env_clear()
+                .envs(
+                    PASSTHROUGH_ENV
+                        .iter()
+                        .filter_map(|key| Some((key, env::var_os(key)?))),
+                )
+                .env("HOME", home.path())
+                .env("LC_ALL", "C");
+            if !self.self_hosted {
+                command.arg("-init").arg(null_device());
+            }
+            Some(home)
+        } else {
+            None
+        };
+        command.envs(self.env.iter().map(|(key, value)| (key, value)));
+        if self.self_hosted {
+            // Only the worker test runs; it prints nothing but the worker's output.
+            command.env(WORKER_DB_ENV, db_path).args([
+                WORKER_TEST_NAME,
+                "--nocapture",
+                "--test-threads=1",
+                "-q",
+            ]);
+        } else {
+            command.args(&self.args).arg(db_path);
+        }
+        Ok((command, home))
+    }
+
+    /// Adds a hint to a startup failure of a self-hosted worker.
+    fn spawn_error(&self, error: String) -> String {
+        if self.self_hosted {
+            format!("{error}; does this test binary declare `sqlite_test_worker!()`?")
+        } else {
+            error
+        }
+    }
+
+    /// Reports the version and compile options of the configured sqlite3 binary.
+    ///
+    /// Runs the binary against an in-memory database, so tests can skip
+    /// themselves before creating any files when a feature is missing.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the process cannot be spawned or queried.
+    ///
+    pub fn probe(&self) -> Result<Sqlite3Info, String> {
+        self.spawn(Path::new(":memory:"))?.info()
+    }
+}
+
+/// Version and compile-time configuration of a sqlite3 binary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sqlite3Info {
+    /// The result of `sqlite_version()`, e.g. `3.45.1`.
+    pub version: String,
+    /// The rows of `PRAGMA compile_options`, e.g. `ENABLE_FTS5` or `THREADSAFE=1`.
+    pub compile_options: Vec<String>,
+}
+
+impl Sqlite3Info {
+    /// Parses [`version`](Self::version) into `(major, minor, patch)`.
+    ///
+    /// Returns `None` if the version string is not in `X.Y.Z` form.
+    pub fn version_tuple(&self) -> Option<(u32, u32, u32)> {
+        parse_version(&self.version)
+    }
+
+    /// Returns `true` if the version is at least `major.minor.patch`.
+    pub fn version_at_least(&self, major: u32, minor: u32, patch: u32) -> bool {
+        self.version_tuple()
+            .is_some_and(|version| version >= (major, minor, patch))
+    }
+
+    /// Returns `true` if the binary was compiled with `option`.
+    ///
+    /// The `SQLITE_` prefix is optional, and options with a value such as
+    /// `THREADSAFE=1` also match on their name alone.
+    pub fn has_compile_option(&self, option: &str) -> bool {
+        let option = option.strip_prefix("SQLITE_").unwrap_or(option);
+        self.compile_options.iter().any(|compiled| {
+            compiled.eq_ignore_ascii_case(option)
+                || compiled
+                    .split_once('=')
+                    .is_some_and(|(name, _)| name.eq_ignore_ascii_case(option))
+        })
+    }
+}
+
+/// An empty directory used as `HOME` for an isolated process, removed on drop.
+#[derive(Debug)]
+pub(super) struct IsolatedHome {
+    path: PathBuf,
+}
+
+impl IsolatedHome {
+    /// Creates a uniquely named empty directory under the system temp directory.
+    ///
+    /// Names are retried until one does not exist yet, so the directory is
+    /// always fresh, even if another user created a directory with the same
+    /// name first.
+    fn create() -> Result<Self, String> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        const ATTEMPTS: usize = 100;
+        for _ in 0..ATTEMPTS {
+            let path = env::temp_dir().join(format!(
+                "sqlite_test_utils-home-{}-{}-{:08x}",
+                process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed),
+                fastrand::u32(..)
+            ));
+            match fs::create_dir(&path) {
+                Ok(()) => return Ok(IsolatedHome { path }),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(format!("Failed to create HOME: {e}")),
+            }
+        }
+        Err(format!(
+            "Failed to create HOME: every one of {ATTEMPTS} names was taken"
+        ))
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for IsolatedHome {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Returns the path of an always-empty file, used as the `-init` script.
+fn null_device() -> &'static str {
+    if cfg!(windows) {
+        "NUL"
+    } else {
+        "/dev/null"
+    }
+}
+
+/// Picks the explicit binary, then the environment override, then `sqlite3`.
+fn resolve_binary(explicit: Option<&Path>, env_value: Option<OsString>) -> PathBuf {
+    if let Some(path) = explicit {
+        return path.to_path_buf();
+    }
+    match env_value {
+        Some(value) if !value.is_empty() => PathBuf::from(value),
+        _ => PathBuf::from("sqlite3"),
+    }
+}
+
+/// Parses an `X.Y.Z` version string.
+fn parse_version(version: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = version.trim().split('.').map(str::parse::<u32>);
+    let major = parts.next()?.ok()?;
+    let minor = parts.next()?.ok()?;
+    let patch = parts.next()?.ok()?;
+    Some((major, minor, patch))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_resolve_binary() {
+        let explicit = Path::new("/opt/sqlite/bin/sqlite3");
+
+        assert_eq!(
+            resolve_binary(Some(explicit), Some("/usr/bin/sqlite3".into())),
+            explicit
+        );
+        assert_eq!(
+            resolve_binary(None, Some("/usr/bin/sqlite3".into())),
+            Path::new("/usr/bin/sqlite3")
+        );
+        assert_eq!(resolve_binary(None, Some("".into())), Path::new("sqlite3"));
+        assert_eq!(resolve_binary(None, None), Path::new("sqlite3"));
+    }
+
+    #[test]
+    fn test_sqlite3_info() {
+        let info = Sqlite3Info {
+            version: "3.45.1".to_string(),
+            compile_options: vec!["ENABLE_FTS5".to_string(), "THREADSAFE=1".to_string()],
+        };
+
+        assert_eq!(info.version_tuple(), Some((3, 45, 1)));
+        assert!(info.version_at_least(3, 45, 1));
+        assert!(info.version_at_least(3, 7, 0));
+        assert!(!info.version_at_least(3, 46, 0));
+
+        assert!(info.has_compile_option("ENABLE_FTS5"));
+        assert!(info.has_compile_option("SQLITE_ENABLE_FTS5"));
+        assert!(info.has_compile_option("THREADSAFE"));
+        assert!(info.has_compile_option("THREADSAFE=1"));
+        assert!(!info.has_compile_option("ENABLE_ICU"));
+
+        assert_eq!(parse_version("garbage"), None);
+    }
+
+    #[test]
+    fn test_probe_reports_version() {
+        let info = Sqlite3ProcessBuilder::new().probe().unwrap();
+
+        assert!(
+            info.version_tuple().is_some(),
+            "Unexpected version: {}",
+            info.version
+        );
+        assert!(!info.compile_options.is_empty());
+    }
+
+    #[test]
+    fn test_readonly_rejects_writes() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+        let mut writer = Sqlite3ProcessBuilder::new().spawn(&db_path).unwrap();
+        writer.execute("CREATE TABLE t (x INTEGER);").unwrap();
+        drop(writer);
+
+        let mut reader = Sqlite3ProcessBuilder::new()
+            .readonly()
+            .spawn(&db_path)
+            .unwrap();
+        reader.execute("INSERT INTO t VALUES (1);").unwrap();
+        let count = reader.execute("SELECT COUNT(*) FROM t;").unwrap();
+        assert_eq!(count.trim(), "0");
+    }
+
+    #[test]
+    fn test_output_is_pinned_over_init_file() {
+        let dir = tempdir().unwrap();
+        let init = dir.path().join("sqliterc");
+        fs::write(
+            &init,
+            ".headers on\n.mode csv\n.timer on\nCREATE TEMP TABLE from_init (x INTEGER);\n",
+        )
+        .unwrap();
+
+        let mut process = Sqlite3ProcessBuilder::new()
+            .init(&init)
+            .spawn(&dir.path().join("test.db"))
+            .unwrap();
+
+        // The init file ran, but its output settings were overridden.
+        let output = process
+            .execute("SELECT COUNT(*), NULL, 'x' FROM from_init;")
+            .unwrap();
+        assert_eq!(output, "0||x\n");
+    }
+
+    #[test]
+    fn test_isolated_environment() {
+        let dir = tempdir().unwrap();
+        let mut process = Sqlite3ProcessBuilder::new()
+            .env("EXTRA_VAR", "set")
+            .spawn(&dir.path().join("test.db"))
+            .unwrap();
+
+        // Cargo sets this for the test binary; an isolated child must not see it.
+        let inherited = process
+            .execute(".shell echo \"[$CARGO_MANIFEST_DIR]\"")
+            .unwrap();
+        assert_eq!(inherited, "[]\n");
+        let extra = process.execute(".shell echo \"[$EXTRA_VAR]\"").unwrap();
+        assert_eq!(extra, "[set]\n");
+
+        let home = process.execute(".shell echo \"$HOME\"").unwrap();
+        let home = PathBuf::from(home.trim());
+        assert!(home.is_dir(), "HOME should exist while running: {home:?}");
+        assert_eq!(fs::read_dir(&home).unwrap().count(), 0);
+
+        drop(process);
+        assert!(!home.exists(), "HOME should be removed after drop");
+    }
+
+    #[test]
+    fn test_cmd_runs_before_input() {
+        let dir = tempdir().unwrap();
+        let mut process = Sqlite3ProcessBuilder::new()
+            .cmd("CREATE TEMP TABLE startup (x INTEGER)")
+            .cmd("INSERT INTO startup VALUES (42)")
+            .spawn(&dir.path().join("test.db"))
+            .unwrap();
+
+        let value = process.execute("SELECT x FROM startup;").unwrap();
+        assert_eq!(value.trim(), "42");
+    }
+}
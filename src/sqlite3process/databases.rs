@@ -0,0 +1,125 @@
+//! Reconnecting to, switching and attaching the databases of a sqlite3 process.
+
+use std::path::Path;
+
+use rusqlite::types::Value;
+
+use super::params::sql_literal;
+use super::Sqlite3Process;
+
+impl Sqlite3Process {
+    /// Returns the path of the main database the process is connected to.
+    ///
+    /// Follows [`open`](Self::open), so diagnostics name the current file.
+    pub fn db_path(&self) -> &Path {
+        &self.db_path
+    }
+
+    /// Closes the connection and opens the same database file again.
+    ///
+    /// Any open transaction is rolled back and its locks are released, the
+    /// cached schema is discarded and attached databases are detached. Shell
+    /// output settings are kept, but connection settings such as `.timeout`
+    /// and pragmas are reset.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be opened.
+    ///
+    pub fn reopen(&mut self) -> Result<(), String> {
+        let db_path = self.db_path.clone();
+        self.open(&db_path)
+    }
+
+    /// Attaches the database at `path` under the schema name `alias`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be attached, e.g. because
+    /// `alias` is already in use.
+    ///
+    pub fn attach(&mut self, path: &Path, alias: &str) -> Result<(), String> {
+        let path = Value::Text(path.to_string_lossy().into_owned());
+        self.execute_checked(&format!(
+            "ATTACH DATABASE {} AS {};",
+            sql_literal(&path),
+            quote_identifier(alias)
+        ))?;
+        Ok(())
+    }
+
+    /// Detaches the database attached as `alias`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no database is attached as `alias`, or it is in use
+    /// by an open transaction.
+    ///
+    pub fn detach(&mut self, alias: &str) -> Result<(), String> {
+        self.execute_checked(&format!("DETACH DATABASE {};", quote_identifier(alias)))?;
+        Ok(())
+    }
+}
+
+/// Quotes a schema name as a SQL identifier.
+pub(super) fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_reopen_releases_locks() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+        let mut process = Sqlite3Process::new(&db_path).unwrap();
+        process
+            .execute_checked("CREATE TABLE t (x); BEGIN IMMEDIATE; INSERT INTO t VALUES (1);")
+            .unwrap();
+
+        let conn = Connection::open(&db_path).unwrap();
+        conn.busy_timeout(Duration::ZERO).unwrap();
+        assert!(conn.execute("INSERT INTO t VALUES (2)", []).is_err());
+
+        process.reopen().unwrap();
+        assert_eq!(process.db_path(), db_path);
+        conn.execute("INSERT INTO t VALUES (2)", []).unwrap();
+        assert_eq!(process.execute("SELECT x FROM t;").unwrap(), "2\n");
+    }
+
+    #[test]
+    fn test_open_attach_and_detach() {
+        let dir = tempdir().unwrap();
+        let mut process = Sqlite3Process::new(&dir.path().join("first.db")).unwrap();
+        let second = dir.path().join("second.db");
+
+        process.open(&second).unwrap();
+        assert_eq!(process.db_path(), second);
+        process.execute_checked("CREATE TABLE t (x);").unwrap();
+
+        let other = dir.path().join("it's other.db");
+        process.attach(&other, "the \"other\"").unwrap();
+        process
+            .execute_checked("CREATE TABLE \"the \"\"other\"\"\".u (y);")
+            .unwrap();
+        assert_eq!(
+            process
+                .execute("SELECT name FROM pragma_database_list ORDER BY seq;")
+                .unwrap(),
+            "main\nthe \"other\"\n"
+        );
+        assert!(process.attach(&other, "the \"other\"").is_err());
+
+        process.detach("the \"other\"").unwrap();
+        assert!(process.detach("the \"other\"").is_err());
+        assert!(Connection::open(&other)
+            .unwrap()
+            .prepare("SELECT y FROM u")
+            .is_ok());
+    }
+}
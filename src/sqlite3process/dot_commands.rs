@@ -0,0 +1,414 @@
+//! Typed wrappers for the sqlite3 shell's dot-commands.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use rusqlite::types::Value;
+
+use super::databases::quote_identifier;
+use super::{sql_literal, LeftoverFiles, Sqlite3Process};
+
+/// Size of the WAL file header in bytes.
+const WAL_HEADER_SIZE: u64 = 32;
+
+/// Size of each WAL frame header in bytes.
+const WAL_FRAME_HEADER_SIZE: u64 = 24;
+
+/// Database header information reported by `.dbinfo`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DbInfo {
+    /// Size of a database page in bytes.
+    pub page_size: u32,
+    /// Number of pages in the database file.
+    pub page_count: u64,
+    /// Number of pages on the freelist.
+    pub freelist_page_count: u64,
+    /// Incremented on every schema change.
+    pub schema_cookie: u32,
+    /// Incremented on every committed change to the database file.
+    pub file_change_counter: u32,
+    /// Value of `PRAGMA user_version`.
+    pub user_version: u32,
+    /// Number of frames in the `-wal` file, or `None` if there is no WAL file.
+    ///
+    /// Computed from the file size, so it includes frames left over from
+    /// before the last WAL reset.
+    pub wal_frames: Option<u64>,
+    /// Every `name: value` line of the `.dbinfo` output, keyed by name.
+    pub raw: BTreeMap<String, String>,
+}
+
+impl Sqlite3Process {
+    /// Lists the tables and views in the database, like `.tables`.
+    ///
+    /// Names in attached databases are prefixed with the schema, as in
+    /// `aux.notes`. Reads the schema table rather than the `.tables` output,
+    /// so names containing spaces come back whole. The table is queried as
+    /// `sqlite_master`, its name before SQLite 3.33 added `sqlite_schema`, so
+    /// older shells work too.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a query fails.
+    ///
+    pub fn tables(&mut self) -> Result<Vec<String>, String> {
+        let schemas = decode_hex_lines(
+            &self.execute_checked("SELECT hex(name) FROM pragma_database_list;")?,
+        )?;
+        let query = schemas
+            .iter()
+            .map(|schema| {
+                let prefix = if schema == "main" {
+                    String::new()
+                } else {
+                    format!("{schema}.")
+                };
+                format!(
+                    "SELECT hex({} || name) FROM {}.sqlite_master \
+                     WHERE type IN ('table', 'view') AND name NOT LIKE 'sqlite\\_%' ESCAPE '\\'",
+                    sql_literal(&Value::Text(prefix)),
+                    quote_identifier(schema)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" UNION ALL ");
+        let mut tables = decode_hex_lines(&self.execute_checked(&format!("{query};"))?)?;
+        tables.sort();
+        Ok(tables)
+    }
+
+    /// Returns the `CREATE` statements of the schema (`.schema`).
+    ///
+    /// Each entry is one complete statement, including its trailing semicolon.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the command fails.
+    ///
+    pub fn schema(&mut self) -> Result<Vec<String>, String> {
+        let output = self.execute_checked(".schema")?;
+        Ok(split_statements(&output))
+    }
+
+    /// Returns the database, or a single table, as a SQL script (`.dump`).
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - Restrict the dump to this table, or `None` for everything
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the command fails.
+    ///
+    pub fn dump(&mut self, table: Option<&str>) -> Result<String, String> {
+        match table {
+            Some(table) => self.execute_checked(&format!(".dump {}", quote_arg(table))),
+            None => self.execute_checked(".dump"),
+        }
+    }
+
+    /// Writes a copy of the database to `path` (`.backup FILE`).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the backup fails.
+    ///
+    pub fn backup(&mut self, path: &Path) -> Result<(), String> {
+        self.execute_checked(&format!(".backup {}", quote_path(path)))?;
+        Ok(())
+    }
+
+    /// Replaces the database contents with the database at `path` (`.restore FILE`).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the restore fails, for example because another
+    /// process holds a lock.
+    ///
+    pub fn restore(&mut self, path: &Path) -> Result<(), String> {
+        self.execute_checked(&format!(".restore {}", quote_path(path)))?;
+        Ok(())
+    }
+
+    /// Imports a CSV file into a table (`.import --csv FILE TABLE`).
+    ///
+    /// If the table does not exist it is created, using the first row of the
+    /// file as column names.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the import fails.
+    ///
+    pub fn import_csv(&mut self, path: &Path, table: &str) -> Result<(), String> {
+        self.execute_checked(&format!(
+            ".import --csv {} {}",
+            quote_path(path),
+            quote_arg(table)
+        ))?;
+        Ok(())
+    }
+
+    /// Closes the current database and opens the one at `path` (`.open FILE`).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be opened.
+    ///
+    pub fn open(&mut self, path: &Path) -> Result<(), String> {
+        self.execute_checked(&format!(".open {}", quote_path(path)))?;
+        self.db_path = path.to_path_buf();
+        self.transcript.set_label(path.display().to_string());
+        Ok(())
+    }
+
+    /// Reports header information about the database (`.dbinfo`).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the command fails or its output cannot be parsed.
+    ///
+    pub fn dbinfo(&mut self) -> Result<DbInfo, String> {
+        let output = self.execute_checked(".dbinfo")?;
+        let mut info = parse_dbinfo(&output)?;
+        let wal = LeftoverFiles::for_db(&self.db_path).wal;
+        info.wal_frames = match wal {
+            Some(wal) => {
+                let size = fs::metadata(&wal)
+                    .map_err(|e| format!("Failed to read WAL size: {e}"))?
+                    .len();
+                Some(wal_frame_count(size, info.page_size))
+            }
+            None => None,
+        };
+        Ok(info)
+    }
+}
+
+/// Splits `.schema` output into complete statements.
+fn split_statements(output: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    for line in output.lines() {
+        if !current.is_empty() {
+            current.push('\n');
+        }
+        current.push_str(line);
+        if line.trim_end().ends_with(';') {
+            statements.push(std::mem::take(&mut current));
+        }
+    }
+    if !current.trim().is_empty() {
+        statements.push(current);
+    }
+    statements
+}
+
+/// Parses the `name: value` lines of `.dbinfo` output.
+fn parse_dbinfo(output: &str) -> Result<DbInfo, String> {
+    let raw: BTreeMap<String, String> = output
+        .lines()
+        .filter_map(|line| {
+            // "data version" has no colon, so fall back to the column alignment.
+            let (name, value) = line
+                .split_once(':')
+                .or_else(|| line.rsplit_once(char::is_whitespace))?;
+            Some((name.trim().to_string(), value.trim().to_string()))
+        })
+        .collect();
+
+    let field = |name: &str| -> Result<u64, String> {
+        let value = raw
+            .get(name)
+            .ok_or_else(|| format!("Missing {name:?} in .dbinfo output"))?;
+        // Values like "1 (utf8)" carry a description after the number.
+        let number = value.split_whitespace().next().unwrap_or_default();
+        number
+            .parse()
+            .map_err(|e| format!("Invalid {name:?} in .dbinfo output: {e}"))
+    };
+    let field_u32 = |name: &str| -> Result<u32, String> {
+        u32::try_from(field(name)?).map_err(|e| format!("Invalid {name:?}: {e}"))
+    };
+
+    Ok(DbInfo {
+        page_size: field_u32("database page size")?,
+        page_count: field("database page count")?,
+        freelist_page_count: field("freelist page count")?,
+        schema_cookie: field_u32("schema cookie")?,
+        file_change_counter: field_u32("file change counter")?,
+        user_version: field_u32("user version")?,
+        wal_frames: None,
+        raw,
+    })
+}
+
+/// Number of complete frames in a WAL file of `wal_size` bytes.
+fn wal_frame_count(wal_size: u64, page_size: u32) -> u64 {
+    let frame_size = WAL_FRAME_HEADER_SIZE + u64::from(page_size);
+    wal_size.saturating_sub(WAL_HEADER_SIZE) / frame_size
+}
+
+/// Quotes a dot-command argument, escaping characters the shell would otherwise
+/// interpret, so the argument arrives unchanged and on a single line.
+pub(crate) fn quote_arg(arg: &str) -> String {
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    quoted.push('"');
+    for c in arg.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Quotes a path for use as a dot-command argument.
+fn quote_path(path: &Path) -> String {
+    quote_arg(&path.to_string_lossy())
+}
+
+/// Decodes output with one `hex()`-encoded UTF-8 string per line.
+fn decode_hex_lines(output: &str) -> Result<Vec<String>, String> {
+    output
+        .lines()
+        .map(|line| {
+            let bytes = (0..line.len())
+                .step_by(2)
+                .map(|at| u8::from_str_radix(line.get(at..at + 2)?, 16).ok())
+                .collect::<Option<Vec<u8>>>();
+            bytes
+                .and_then(|bytes| String::from_utf8(bytes).ok())
+                .ok_or_else(|| format!("Invalid hex-encoded name {line:?}"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn new_test_process() -> (Sqlite3Process, tempfile::TempDir) {
+        let dir = tempdir().unwrap();
+        let process = Sqlite3Process::new(&dir.path().join("test.db")).unwrap();
+        (process, dir)
+    }
+
+    #[test]
+    fn test_split_statements() {
+        let output = "CREATE TABLE a (x);\nCREATE TABLE b (\n  y INTEGER\n);\n";
+        assert_eq!(
+            split_statements(output),
+            vec!["CREATE TABLE a (x);", "CREATE TABLE b (\n  y INTEGER\n);"]
+        );
+    }
+
+    #[test]
+    fn test_quote_arg() {
+        assert_eq!(quote_arg("plain"), "\"plain\"");
+        assert_eq!(quote_arg("with space"), "\"with space\"");
+        assert_eq!(quote_arg(r#"a"b\c"#), r#""a\"b\\c""#);
+        assert_eq!(quote_arg("two\nlines"), r#""two\nlines""#);
+    }
+
+    #[test]
+    fn test_wal_frame_count() {
+        assert_eq!(wal_frame_count(0, 4096), 0);
+        assert_eq!(wal_frame_count(32, 4096), 0);
+        assert_eq!(wal_frame_count(32 + 3 * (24 + 4096), 4096), 3);
+    }
+
+    #[test]
+    fn test_tables_schema_and_dump() {
+        let (mut process, dir) = new_test_process();
+        process
+            .execute(
+                "CREATE TABLE b (y TEXT); CREATE TABLE a (x INTEGER); INSERT INTO a VALUES (7);",
+            )
+            .unwrap();
+
+        assert_eq!(process.tables().unwrap(), vec!["a", "b"]);
+        assert_eq!(
+            process.schema().unwrap(),
+            vec!["CREATE TABLE b (y TEXT);", "CREATE TABLE a (x INTEGER);"]
+        );
+
+        let dump = process.dump(Some("a")).unwrap();
+        assert!(dump.contains("INSERT INTO a VALUES(7);"), "got: {dump}");
+        assert!(!dump.contains("CREATE TABLE b"), "got: {dump}");
+
+        process
+            .execute("CREATE VIEW \"with space\" AS SELECT x FROM a;")
+            .unwrap();
+        process.attach(&dir.path().join("aux.db"), "aux").unwrap();
+        process.execute("CREATE TABLE aux.c (z);").unwrap();
+        assert_eq!(
+            process.tables().unwrap(),
+            vec!["a", "aux.c", "b", "with space"]
+        );
+    }
+
+    #[test]
+    fn test_backup_and_restore() {
+        let (mut process, dir) = new_test_process();
+        let backup_path = dir.path().join("backup with space.db");
+        process
+            .execute("CREATE TABLE t (x INTEGER); INSERT INTO t VALUES (1);")
+            .unwrap();
+
+        process.backup(&backup_path).unwrap();
+        process.execute("DELETE FROM t;").unwrap();
+        process.restore(&backup_path).unwrap();
+
+        assert_eq!(process.execute("SELECT x FROM t;").unwrap(), "1\n");
+        let missing = dir.path().join("missing").join("backup.db");
+        assert!(process.restore(&missing).is_err());
+    }
+
+    #[test]
+    fn test_import_csv_and_open() {
+        let (mut process, dir) = new_test_process();
+        let csv_path = dir.path().join("data.csv");
+        fs::write(&csv_path, "id,name\n1,one\n2,\"two, quoted\"\n").unwrap();
+
+        process.import_csv(&csv_path, "people").unwrap();
+        assert_eq!(
+            process
+                .execute("SELECT name FROM people ORDER BY id;")
+                .unwrap(),
+            "one\ntwo, quoted\n"
+        );
+
+        let other_path = dir.path().join("other.db");
+        process.open(&other_path).unwrap();
+        assert!(process.tables().unwrap().is_empty());
+        assert_eq!(process.db_path, other_path);
+    }
+
+    #[test]
+    fn test_dbinfo_reports_header_and_wal_frames() {
+        let (mut process, _dir) = new_test_process();
+        process.execute("PRAGMA page_size = 1024;").unwrap();
+        process.execute("CREATE TABLE t (x INTEGER);").unwrap();
+        let before = process.dbinfo().unwrap();
+        assert_eq!(before.page_size, 1024);
+        assert_eq!(before.wal_frames, None);
+        assert_eq!(
+            before.raw.get("write format").map(String::as_str),
+            Some("1")
+        );
+
+        process.enable_wal_mode();
+        process.disable_wal_checkpointing();
+        process.execute("CREATE TABLE u (y INTEGER);").unwrap();
+        let after = process.dbinfo().unwrap();
+
+        assert!(after.schema_cookie > before.schema_cookie);
+        assert!(after.wal_frames.unwrap() > 0);
+    }
+}
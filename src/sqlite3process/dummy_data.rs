@@ -0,0 +1,210 @@
+//! Bulk creation of dummy tables through the sqlite3 process.
+
+use std::fmt::Write as _;
+
+use rusqlite::types::Value;
+
+use super::params::sql_literal;
+use super::Sqlite3Process;
+use crate::create_note;
+
+/// Number of rows sent in each multi-row `INSERT` statement.
+const ROWS_PER_INSERT: usize = 500;
+
+/// Describes a dummy table for [`Sqlite3Process::create_dummy_data_with`].
+///
+/// The table has an `id INTEGER PRIMARY KEY` and a `value TEXT` column.
+/// The default is the table made by [`Sqlite3Process::create_dummy_data`]:
+/// `test` with 999 rows of `Hello, World! {n}`.
+///
+/// # Example
+///
+/// ```rust
+/// use sqlite_test_utils::{DummyData, Sqlite3Process};
+///
+/// let dir = tempfile::tempdir().unwrap();
+/// let mut process = Sqlite3Process::new(&dir.path().join("test.db")).unwrap();
+///
+/// process
+///     .create_dummy_data_with(&DummyData::new("items").rows(10_000).template("item {n}"))
+///     .unwrap();
+/// assert_eq!(process.execute("SELECT COUNT(*) FROM items;").unwrap(), "10000\n");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DummyData {
+    table: String,
+    rows: usize,
+    values: DummyValues,
+}
+
+/// How the `value` column of a [`DummyData`] table is filled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DummyValues {
+    /// A template in which every `{n}` is replaced by the 1-based row number.
+    Template(String),
+    /// Seeded random Lorem notes, the same ones [`init_test_db`](crate::init_test_db)
+    /// generates for the same seed and word count.
+    Notes {
+        /// Random seed for reproducible data generation.
+        seed: u64,
+        /// Maximum number of words per note.
+        word_count: usize,
+    },
+}
+
+impl Default for DummyData {
+    fn default() -> Self {
+        DummyData {
+            table: "test".to_string(),
+            rows: 999,
+            values: DummyValues::Template("Hello, World! {n}".to_string()),
+        }
+    }
+}
+
+impl DummyData {
+    /// Describes a table named `table` with the default rows and values.
+    pub fn new<S: Into<String>>(table: S) -> Self {
+        DummyData {
+            table: table.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Sets the number of rows to insert.
+    #[must_use]
+    pub fn rows(mut self, rows: usize) -> Self {
+        self.rows = rows;
+        self
+    }
+
+    /// Fills the `value` column from a template; `{n}` is the row number.
+    #[must_use]
+    pub fn template<S: Into<String>>(mut self, template: S) -> Self {
+        self.values = DummyValues::Template(template.into());
+        self
+    }
+
+    /// Fills the `value` column with seeded random Lorem notes.
+    #[must_use]
+    pub fn notes(mut self, seed: u64, word_count: usize) -> Self {
+        self.values = DummyValues::Notes { seed, word_count };
+        self
+    }
+
+    /// Builds the script that opens a transaction, creates and fills the table.
+    ///
+    /// The script leaves the transaction open so the caller can decide whether
+    /// to commit it.
+    fn script(&self) -> String {
+        let table = &self.table;
+        let mut script =
+            format!("BEGIN;\nCREATE TABLE {table} (id INTEGER PRIMARY KEY, value TEXT);\n");
+
+        if let DummyValues::Notes { seed, .. } = self.values {
+            fastrand::seed(seed);
+        }
+        let mut row = 0;
+        while row < self.rows {
+            let chunk_end = (row + ROWS_PER_INSERT).min(self.rows);
+            let _ = write!(script, "INSERT INTO {table} (value) VALUES ");
+            for number in row + 1..=chunk_end {
+                let value = match self.values {
+                    DummyValues::Template(ref template) => {
+                        template.replace("{n}", &number.to_string())
+                    }
+                    DummyValues::Notes { word_count, .. } => create_note(word_count),
+                };
+                let separator = if number == chunk_end { ";\n" } else { "," };
+                let _ = write!(script, "({}){separator}", sql_literal(&Value::Text(value)));
+            }
+            row = chunk_end;
+        }
+        script
+    }
+}
+
+impl Sqlite3Process {
+    /// Creates and fills a dummy table described by `data`.
+    ///
+    /// All rows are inserted in a single transaction, streamed to the process
+    /// as one batch of multi-row `INSERT` statements.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the table already exists or any statement fails; the
+    /// transaction is rolled back in that case.
+    ///
+    pub fn create_dummy_data_with(&mut self, data: &DummyData) -> Result<(), String> {
+        // The shell keeps going after an error, so only commit a clean run.
+        if let Err(e) = self.execute_checked(&data.script()) {
+            let _ = self.execute("ROLLBACK;");
+            return Err(e);
+        }
+        self.execute_checked("COMMIT;")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{init_test_db, read_row};
+    use rusqlite::Connection;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_script_chunks_rows() {
+        let script = DummyData::new("t").rows(501).template("v{n}").script();
+
+        assert_eq!(script.matches("INSERT INTO t").count(), 2);
+        assert!(script.contains("('v1'),('v2')"));
+        assert!(script.contains("('v500');\nINSERT INTO t (value) VALUES ('v501');\n"));
+        assert!(script.starts_with("BEGIN;\n"));
+    }
+
+    #[test]
+    fn test_create_dummy_data_with_template() {
+        let dir = tempdir().unwrap();
+        let mut process = Sqlite3Process::new(&dir.path().join("test.db")).unwrap();
+
+        let data = DummyData::new("items").rows(1234).template("it's #{n}");
+        process.create_dummy_data_with(&data).unwrap();
+
+        assert_eq!(
+            process
+                .execute("SELECT COUNT(*), MAX(value) FROM items WHERE id = 1234;")
+                .unwrap(),
+            "1|it's #1234\n"
+        );
+
+        // A second attempt fails on the existing table and leaves no open transaction.
+        assert!(process.create_dummy_data_with(&data).is_err());
+        assert_eq!(
+            process.execute("SELECT COUNT(*) FROM items;").unwrap(),
+            "1234\n"
+        );
+        process.execute_checked("BEGIN; COMMIT;").unwrap();
+    }
+
+    #[test]
+    fn test_create_dummy_data_with_notes_matches_init_test_db() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+        let mut process = Sqlite3Process::new(&db_path).unwrap();
+        process
+            .create_dummy_data_with(&DummyData::new("notes_copy").rows(20).notes(42, 10))
+            .unwrap();
+
+        let conn = Connection::open(&db_path).unwrap();
+        init_test_db(&conn, "main", 42, 20, 10).unwrap();
+        for id in 1..=20 {
+            let copy: String = conn
+                .query_row("SELECT value FROM notes_copy WHERE id = ?1", [id], |row| {
+                    row.get(0)
+                })
+                .unwrap();
+            assert_eq!(copy, read_row(&conn, "main", id).unwrap());
+        }
+    }
+}
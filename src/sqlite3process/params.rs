@@ -0,0 +1,155 @@
+//! Parameter binding for statements run by the sqlite3 process.
+
+use std::fmt::Write as _;
+
+use rusqlite::types::Value;
+
+use super::dot_commands::quote_arg;
+use super::Sqlite3Process;
+
+impl Sqlite3Process {
+    /// Executes a SQL statement with positional parameters bound to `?1`, `?2`, ...
+    ///
+    /// Values are bound through the shell's `.parameter set` mechanism, so they
+    /// never need escaping in `sql`. The shell binds parameters by name, which
+    /// means placeholders must be numbered: use `?1` rather than a bare `?`.
+    ///
+    /// # Arguments
+    ///
+    /// * `sql` - The SQL statement to execute
+    /// * `params` - The values to bind, in placeholder order
+    ///
+    /// # Returns
+    ///
+    /// Returns the output from sqlite3 as a string.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if execution fails or the statement reported an error
+    /// (see [`execute_checked`](Self::execute_checked)).
+    ///
+    pub fn execute_params(&mut self, sql: &str, params: &[Value]) -> Result<String, String> {
+        self.execute_checked(&Self::params_script(sql, params))
+    }
+
+    /// Builds the script that binds `params`, runs `sql` and clears the bindings.
+    pub(crate) fn params_script(sql: &str, params: &[Value]) -> String {
+        let mut script = String::from(".parameter clear\n");
+        for (index, value) in params.iter().enumerate() {
+            let _ = writeln!(
+                script,
+                ".parameter set ?{} {}",
+                index + 1,
+                quote_arg(&sql_literal(value))
+            );
+        }
+        script.push_str(sql);
+        // Terminate `sql` in case it lacks a semicolon, or the shell would read
+        // the next dot-command as part of the statement.
+        script.push_str("\n;\n.parameter clear");
+        script
+    }
+}
+
+/// Formats a value as a SQL literal that evaluates back to the same value.
+///
+/// Text is single-quoted with embedded quotes doubled, blobs become `X'..'`
+/// hex literals and non-finite reals map to what SQLite would store for them.
+///
+/// # Example
+///
+/// ```rust
+/// use rusqlite::types::Value;
+/// use sqlite_test_utils::sql_literal;
+///
+/// assert_eq!(sql_literal(&Value::Text("it's".into())), "'it''s'");
+/// assert_eq!(sql_literal(&Value::Blob(vec![0x00, 0xff])), "X'00FF'");
+/// assert_eq!(sql_literal(&Value::Null), "NULL");
+/// ```
+pub fn sql_literal(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        // The literal 9223372036854775808 does not fit in an integer, so negating
+        // it would produce a real.
+        Value::Integer(i64::MIN) => format!("({} - 1)", i64::MIN + 1),
+        Value::Integer(integer) => integer.to_string(),
+        // SQLite stores NaN as NULL and has no literal for infinity, but 9e999
+        // overflows to it.
+        Value::Real(real) if real.is_nan() => "NULL".to_string(),
+        Value::Real(real) if *real == f64::INFINITY => "9e999".to_string(),
+        Value::Real(real) if *real == f64::NEG_INFINITY => "-9e999".to_string(),
+        // Debug formatting is the shortest representation that round-trips.
+        Value::Real(real) => format!("{real:?}"),
+        Value::Text(text) => format!("'{}'", text.replace('\'', "''")),
+        Value::Blob(blob) => {
+            let mut literal = String::with_capacity(blob.len() * 2 + 3);
+            literal.push_str("X'");
+            for byte in blob {
+                let _ = write!(literal, "{byte:02X}");
+            }
+            literal.push('\'');
+            literal
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_sql_literal() {
+        assert_eq!(sql_literal(&Value::Integer(-5)), "-5");
+        assert_eq!(
+            sql_literal(&Value::Integer(i64::MIN)),
+            "(-9223372036854775807 - 1)"
+        );
+        assert_eq!(sql_literal(&Value::Real(1.0)), "1.0");
+        assert_eq!(sql_literal(&Value::Real(f64::NAN)), "NULL");
+        assert_eq!(sql_literal(&Value::Real(f64::NEG_INFINITY)), "-9e999");
+        assert_eq!(sql_literal(&Value::Blob(Vec::new())), "X''");
+    }
+
+    #[test]
+    fn test_execute_params_round_trips_values() {
+        let dir = tempdir().unwrap();
+        let mut process = Sqlite3Process::new(&dir.path().join("test.db")).unwrap();
+        process
+            .execute("CREATE TABLE t (i INTEGER, r REAL, s TEXT, b BLOB, n);")
+            .unwrap();
+
+        let params = [
+            Value::Integer(i64::MIN),
+            Value::Real(0.1),
+            Value::Text("it's \"quoted\"\nand \\ multi-line".to_string()),
+            Value::Blob(vec![0x00, 0x27, 0xff]),
+            Value::Null,
+        ];
+        process
+            .execute_params("INSERT INTO t VALUES (?1, ?2, ?3, ?4, ?5);", &params)
+            .unwrap();
+
+        // Read the row back in-process to compare exact values and types.
+        let conn = rusqlite::Connection::open(dir.path().join("test.db")).unwrap();
+        let row: Vec<Value> = conn
+            .query_row("SELECT i, r, s, b, n FROM t", [], |row| {
+                (0..5).map(|index| row.get(index)).collect()
+            })
+            .unwrap();
+        assert_eq!(row, params);
+
+        // Parameters do not leak into later statements.
+        let output = process.execute("SELECT typeof(?1);").unwrap();
+        assert_eq!(output, "null\n");
+    }
+
+    #[test]
+    fn test_execute_params_reports_errors() {
+        let dir = tempdir().unwrap();
+        let mut process = Sqlite3Process::new(&dir.path().join("test.db")).unwrap();
+
+        let result = process.execute_params("SELECT * FROM missing WHERE x = ?1;", &[1.into()]);
+        assert!(result.is_err());
+    }
+}
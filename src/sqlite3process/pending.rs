@@ -0,0 +1,158 @@
+//! Submitting statements without waiting for their output.
+
+use std::time::{Duration, Instant};
+
+use super::Sqlite3Process;
+
+/// A statement submitted with [`Sqlite3Process::submit`] that may still be running.
+///
+/// The process cannot run anything else while the result is pending. Dropping
+/// an uncollected result is allowed: the next command on the process first
+/// waits for the abandoned statement to finish and discards its output.
+pub struct PendingResult<'a> {
+    process: &'a mut Sqlite3Process,
+    result: Option<Result<String, String>>,
+}
+
+impl Sqlite3Process {
+    /// Sends a SQL statement and returns without waiting for it to finish.
+    ///
+    /// This allows a test to start a statement that blocks on a lock held by
+    /// another process, check that it is blocked, release the lock and then
+    /// watch the statement complete.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    /// use sqlite_test_utils::Sqlite3Process;
+    ///
+    /// let dir = tempfile::tempdir().unwrap();
+    /// let db_path = dir.path().join("test.db");
+    /// let mut holder = Sqlite3Process::new(&db_path).unwrap();
+    /// let mut waiter = Sqlite3Process::new(&db_path).unwrap();
+    /// holder.execute("CREATE TABLE t (x); BEGIN IMMEDIATE;").unwrap();
+    ///
+    /// waiter.execute(".timeout 10000").unwrap();
+    /// let mut pending = waiter.submit("INSERT INTO t VALUES (1);").unwrap();
+    /// assert!(pending.wait(Duration::from_millis(200)).is_none(), "should be blocked");
+    ///
+    /// holder.execute("COMMIT;").unwrap();
+    /// assert!(pending.wait(Duration::from_secs(10)).unwrap().is_ok());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the statement cannot be written to the process.
+    ///
+    pub fn submit(&mut self, sql: &str) -> Result<PendingResult<'_>, String> {
+        self.begin_command(sql)?;
+        Ok(PendingResult {
+            process: self,
+            result: None,
+        })
+    }
+}
+
+impl PendingResult<'_> {
+    /// Returns `true` once the statement has finished and its output is available.
+    pub fn is_ready(&mut self) -> bool {
+        self.poll(Some(Instant::now()));
+        self.result.is_some()
+    }
+
+    /// Returns the result if the statement has finished, without blocking.
+    ///
+    /// Once available, the same result is returned by every later call.
+    pub fn try_collect(&mut self) -> Option<Result<String, String>> {
+        self.poll(Some(Instant::now()));
+        self.result.clone()
+    }
+
+    /// Waits up to `timeout` for the statement to finish.
+    ///
+    /// Returns `None` if it is still running when the timeout expires; it can
+    /// be waited for again later.
+    pub fn wait(&mut self, timeout: Duration) -> Option<Result<String, String>> {
+        self.poll(Some(Instant::now() + timeout));
+        self.result.clone()
+    }
+
+    /// Blocks until the statement finishes and returns its output.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading the output fails.
+    ///
+    pub fn collect(mut self) -> Result<String, String> {
+        self.poll(None);
+        self.result
+            .take()
+            .unwrap_or_else(|| Err("No command is pending".to_string()))
+    }
+
+    /// Reads available output until the statement finishes or `deadline` passes.
+    fn poll(&mut self, deadline: Option<Instant>) {
+        if self.result.is_none() {
+            self.result = self.process.complete_command(deadline);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_submit_blocks_until_lock_released() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+        let mut holder = Sqlite3Process::new(&db_path).unwrap();
+        let mut waiter = Sqlite3Process::new(&db_path).unwrap();
+        holder
+            .execute("CREATE TABLE t (x INTEGER); BEGIN IMMEDIATE; INSERT INTO t VALUES (1);")
+            .unwrap();
+        waiter.execute(".timeout 10000").unwrap();
+
+        let mut pending = waiter.submit("INSERT INTO t VALUES (2);").unwrap();
+        assert!(!pending.is_ready());
+        assert!(pending.try_collect().is_none());
+        assert!(pending.wait(Duration::from_millis(300)).is_none());
+
+        holder.execute("COMMIT;").unwrap();
+        let result = pending.wait(Duration::from_secs(10)).unwrap();
+        assert_eq!(result, Ok(String::new()));
+        assert!(pending.is_ready());
+        assert_eq!(pending.try_collect(), Some(Ok(String::new())));
+
+        assert_eq!(waiter.execute("SELECT COUNT(*) FROM t;").unwrap(), "2\n");
+    }
+
+    #[test]
+    fn test_abandoned_result_is_drained_by_next_command() {
+        let dir = tempdir().unwrap();
+        let mut process = Sqlite3Process::new(&dir.path().join("test.db")).unwrap();
+
+        let pending = process.submit("SELECT 'abandoned';").unwrap();
+        drop(pending);
+
+        assert_eq!(process.execute("SELECT 'next';").unwrap(), "next\n");
+        assert_eq!(
+            process.submit("SELECT 'collected';").unwrap().collect(),
+            Ok("collected\n".to_string())
+        );
+
+        let commands: Vec<&str> = process
+            .transcript()
+            .entries()
+            .iter()
+            .map(|entry| entry.command.as_str())
+            .collect();
+        assert!(commands.ends_with(&[
+            "SELECT 'abandoned';",
+            "SELECT 'next';",
+            "SELECT 'collected';"
+        ]));
+    }
+}
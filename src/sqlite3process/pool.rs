@@ -0,0 +1,354 @@
+//! A group of sqlite3 processes sharing one database and its settings.
+
+use std::ops::{Index, IndexMut};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use super::{ExitReport, PendingResult, Sqlite3Process, Sqlite3ProcessBuilder};
+
+/// Builder for a [`ProcessPool`].
+///
+/// Every member is spawned by the same [`Sqlite3ProcessBuilder`] and gets the
+/// same busy timeout and journal mode.
+#[derive(Debug, Clone)]
+pub struct ProcessPoolBuilder {
+    size: usize,
+    process: Sqlite3ProcessBuilder,
+    journal_mode: Option<String>,
+    busy_timeout: Option<Duration>,
+}
+
+impl Default for ProcessPoolBuilder {
+    fn default() -> Self {
+        ProcessPoolBuilder {
+            size: 2,
+            process: Sqlite3ProcessBuilder::new(),
+            journal_mode: None,
+            busy_timeout: None,
+        }
+    }
+}
+
+impl ProcessPoolBuilder {
+    /// Creates a builder for a pool of two default processes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the number of processes.
+    #[must_use]
+    pub fn size(mut self, size: usize) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// Spawns every member with `builder`.
+    #[must_use]
+    pub fn process(mut self, builder: Sqlite3ProcessBuilder) -> Self {
+        self.process = builder;
+        self
+    }
+
+    /// Sets the journal mode (`PRAGMA journal_mode`) on every member.
+    #[must_use]
+    pub fn journal_mode(mut self, mode: &str) -> Self {
+        self.journal_mode = Some(mode.to_string());
+        self
+    }
+
+    /// Sets the busy timeout (`.timeout`) of every member.
+    #[must_use]
+    pub fn busy_timeout(mut self, timeout: Duration) -> Self {
+        self.busy_timeout = Some(timeout);
+        self
+    }
+
+    /// Spawns the processes on the database at `db_path` and applies the settings.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the member that could not be spawned or
+    /// configured. Members spawned so far are shut down.
+    ///
+    pub fn spawn(&self, db_path: &Path) -> Result<ProcessPool, String> {
+        let mut processes = Vec::with_capacity(self.size);
+        for index in 0..self.size {
+            let mut process = self
+                .process
+                .spawn(db_path)
+                .map_err(|e| format!("process {index}: {e}"))?;
+            self.configure(&mut process)
+                .map_err(|e| format!("process {index}: {e}"))?;
+            processes.push(process);
+        }
+        Ok(ProcessPool { processes })
+    }
+
+    fn configure(&self, process: &mut Sqlite3Process) -> Result<(), String> {
+        if let Some(timeout) = self.busy_timeout {
+            process.execute_checked(&format!(".timeout {}", timeout.as_millis()))?;
+        }
+        if let Some(ref mode) = self.journal_mode {
+            let output = process.execute_checked(&format!("PRAGMA journal_mode = {mode};"))?;
+            if !output.trim().eq_ignore_ascii_case(mode) {
+                return Err(format!(
+                    "journal mode is {} instead of {mode}",
+                    output.trim()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// N sqlite3 processes on the same database, driven together.
+///
+/// Members are addressed by index. [`broadcast`](Self::broadcast) and
+/// [`execute_each`](Self::execute_each) send statements to every member before
+/// waiting for any of them, so the statements run concurrently, and
+/// [`shutdown`](Self::shutdown) closes all members with one error report.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use sqlite_test_utils::ProcessPool;
+///
+/// let dir = tempfile::tempdir().unwrap();
+/// let mut pool = ProcessPool::builder()
+///     .size(4)
+///     .journal_mode("WAL")
+///     .busy_timeout(Duration::from_secs(5))
+///     .spawn(&dir.path().join("test.db"))
+///     .unwrap();
+///
+/// pool[0].execute_checked("CREATE TABLE t (x);").unwrap();
+/// let inserts: Vec<String> = (0..4)
+///     .map(|i| format!("INSERT INTO t VALUES ({i});"))
+///     .collect();
+/// let inserts: Vec<&str> = inserts.iter().map(String::as_str).collect();
+/// assert!(pool.execute_each(&inserts).iter().all(Result::is_ok));
+///
+/// for count in pool.broadcast("SELECT COUNT(*) FROM t;") {
+///     assert_eq!(count.unwrap(), "4\n");
+/// }
+/// pool.shutdown().unwrap();
+/// ```
+pub struct ProcessPool {
+    processes: Vec<Sqlite3Process>,
+}
+
+impl ProcessPool {
+    /// Spawns `size` default processes on the database at `db_path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a process cannot be spawned.
+    ///
+    pub fn new(db_path: &Path, size: usize) -> Result<Self, String> {
+        Self::builder().size(size).spawn(db_path)
+    }
+
+    /// Returns a builder to configure the pool's size and shared settings.
+    pub fn builder() -> ProcessPoolBuilder {
+        ProcessPoolBuilder::new()
+    }
+
+    /// Returns the number of processes.
+    pub fn len(&self) -> usize {
+        self.processes.len()
+    }
+
+    /// Returns `true` if the pool has no processes.
+    pub fn is_empty(&self) -> bool {
+        self.processes.is_empty()
+    }
+
+    /// Returns the member at `index`, if any.
+    pub fn get(&self, index: usize) -> Option<&Sqlite3Process> {
+        self.processes.get(index)
+    }
+
+    /// Returns the member at `index` mutably, if any.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Sqlite3Process> {
+        self.processes.get_mut(index)
+    }
+
+    /// Iterates over the members in index order.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Sqlite3Process> {
+        self.processes.iter_mut()
+    }
+
+    /// Takes the members out of the pool, in index order, e.g. to drive each
+    /// from its own thread.
+    pub fn into_processes(self) -> Vec<Sqlite3Process> {
+        self.processes
+    }
+
+    /// Runs `sql` on every member concurrently.
+    ///
+    /// # Returns
+    ///
+    /// Each member's output, in index order, or its error as
+    /// [`Sqlite3Process::execute_checked`] reports it.
+    ///
+    pub fn broadcast(&mut self, sql: &str) -> Vec<Result<String, String>> {
+        let sqls = vec![sql; self.len()];
+        self.execute_each(&sqls)
+    }
+
+    /// Runs `sqls[i]` on member `i`, all members concurrently.
+    ///
+    /// # Returns
+    ///
+    /// Each member's output, in index order, or its error as
+    /// [`Sqlite3Process::execute_checked`] reports it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `sqls` does not have one statement per member.
+    ///
+    pub fn execute_each(&mut self, sqls: &[&str]) -> Vec<Result<String, String>> {
+        assert_eq!(
+            sqls.len(),
+            self.len(),
+            "execute_each needs one statement per pool member"
+        );
+        let pending: Vec<Result<PendingResult<'_>, String>> = self
+            .processes
+            .iter_mut()
+            .zip(sqls)
+            .map(|(process, sql)| process.submit(sql))
+            .collect();
+        let outputs: Vec<Result<String, String>> = pending
+            .into_iter()
+            .map(|pending| pending.and_then(PendingResult::collect))
+            .collect();
+        outputs
+            .into_iter()
+            .zip(&self.processes)
+            .map(|(output, process)| {
+                output.and_then(|output| match process.last_stderr() {
+                    "" => Ok(output),
+                    stderr => Err(stderr.trim_end().to_string()),
+                })
+            })
+            .collect()
+    }
+
+    /// Closes every member concurrently.
+    ///
+    /// # Returns
+    ///
+    /// Each member's exit report, in index order. The sqlite3 shell exits with
+    /// a non-zero status once a statement has failed, so check
+    /// [`ExitReport::success`] only where that matters.
+    ///
+    /// # Errors
+    ///
+    /// Returns one error listing every member that did not exit within its
+    /// grace period or could not be waited for.
+    ///
+    pub fn shutdown(self) -> Result<Vec<ExitReport>, String> {
+        let total = self.processes.len();
+        let results: Vec<Result<ExitReport, String>> = thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .processes
+                .into_iter()
+                .map(|process| scope.spawn(move || process.close()))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|_| Err("close panicked".to_string()))
+                })
+                .collect()
+        });
+
+        let mut reports = Vec::with_capacity(total);
+        let mut failures = Vec::new();
+        for (index, result) in results.into_iter().enumerate() {
+            match result {
+                Ok(report) => reports.push(report),
+                Err(e) => failures.push(format!("process {index}: {e}")),
+            }
+        }
+        if failures.is_empty() {
+            Ok(reports)
+        } else {
+            Err(format!(
+                "{} of {total} sqlite3 processes failed to shut down:\n{}",
+                failures.len(),
+                failures.join("\n")
+            ))
+        }
+    }
+}
+
+impl Index<usize> for ProcessPool {
+    type Output = Sqlite3Process;
+
+    fn index(&self, index: usize) -> &Sqlite3Process {
+        &self.processes[index]
+    }
+}
+
+impl IndexMut<usize> for ProcessPool {
+    fn index_mut(&mut self, index: usize) -> &mut Sqlite3Process {
+        &mut self.processes[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_shared_settings_and_broadcast() {
+        let dir = tempdir().unwrap();
+        let mut pool = ProcessPool::builder()
+            .size(3)
+            .journal_mode("wal")
+            .busy_timeout(Duration::from_millis(2500))
+            .spawn(&dir.path().join("test.db"))
+            .unwrap();
+        assert_eq!(pool.len(), 3);
+
+        for mode in pool.broadcast("PRAGMA journal_mode;") {
+            assert_eq!(mode.unwrap(), "wal\n");
+        }
+        for timeout in pool.broadcast("PRAGMA busy_timeout;") {
+            assert_eq!(timeout.unwrap(), "2500\n");
+        }
+        let results = pool.execute_each(&["SELECT 0;", "SELECT * FROM missing;", "SELECT 2;"]);
+        assert_eq!(results[0], Ok("0\n".to_string()));
+        assert!(results[1].as_ref().unwrap_err().contains("no such table"));
+        assert_eq!(results[2], Ok("2\n".to_string()));
+
+        let reports = pool.shutdown().unwrap();
+        assert_eq!(reports.len(), 3);
+    }
+
+    #[test]
+    fn test_statements_run_concurrently() {
+        let dir = tempdir().unwrap();
+        let mut pool = ProcessPool::builder()
+            .busy_timeout(Duration::from_secs(10))
+            .spawn(&dir.path().join("test.db"))
+            .unwrap();
+        pool[1]
+            .execute_checked("CREATE TABLE t (x); BEGIN IMMEDIATE;")
+            .unwrap();
+
+        // Member 0 waits for member 1's lock, which member 1 releases in the
+        // same round; run one after the other, the insert would wait forever.
+        let results = pool.execute_each(&["INSERT INTO t VALUES (1);", "COMMIT;"]);
+        assert_eq!(results, [Ok(String::new()), Ok(String::new())]);
+        assert_eq!(pool[1].execute("SELECT COUNT(*) FROM t;").unwrap(), "1\n");
+        assert!(pool.get(2).is_none());
+    }
+}
@@ -0,0 +1,254 @@
+//! Process-level information about the sqlite3 child, read from `/proc` on Linux.
+
+#[cfg(target_os = "linux")]
+use std::fs;
+#[cfg(target_os = "linux")]
+use std::os::unix::fs::MetadataExt;
+#[cfg(target_os = "linux")]
+use std::time::Duration;
+
+use super::Sqlite3Process;
+
+/// CPU time, memory and file descriptor usage of a sqlite3 process.
+///
+/// Returned by [`Sqlite3Process::resource_usage`].
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceUsage {
+    /// CPU time spent in user mode.
+    pub user_time: Duration,
+    /// CPU time spent in the kernel.
+    pub system_time: Duration,
+    /// Current resident set size in bytes.
+    pub rss_bytes: u64,
+    /// Peak resident set size in bytes.
+    pub peak_rss_bytes: u64,
+    /// Number of open file descriptors.
+    pub open_fds: usize,
+}
+
+#[cfg(target_os = "linux")]
+impl ResourceUsage {
+    /// Returns the total CPU time, user plus system.
+    pub fn cpu_time(&self) -> Duration {
+        self.user_time + self.system_time
+    }
+}
+
+impl Sqlite3Process {
+    /// Returns the OS process ID of the child, or `None` once it has exited.
+    pub fn pid(&self) -> Option<u32> {
+        self.child.as_ref().map(std::process::Child::id)
+    }
+
+    /// Reports the child's CPU time, memory and open file descriptors.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the process has exited or `/proc` cannot be read.
+    ///
+    #[cfg(target_os = "linux")]
+    pub fn resource_usage(&self) -> Result<ResourceUsage, String> {
+        let pid = self.pid().ok_or("sqlite3 process has exited")?;
+        let read = |name: &str| {
+            fs::read_to_string(format!("/proc/{pid}/{name}"))
+                .map_err(|e| format!("Failed to read /proc/{pid}/{name}: {e}"))
+        };
+
+        let (user_ticks, system_ticks) = parse_cpu_ticks(&read("stat")?)?;
+        // SAFETY: `sysconf` has no memory-safety preconditions.
+        let ticks_per_second = match unsafe { libc::sysconf(libc::_SC_CLK_TCK) } {
+            ticks if ticks > 0 => ticks.unsigned_abs(),
+            _ => 100,
+        };
+        let ticks_to_duration =
+            |ticks: u64| Duration::from_secs_f64(ticks as f64 / ticks_per_second as f64);
+
+        let status = read("status")?;
+        let open_fds = fs::read_dir(format!("/proc/{pid}/fd"))
+            .map_err(|e| format!("Failed to read /proc/{pid}/fd: {e}"))?
+            .count();
+
+        Ok(ResourceUsage {
+            user_time: ticks_to_duration(user_ticks),
+            system_time: ticks_to_duration(system_ticks),
+            rss_bytes: status_kilobytes(&status, "VmRSS")? * 1024,
+            peak_rss_bytes: status_kilobytes(&status, "VmHWM")? * 1024,
+            open_fds,
+        })
+    }
+
+    /// Returns `true` if the child holds any fcntl lock on the main database file.
+    ///
+    /// In rollback-journal modes a process holds no lock on the database file
+    /// outside a transaction, so this can confirm that a `COMMIT` or `ROLLBACK`
+    /// really released its locks.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the process has exited, or the database file or
+    /// `/proc/locks` cannot be read.
+    ///
+    #[cfg(target_os = "linux")]
+    pub fn holds_db_lock(&self) -> Result<bool, String> {
+        let pid = self.pid().ok_or("sqlite3 process has exited")?;
+        let metadata = fs::metadata(&self.db_path)
+            .map_err(|e| format!("Failed to stat {}: {e}", self.db_path.display()))?;
+        let file = (
+            libc::major(metadata.dev()),
+            libc::minor(metadata.dev()),
+            metadata.ino(),
+        );
+        let locks = fs::read_to_string("/proc/locks")
+            .map_err(|e| format!("Failed to read /proc/locks: {e}"))?;
+        Ok(holds_lock(&locks, pid, file))
+    }
+}
+
+/// Extracts `utime` and `stime` from `/proc/<pid>/stat`.
+#[cfg(target_os = "linux")]
+fn parse_cpu_ticks(stat: &str) -> Result<(u64, u64), String> {
+    // The command name may contain spaces, so count fields after its `)`.
+    let fields: Vec<&str> = stat
+        .rsplit_once(')')
+        .map(|(_, rest)| rest.split_whitespace().collect())
+        .unwrap_or_default();
+    // `state` is field 3 of the file and `utime`/`stime` are 14 and 15.
+    let field = |index: usize| {
+        fields
+            .get(index - 3)
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| format!("Invalid /proc stat: {stat:?}"))
+    };
+    Ok((field(14)?, field(15)?))
+}
+
+/// Reads a `Name:   1234 kB` line from `/proc/<pid>/status`.
+#[cfg(target_os = "linux")]
+fn status_kilobytes(status: &str, name: &str) -> Result<u64, String> {
+    status
+        .lines()
+        .find_map(|line| {
+            let value = line.strip_prefix(name)?.strip_prefix(':')?;
+            value.split_whitespace().next()?.parse().ok()
+        })
+        .ok_or_else(|| format!("No {name} in /proc status"))
+}
+
+/// Returns `true` if `/proc/locks` lists a POSIX lock by `pid` on `file`,
+/// given as the major and minor device number and the inode.
+///
+/// On overlayfs and btrfs `stat` reports a different device than the one
+/// `/proc/locks` uses, so if none of `pid`'s locks are on `file`'s device,
+/// a lock on the same inode counts.
+#[cfg(target_os = "linux")]
+fn holds_lock(locks: &str, pid: u32, file: (u32, u32, u64)) -> bool {
+    let pid = pid.to_string();
+    let held: Vec<(u32, u32, u64)> = locks
+        .lines()
+        .filter_map(|line| {
+            // "1: POSIX  ADVISORY  WRITE 1234 fd:00:5678 1073741824 1073741824",
+            // with the device numbers in hex.
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.get(1) == Some(&"POSIX") && fields.get(4) == Some(&pid.as_str()) {
+                parse_lock_file(fields.get(5)?)
+            } else {
+                None
+            }
+        })
+        .collect();
+    let (major, minor, inode) = file;
+    if held.iter().any(|&(a, b, _)| (a, b) == (major, minor)) {
+        held.contains(&file)
+    } else {
+        held.iter().any(|&(_, _, i)| i == inode)
+    }
+}
+
+/// Parses the `MAJOR:MINOR:INODE` field of a `/proc/locks` line.
+#[cfg(target_os = "linux")]
+fn parse_lock_file(field: &str) -> Option<(u32, u32, u64)> {
+    let mut parts = field.split(':');
+    let major = u32::from_str_radix(parts.next()?, 16).ok()?;
+    let minor = u32::from_str_radix(parts.next()?, 16).ok()?;
+    let inode = parts.next()?.parse().ok()?;
+    Some((major, minor, inode))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_pid() {
+        let dir = tempdir().unwrap();
+        let process = Sqlite3Process::new(&dir.path().join("test.db")).unwrap();
+
+        let pid = process.pid().unwrap();
+        assert_eq!(Some(pid), process.child.as_ref().map(|child| child.id()));
+        assert_ne!(pid, std::process::id());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_parse_proc_files() {
+        let stat = "42 (sqlite3 (odd)) S 1 42 42 0 -1 4194560 300 0 0 0 7 3 0 0 20 0 1 0";
+        assert_eq!(parse_cpu_ticks(stat), Ok((7, 3)));
+        assert!(parse_cpu_ticks("garbage").is_err());
+
+        let status = "Name:\tsqlite3\nVmHWM:\t    4096 kB\nVmRSS:\t    2048 kB\n";
+        assert_eq!(status_kilobytes(status, "VmHWM"), Ok(4096));
+        assert_eq!(status_kilobytes(status, "VmRSS"), Ok(2048));
+        assert!(status_kilobytes(status, "VmSwap").is_err());
+
+        let locks = "1: POSIX  ADVISORY  WRITE 42 fd:00:5678 1073741825 1073741825\n\
+                     2: FLOCK  ADVISORY  WRITE 7 fd:00:9999 0 EOF\n\
+                     3: POSIX  ADVISORY  READ 42 08:01:4321 0 EOF\n";
+        assert!(holds_lock(locks, 42, (0xfd, 0, 5678)));
+        assert!(holds_lock(locks, 42, (8, 1, 4321)));
+        assert!(!holds_lock(locks, 42, (8, 1, 5678)));
+        assert!(!holds_lock(locks, 42, (0xfd, 0, 9999)));
+        assert!(!holds_lock(locks, 7, (0xfd, 0, 9999)));
+        // On overlayfs `stat` reports another device, so the inode decides.
+        assert!(holds_lock(locks, 42, (0x2a, 3, 5678)));
+        assert!(!holds_lock(locks, 42, (0x2a, 3, 9999)));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_resource_usage() {
+        let dir = tempdir().unwrap();
+        let mut process = Sqlite3Process::new(&dir.path().join("test.db")).unwrap();
+        process
+            .execute(
+                "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 3000000) \
+                 SELECT COUNT(*) FROM n;",
+            )
+            .unwrap();
+
+        let usage = process.resource_usage().unwrap();
+        assert!(usage.cpu_time() > Duration::ZERO, "{usage:?}");
+        assert!(usage.rss_bytes > 0);
+        assert!(usage.peak_rss_bytes >= usage.rss_bytes);
+        // stdin, stdout, stderr and the database.
+        assert!(usage.open_fds >= 4, "{usage:?}");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_holds_db_lock_until_commit() {
+        let dir = tempdir().unwrap();
+        let mut process = Sqlite3Process::new(&dir.path().join("test.db")).unwrap();
+        process.execute_checked("CREATE TABLE t (x);").unwrap();
+        assert!(!process.holds_db_lock().unwrap());
+
+        process
+            .execute_checked("BEGIN IMMEDIATE; INSERT INTO t VALUES (1);")
+            .unwrap();
+        assert!(process.holds_db_lock().unwrap());
+
+        process.execute_checked("COMMIT;").unwrap();
+        assert!(!process.holds_db_lock().unwrap());
+    }
+}
@@ -0,0 +1,171 @@
+//! In-memory record of everything a sqlite3 process was asked to run.
+
+use std::fmt;
+// Always the real clock: these are measurements, not timeouts, and tests that
+// move the mock clock must not disturb transcripts recorded meanwhile.
+use std::time::{Duration, Instant};
+
+/// One command sent to a sqlite3 process and what came back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TranscriptEntry {
+    /// Time between spawning the process and sending the command.
+    pub at: Duration,
+    /// How long the command took until its output was complete.
+    pub duration: Duration,
+    /// The SQL or dot-command text, as sent.
+    pub command: String,
+    /// What the command printed on stdout, or why reading it failed.
+    pub output: Result<String, String>,
+    /// What the command printed on stderr.
+    pub stderr: String,
+}
+
+/// The commands a [`Sqlite3Process`](super::Sqlite3Process) has run, in order.
+///
+/// Rendered with [`Display`](fmt::Display), which is what gets printed when a
+/// test panics with transcript dumping enabled.
+#[derive(Debug, Clone)]
+pub struct Transcript {
+    label: String,
+    spawned_at: Instant,
+    entries: Vec<TranscriptEntry>,
+}
+
+impl Transcript {
+    /// Starts an empty transcript for the process described by `label`.
+    pub(super) fn new(label: String) -> Self {
+        Transcript {
+            label,
+            spawned_at: Instant::now(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Returns the recorded entries, oldest first.
+    pub fn entries(&self) -> &[TranscriptEntry] {
+        &self.entries
+    }
+
+    /// Marks the start of a command, returning the offset to pass to [`record`](Self::record).
+    pub(super) fn start(&self) -> (Duration, Instant) {
+        let now = Instant::now();
+        (now.duration_since(self.spawned_at), now)
+    }
+
+    /// Appends the result of a command started at `start`.
+    pub(super) fn record(
+        &mut self,
+        start: (Duration, Instant),
+        command: &str,
+        output: &Result<String, String>,
+        stderr: &str,
+    ) {
+        let (at, started) = start;
+        self.entries.push(TranscriptEntry {
+            at,
+            duration: started.elapsed(),
+            command: command.to_string(),
+            output: output.clone(),
+            stderr: stderr.to_string(),
+        });
+    }
+
+    /// Sets the description printed in the transcript header.
+    pub(super) fn set_label(&mut self, label: String) {
+        self.label = label;
+    }
+}
+
+impl fmt::Display for Transcript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "--- sqlite3 transcript for {} ---", self.label)?;
+        for entry in &self.entries {
+            writeln!(
+                f,
+                "[+{:.3}s, {:.1}ms]",
+                entry.at.as_secs_f64(),
+                entry.duration.as_secs_f64() * 1000.0
+            )?;
+            for line in entry.command.lines() {
+                writeln!(f, "sqlite> {line}")?;
+            }
+            match entry.output {
+                Ok(ref output) => write!(f, "{output}")?,
+                Err(ref error) => writeln!(f, "[error] {error}")?,
+            }
+            for line in entry.stderr.lines() {
+                writeln!(f, "[stderr] {line}")?;
+            }
+        }
+        write!(
+            f,
+            "--- end of transcript ({} commands) ---",
+            self.entries.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Sqlite3Process;
+    use tempfile::tempdir;
+
+    fn entry(command: &str, output: Result<&str, &str>, stderr: &str) -> TranscriptEntry {
+        TranscriptEntry {
+            at: Duration::from_millis(1500),
+            duration: Duration::from_micros(2500),
+            command: command.to_string(),
+            output: output.map(str::to_string).map_err(str::to_string),
+            stderr: stderr.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let mut transcript = Transcript::new("test.db".to_string());
+        transcript.entries = vec![
+            entry("SELECT 1;\nSELECT 2;", Ok("1\n2\n"), ""),
+            entry("SELECT x;", Ok(""), "Parse error: no such column: x\n"),
+            entry(".exit", Err("Failed to read"), ""),
+        ];
+
+        assert_eq!(
+            transcript.to_string(),
+            "--- sqlite3 transcript for test.db ---\n\
+             [+1.500s, 2.5ms]\n\
+             sqlite> SELECT 1;\n\
+             sqlite> SELECT 2;\n\
+             1\n\
+             2\n\
+             [+1.500s, 2.5ms]\n\
+             sqlite> SELECT x;\n\
+             [stderr] Parse error: no such column: x\n\
+             [+1.500s, 2.5ms]\n\
+             sqlite> .exit\n\
+             [error] Failed to read\n\
+             --- end of transcript (3 commands) ---"
+        );
+    }
+
+    #[test]
+    fn test_process_records_commands() {
+        let dir = tempdir().unwrap();
+        let mut process = Sqlite3Process::new(&dir.path().join("test.db")).unwrap();
+        let startup_entries = process.transcript().entries().len();
+
+        process.execute("SELECT 40 + 2;").unwrap();
+        process.execute("SELECT * FROM missing;").unwrap();
+
+        let entries = &process.transcript().entries()[startup_entries..];
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].command, "SELECT 40 + 2;");
+        assert_eq!(entries[0].output, Ok("42\n".to_string()));
+        assert!(entries[0].stderr.is_empty());
+        assert!(entries[1].stderr.contains("no such table: missing"));
+        assert!(process
+            .transcript()
+            .to_string()
+            .contains("sqlite> SELECT 40 + 2;\n42\n"));
+    }
+}
@@ -0,0 +1,473 @@
+//! A sqlite3 shell stand-in that runs inside the test binary on rusqlite.
+//!
+//! [`Sqlite3ProcessBuilder::self_hosted`](crate::Sqlite3ProcessBuilder::self_hosted)
+//! re-executes the current test binary with [`WORKER_DB_ENV`] set, and the test
+//! declared by [`sqlite_test_worker!`](crate::sqlite_test_worker) turns that
+//! process into a worker. The worker reads SQL from stdin and answers the way
+//! the sqlite3 shell does in the output mode [`Sqlite3Process`](crate::Sqlite3Process)
+//! pins, so the same process API works against the SQLite library linked into
+//! rusqlite.
+
+use std::collections::HashMap;
+use std::env;
+use std::ffi::CString;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use fallible_iterator::FallibleIterator;
+use rusqlite::types::Value;
+use rusqlite::{Batch, Connection, Statement};
+
+use crate::Barrier;
+
+/// Environment variable holding the database path of a self-hosted worker.
+pub const WORKER_DB_ENV: &str = "SQLITE_TEST_UTILS_WORKER_DB";
+
+/// Name of the test declared by [`sqlite_test_worker!`](crate::sqlite_test_worker),
+/// used as the test filter when re-executing the test binary.
+pub(crate) const WORKER_TEST_NAME: &str = "sqlite_test_utils_worker";
+
+/// Declares the test that lets the test binary act as a self-hosted worker.
+///
+/// Invoke it once in every test binary that uses
+/// [`Sqlite3ProcessBuilder::self_hosted`](crate::Sqlite3ProcessBuilder::self_hosted).
+/// When run normally the declared test does nothing; in a re-executed worker it
+/// serves the database and exits the process.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use sqlite_test_utils::{sqlite_test_worker, Sqlite3Process};
+///
+/// sqlite_test_worker!();
+///
+/// #[test]
+/// fn locking_uses_the_linked_sqlite() {
+///     let dir = tempfile::tempdir().unwrap();
+///     let mut process = Sqlite3Process::builder()
+///         .self_hosted()
+///         .spawn(&dir.path().join("test.db"))
+///         .unwrap();
+///     assert_eq!(process.sqlite_version().unwrap(), rusqlite::version());
+/// }
+/// ```
+#[macro_export]
+macro_rules! sqlite_test_worker {
+    () => {
+        #[test]
+        fn sqlite_test_utils_worker() {
+            $crate::run_worker_if_requested();
+        }
+    };
+}
+
+/// Serves the database named by [`WORKER_DB_ENV`] and exits, if it is set.
+///
+/// Called by the test that [`sqlite_test_worker!`](crate::sqlite_test_worker)
+/// declares; returns immediately in a normal test run.
+#[doc(hidden)]
+pub fn run_worker_if_requested() {
+    let Some(db_path) = env::var_os(WORKER_DB_ENV) else {
+        return;
+    };
+    let stdin = io::stdin();
+    let code = match run_worker(
+        Path::new(&db_path),
+        stdin.lock(),
+        io::stdout(),
+        io::stderr(),
+    ) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("Worker failed: {e}");
+            1
+        }
+    };
+    // Exit before the test harness prints its summary to our stdout.
+    std::process::exit(code);
+}
+
+/// Runs shell input from `input` against `db_path` until `.exit` or end of input.
+fn run_worker(
+    db_path: &Path,
+    input: impl BufRead,
+    mut out: impl Write,
+    mut err: impl Write,
+) -> io::Result<()> {
+    let mut worker = Worker::open(db_path).map_err(io::Error::other)?;
+    let mut buffer = String::new();
+    for line in input.lines() {
+        let line = line?;
+        if buffer.is_empty() && line.trim_start().starts_with('.') {
+            if !worker.dot_command(line.trim(), &mut err)? {
+                break;
+            }
+        } else {
+            buffer.push_str(&line);
+            buffer.push('\n');
+            if is_complete(&buffer) {
+                worker.run_sql(&std::mem::take(&mut buffer), &mut out, &mut err)?;
+            }
+        }
+        out.flush()?;
+        err.flush()?;
+    }
+    out.flush()
+}
+
+/// A connection plus the shell state the process API relies on.
+struct Worker {
+    conn: Connection,
+    parameters: HashMap<String, String>,
+}
+
+impl Worker {
+    fn open(db_path: &Path) -> rusqlite::Result<Self> {
+        let conn = Connection::open(db_path)?;
+        // The shell does not retry on a busy database unless `.timeout` is set.
+        conn.busy_timeout(Duration::ZERO)?;
+        Ok(Worker {
+            conn,
+            parameters: HashMap::new(),
+        })
+    }
+
+    /// Handles a dot-command; returns `false` if the worker should exit.
+    fn dot_command(&mut self, line: &str, err: &mut impl Write) -> io::Result<bool> {
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        match name {
+            ".exit" | ".quit" => return Ok(false),
+            ".open" => {
+                let path = unquote_arg(line[name.len()..].trim());
+                match Worker::open(Path::new(&path)) {
+                    Ok(worker) => *self = worker,
+                    Err(e) => writeln!(err, "Error: unable to open database \"{path}\": {e}")?,
+                }
+            }
+            // Output settings `Sqlite3Process` pins at startup; the worker
+            // always prints in that format.
+            ".mode" | ".headers" | ".separator" | ".nullvalue" | ".timer" | ".echo"
+            | ".changes" | ".stats" | ".eqp" => {}
+            ".timeout" => {
+                let millis = words.next().and_then(|ms| ms.parse().ok()).unwrap_or(0);
+                if let Err(e) = self.conn.busy_timeout(Duration::from_millis(millis)) {
+                    writeln!(err, "Error: {e}")?;
+                }
+            }
+            ".gate" => {
+                // `.gate DIR STEP POLLS`, as written by `Barrier::worker_gate_command`.
+                let mut args = line[name.len()..].trim().rsplitn(3, char::is_whitespace);
+                match (args.next().map(str::parse), args.next(), args.next()) {
+                    (Some(Ok(polls)), Some(step), Some(dir)) => {
+                        let dir = unquote_arg(dir.trim());
+                        if let Err(e) = Barrier::from_gate_args(Path::new(&dir), polls).gate(step) {
+                            writeln!(err, "Error: {e}")?;
+                        }
+                    }
+                    _ => writeln!(err, "Usage: .gate DIR STEP POLLS")?,
+                }
+            }
+            ".parameter" => match words.next() {
+                Some("init") => {}
+                Some("clear") => self.parameters.clear(),
+                Some("set") => {
+                    // `.parameter set ?N VALUE`, where VALUE is a SQL literal.
+                    let rest = line.split_once("set").map_or("", |(_, rest)| rest).trim();
+                    match rest.split_once(char::is_whitespace) {
+                        Some((key, value)) => {
+                            self.parameters
+                                .insert(key.to_string(), value.trim().to_string());
+                        }
+                        None => writeln!(err, "Usage: .parameter set NAME VALUE")?,
+                    }
+                }
+                _ => writeln!(err, "Usage: .parameter CMD ...")?,
+            },
+            _ => writeln!(
+                err,
+                "Error: unknown command or invalid arguments:  \"{}\". Enter \".help\" for help",
+                name.trim_start_matches('.')
+            )?,
+        }
+        Ok(true)
+    }
+
+    /// Runs every statement in `sql`, printing rows in list mode.
+    ///
+    /// Like the shell, stops at the first failing statement of the input.
+    fn run_sql(&self, sql: &str, out: &mut impl Write, err: &mut impl Write) -> io::Result<()> {
+        let mut batch = Batch::new(&self.conn, sql);
+        loop {
+            let mut statement = match batch.next() {
+                Ok(Some(statement)) => statement,
+                Ok(None) => return Ok(()),
+                Err(e) => return writeln!(err, "Parse error: {}", error_message(&e)),
+            };
+            if let Err(e) = self.bind_parameters(&mut statement) {
+                return writeln!(err, "Runtime error: {}", error_message(&e));
+            }
+            if let Err(e) = print_rows(&mut statement, out)? {
+                return writeln!(err, "Runtime error: {}", error_message(&e));
+            }
+        }
+    }
+
+    /// Binds the values set with `.parameter set` to the statement's parameters.
+    fn bind_parameters(&self, statement: &mut Statement<'_>) -> rusqlite::Result<()> {
+        for index in 1..=statement.parameter_count() {
+            let Some(literal) = statement
+                .parameter_name(index)
+                .and_then(|name| self.parameters.get(name))
+            else {
+                continue;
+            };
+            // The shell evaluates the literal as an expression, so do the same.
+            let value: Value = self
+                .conn
+                .query_row(&format!("SELECT {literal}"), [], |row| row.get(0))?;
+            statement.raw_bind_parameter(index, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs every statement in `sql` on `conn`, returning the rows in list mode.
+///
+/// Stops at the first failing statement and returns SQLite's message for it,
+/// so an in-process connection answers like a sqlite3 process.
+pub(crate) fn query_list_mode(conn: &Connection, sql: &str) -> Result<String, String> {
+    let mut output = Vec::new();
+    let mut batch = Batch::new(conn, sql);
+    while let Some(mut statement) = batch.next().map_err(|e| error_message(&e))? {
+        print_rows(&mut statement, &mut output)
+            .map_err(|e| e.to_string())?
+            .map_err(|e| error_message(&e))?;
+    }
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
+
+/// Steps a statement, printing each row as `|`-separated values.
+///
+/// The outer error is an I/O failure; the inner one a SQLite error.
+fn print_rows(
+    statement: &mut Statement<'_>,
+    out: &mut impl Write,
+) -> io::Result<rusqlite::Result<()>> {
+    let columns = statement.column_count();
+    let mut rows = statement.raw_query();
+    loop {
+        let row = match rows.next() {
+            Ok(Some(row)) => row,
+            Ok(None) => return Ok(Ok(())),
+            Err(e) => return Ok(Err(e)),
+        };
+        let mut line = Vec::new();
+        for column in 0..columns {
+            if column > 0 {
+                line.push(b'|');
+            }
+            match row.get_ref(column) {
+                Ok(value) => write_value(&mut line, value.into()),
+                Err(e) => return Ok(Err(e)),
+            }
+        }
+        line.push(b'\n');
+        out.write_all(&line)?;
+    }
+}
+
+/// Appends a value the way the shell prints it in list mode.
+fn write_value(line: &mut Vec<u8>, value: Value) {
+    match value {
+        Value::Null => {}
+        Value::Integer(integer) => line.extend_from_slice(integer.to_string().as_bytes()),
+        Value::Real(real) => line.extend_from_slice(format_real(real).as_bytes()),
+        Value::Text(text) => line.extend_from_slice(text.as_bytes()),
+        Value::Blob(blob) => line.extend_from_slice(&blob),
+    }
+}
+
+/// Formats a real the way the shell prints it, with SQLite's `%!.15g`.
+///
+/// That is 15 significant digits without trailing zeros, but always with a
+/// digit after the decimal point, and an exponent of at least two digits.
+pub(crate) fn format_real(real: f64) -> String {
+    if real.is_infinite() {
+        return if real > 0.0 { "Inf" } else { "-Inf" }.to_string();
+    }
+    if real == 0.0 {
+        return "0.0".to_string();
+    }
+    // Rounding to 15 digits first decides between the two notations, as `%g` does.
+    let scientific = format!("{real:.14e}");
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    let trim = |digits: &str| {
+        if !digits.contains('.') {
+            return format!("{digits}.0");
+        }
+        let trimmed = digits.trim_end_matches('0');
+        if trimmed.ends_with('.') {
+            format!("{trimmed}0")
+        } else {
+            trimmed.to_string()
+        }
+    };
+    if (-4..15).contains(&exponent) {
+        let decimals = usize::try_from(14 - exponent).unwrap_or(0);
+        trim(&format!("{real:.decimals$}"))
+    } else {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{sign}{:02}", trim(mantissa), exponent.abs())
+    }
+}
+
+/// Reads a dot-command argument, undoing the shell's double-quote escapes.
+fn unquote_arg(arg: &str) -> String {
+    let Some(quoted) = arg.strip_prefix('"') else {
+        return arg
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_string();
+    };
+    let mut unquoted = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => break,
+            '\\' => match chars.next() {
+                Some('n') => unquoted.push('\n'),
+                Some('r') => unquoted.push('\r'),
+                Some(escaped) => unquoted.push(escaped),
+                None => break,
+            },
+            _ => unquoted.push(c),
+        }
+    }
+    unquoted
+}
+
+/// Returns SQLite's message for an error, without rusqlite's decoration.
+fn error_message(error: &rusqlite::Error) -> String {
+    match error {
+        rusqlite::Error::SqliteFailure(_, Some(message)) => message.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Returns `true` if `sql` ends with a complete statement, as the shell decides it.
+fn is_complete(sql: &str) -> bool {
+    let Ok(sql) = CString::new(sql) else {
+        return true;
+    };
+    // SAFETY: `sql` is a valid NUL-terminated string for the duration of the call.
+    unsafe { rusqlite::ffi::sqlite3_complete(sql.as_ptr()) != 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{init_test_db, read_row, Sqlite3Process};
+    use tempfile::tempdir;
+
+    fn run(input: &str) -> (String, String) {
+        let dir = tempdir().unwrap();
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        run_worker(
+            &dir.path().join("test.db"),
+            input.as_bytes(),
+            &mut out,
+            &mut err,
+        )
+        .unwrap();
+        (
+            String::from_utf8(out).unwrap(),
+            String::from_utf8(err).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_worker_speaks_list_mode() {
+        let (out, err) = run("\
+.mode list
+CREATE TABLE t (a, b);
+INSERT INTO t VALUES (1, 'x'), (2.5, NULL),
+  (X'6869', 'y');
+SELECT * FROM t;SELECT 'MARKER_END';
+.parameter set ?1 'it''s'
+SELECT ?1
+;SELECT * FROM missing;
+.parameter clear
+.bogus
+.exit
+SELECT 'not reached';
+");
+
+        assert_eq!(out, "1|x\n2.5|\nhi|y\nMARKER_END\nit's\n");
+        assert_eq!(
+            err,
+            "Parse error: no such table: missing\n\
+             Error: unknown command or invalid arguments:  \"bogus\". Enter \".help\" for help\n"
+        );
+    }
+
+    #[test]
+    fn test_self_hosted_process_uses_linked_sqlite() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+        let mut process = Sqlite3Process::builder()
+            .self_hosted()
+            .spawn(&db_path)
+            .unwrap();
+
+        assert_eq!(process.sqlite_version().unwrap(), rusqlite::version());
+        init_test_db(&mut process, "main", 42, 10, 5).unwrap();
+        process.execute_checked("BEGIN IMMEDIATE;").unwrap();
+
+        let conn = Connection::open(&db_path).unwrap();
+        conn.busy_timeout(Duration::ZERO).unwrap();
+        assert!(conn.execute("DELETE FROM notes", []).is_err());
+
+        process.execute_checked("COMMIT;").unwrap();
+        process.reopen().unwrap();
+        assert_eq!(
+            read_row(&conn, "main", 3).unwrap(),
+            read_row(&mut process, "main", 3).unwrap()
+        );
+        assert!(process.close().unwrap().success());
+    }
+
+    #[test]
+    fn test_format_real_matches_shell() {
+        // As printed by `sqlite3` in list mode.
+        let expected = [
+            (100.0, "100.0"),
+            (0.1, "0.1"),
+            (1e300, "1.0e+300"),
+            (-2.5e-7, "-2.5e-07"),
+            (f64::INFINITY, "Inf"),
+            (1.0 / 3.0, "0.333333333333333"),
+            (123_456_789_012_345_680.0, "1.23456789012346e+17"),
+            (1e15, "1.0e+15"),
+            (1e14, "100000000000000.0"),
+            (0.0001, "0.0001"),
+            (0.00001, "1.0e-05"),
+            (0.0, "0.0"),
+            (12345.678, "12345.678"),
+        ];
+        for (real, printed) in expected {
+            assert_eq!(format_real(real), printed);
+        }
+    }
+
+    #[test]
+    fn test_unquote_arg() {
+        assert_eq!(unquote_arg("plain.db extra"), "plain.db");
+        assert_eq!(unquote_arg(r#""dir\\it \"x\".db""#), r#"dir\it "x".db"#);
+        assert_eq!(unquote_arg(r#""a\nb""#), "a\nb");
+    }
+}
@@ -0,0 +1,708 @@
+//! Seeded random insert/update/read workloads run from several processes at once.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{self, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rusqlite::types::Value;
+use rusqlite::Connection;
+
+use crate::{
+    create_note, init_test_db, set_journal_mode, Executor, ProcessPool, Sqlite3ProcessBuilder,
+};
+
+mod busy;
+mod consistency;
+mod recording;
+mod shrink;
+mod stats;
+
+pub use busy::BusyStrategy;
+pub use recording::{RecordedOp, ReplayMode, WorkloadRecording};
+pub use stats::{LatencyHistogram, OpStats, WorkloadReport};
+
+/// The kinds of operation a [`Workload`] runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum OpKind {
+    /// Inserts a new note, like [`insert_test_db`](crate::insert_test_db).
+    Insert,
+    /// Replaces the text of a note, like [`update_test_db`](crate::update_test_db).
+    Update,
+    /// Reads a note, like [`read_row`](crate::read_row).
+    Read,
+}
+
+impl fmt::Display for OpKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OpKind::Insert => "insert",
+            OpKind::Update => "update",
+            OpKind::Read => "read",
+        })
+    }
+}
+
+/// An operation that succeeded during a [`Workload`] run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommittedOp {
+    /// Index of the worker that ran it.
+    pub worker: usize,
+    /// What it did.
+    pub kind: OpKind,
+    /// The row it inserted, updated or read.
+    pub id: i64,
+    /// The text it wrote, or the text it read.
+    pub text: String,
+    /// When its successful attempt was sent, from the start of the run.
+    pub started: Duration,
+    /// When its successful attempt returned, from the start of the run.
+    pub finished: Duration,
+}
+
+/// When each worker stops.
+#[derive(Debug, Clone, Copy)]
+enum Limit {
+    Operations(usize),
+    Duration(Duration),
+}
+
+/// A seeded random mix of inserts, updates and reads on the `notes` table,
+/// run concurrently by several sqlite3 processes or threads.
+///
+/// The database is initialized with [`init_test_db`], then each worker drives
+/// its own process, or with [`threads`](Self::threads) its own in-process
+/// connection, through operations drawn from its own seeded generator, so the
+/// same seed produces the same operations per worker. Running the same seed
+/// both ways compares cross-process with in-process locking. How a worker
+/// waits for locks is set by its [`BusyStrategy`]; an operation that still
+/// fails with `SQLITE_BUSY` is retried after a short random backoff.
+///
+/// At the end the reads and the final table are checked against the log of
+/// committed operations, and the report gives latency and lock wait
+/// histograms, busy and retry counts and throughput per operation kind. With
+/// [`record`](Self::record), every statement attempt is also written to a
+/// [`WorkloadRecording`] that can be replayed to reproduce a failure.
+///
+/// # Example
+///
+/// ```rust
+/// use sqlite_test_utils::Workload;
+///
+/// let dir = tempfile::tempdir().unwrap();
+/// let report = Workload::new(42)
+///     .workers(3)
+///     .ops_per_worker(20)
+///     .run(&dir.path().join("test.db"))
+///     .unwrap();
+///
+/// assert_eq!(report.total().ok + report.total().failed, 60);
+/// println!("{report}");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Workload {
+    seed: u64,
+    workers: usize,
+    limit: Limit,
+    mix: [(OpKind, u32); 3],
+    initial_rows: usize,
+    word_count: usize,
+    max_retries: u32,
+    max_backoff: Duration,
+    busy_strategy: BusyStrategy,
+    worker_busy_strategies: BTreeMap<usize, BusyStrategy>,
+    in_process: bool,
+    journal_mode: Option<String>,
+    process: Sqlite3ProcessBuilder,
+    record: Option<PathBuf>,
+}
+
+impl Workload {
+    /// Creates a workload of 4 workers running 100 operations each, drawn
+    /// from `seed`.
+    pub fn new(seed: u64) -> Self {
+        Workload {
+            seed,
+            workers: 4,
+            limit: Limit::Operations(100),
+            mix: [(OpKind::Insert, 2), (OpKind::Update, 4), (OpKind::Read, 4)],
+            initial_rows: 100,
+            word_count: 10,
+            max_retries: 100,
+            max_backoff: Duration::from_millis(2),
+            busy_strategy: BusyStrategy::Retry,
+            worker_busy_strategies: BTreeMap::new(),
+            in_process: false,
+            journal_mode: None,
+            process: Sqlite3ProcessBuilder::new(),
+            record: None,
+        }
+    }
+
+    /// Sets the number of concurrent workers.
+    #[must_use]
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers;
+        self
+    }
+
+    /// Makes each worker run `ops` operations, replacing any duration limit.
+    #[must_use]
+    pub fn ops_per_worker(mut self, ops: usize) -> Self {
+        self.limit = Limit::Operations(ops);
+        self
+    }
+
+    /// Makes each worker run for `duration`, replacing any operation limit.
+    #[must_use]
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.limit = Limit::Duration(duration);
+        self
+    }
+
+    /// Sets the relative weights of inserts, updates and reads.
+    #[must_use]
+    pub fn mix(mut self, insert: u32, update: u32, read: u32) -> Self {
+        self.mix = [
+            (OpKind::Insert, insert),
+            (OpKind::Update, update),
+            (OpKind::Read, read),
+        ];
+        self
+    }
+
+    /// Sets the number of rows created before the workers start.
+    #[must_use]
+    pub fn initial_rows(mut self, rows: usize) -> Self {
+        self.initial_rows = rows;
+        self
+    }
+
+    /// Sets the maximum number of words per note.
+    #[must_use]
+    pub fn word_count(mut self, word_count: usize) -> Self {
+        self.word_count = word_count;
+        self
+    }
+
+    /// Sets how often a busy operation is retried before it counts as failed.
+    #[must_use]
+    pub fn max_retries(mut self, retries: u32) -> Self {
+        self.max_retries = retries;
+        self
+    }
+
+    /// Sets the longest random pause before retrying a busy operation.
+    #[must_use]
+    pub fn max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Sets the busy timeout of every worker, like
+    /// `busy_strategy(BusyStrategy::Timeout(timeout))`.
+    #[must_use]
+    pub fn busy_timeout(self, timeout: Duration) -> Self {
+        self.busy_strategy(BusyStrategy::Timeout(timeout))
+    }
+
+    /// Sets how workers wait for locks, unless set per worker.
+    ///
+    /// Defaults to [`BusyStrategy::Retry`], so contention shows up as
+    /// `SQLITE_BUSY` and retries rather than as latency.
+    #[must_use]
+    pub fn busy_strategy(mut self, strategy: BusyStrategy) -> Self {
+        self.busy_strategy = strategy;
+        self
+    }
+
+    /// Sets how worker `worker` (counting from 0) waits for locks.
+    #[must_use]
+    pub fn worker_busy_strategy(mut self, worker: usize, strategy: BusyStrategy) -> Self {
+        self.worker_busy_strategies.insert(worker, strategy);
+        self
+    }
+
+    /// Runs the workers as threads, each with its own rusqlite connection,
+    /// instead of as sqlite3 processes.
+    #[must_use]
+    pub fn threads(mut self) -> Self {
+        self.in_process = true;
+        self
+    }
+
+    /// Sets the journal mode of every worker.
+    #[must_use]
+    pub fn journal_mode(mut self, mode: &str) -> Self {
+        self.journal_mode = Some(mode.to_string());
+        self
+    }
+
+    /// Spawns the worker processes with `builder`. Ignored for threads.
+    #[must_use]
+    pub fn process(mut self, builder: Sqlite3ProcessBuilder) -> Self {
+        self.process = builder;
+        self
+    }
+
+    /// Records every statement attempt to `path` as a [`WorkloadRecording`].
+    ///
+    /// The file is written once the workers stop, before the final state is
+    /// checked, so it is there when the run fails.
+    #[must_use]
+    pub fn record(mut self, path: &Path) -> Self {
+        self.record = Some(path.to_path_buf());
+        self
+    }
+
+    /// Initializes the database at `db_path` and runs the workload on it.
+    ///
+    /// The database must not have a `notes` table yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be initialized, a worker
+    /// cannot be started, an operation fails with anything but
+    /// `SQLITE_BUSY`, the recording cannot be written, or the final state is
+    /// inconsistent with the committed operations. Consistency errors end
+    /// with the report.
+    ///
+    pub fn run(&self, db_path: &Path) -> Result<WorkloadReport, String> {
+        if self.mix.iter().all(|(_, weight)| *weight == 0) {
+            return Err("Workload mix has no operations".to_string());
+        }
+        let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+        init_test_db(&conn, "main", self.seed, self.initial_rows, self.word_count)
+            .map_err(|e| format!("Failed to initialize workload database: {e}"))?;
+        let initial = read_notes(&conn)?;
+
+        let recorder = self.record.as_ref().map(|_| Mutex::new(Vec::new()));
+        let start = Instant::now();
+        let outcomes = if self.in_process {
+            self.run_threads(db_path, start, recorder.as_ref())?
+        } else {
+            self.run_processes(db_path, start, recorder.as_ref())?
+        };
+        let elapsed = start.elapsed();
+        if let (Some(path), Some(recorder)) = (&self.record, recorder) {
+            self.recording(recorder.into_inner().unwrap()).save(path)?;
+        }
+
+        let mut report = WorkloadReport {
+            workers: self.workers,
+            in_process: self.in_process,
+            elapsed,
+            stats: BTreeMap::new(),
+            log: Vec::new(),
+        };
+        for (worker, outcome) in outcomes.into_iter().enumerate() {
+            let outcome = outcome.map_err(|e| format!("worker {worker}: {e}"))?;
+            report.add(&outcome.stats);
+            report.log.extend(outcome.log);
+        }
+        report.log.sort_by_key(|op| op.finished);
+
+        consistency::check(&report.log, &initial, &read_notes(&conn)?, elapsed)
+            .map_err(|e| format!("{e}\n{report}"))?;
+        Ok(report)
+    }
+
+    /// Runs every worker on its own sqlite3 process.
+    fn run_processes(
+        &self,
+        db_path: &Path,
+        start: Instant,
+        recorder: Option<&Recorder>,
+    ) -> Result<Vec<Result<WorkerOutcome, String>>, String> {
+        let mut pool = ProcessPool::builder()
+            .size(self.workers)
+            .process(self.process.clone());
+        if let Some(ref mode) = self.journal_mode {
+            pool = pool.journal_mode(mode);
+        }
+        let mut processes = pool.spawn(db_path)?.into_processes();
+        for (worker, process) in processes.iter_mut().enumerate() {
+            self.strategy(worker)
+                .apply_to_process(process)
+                .map_err(|e| format!("worker {worker}: {e}"))?;
+        }
+
+        Ok(self.spawn_workers(processes, |worker, mut process| {
+            let outcome = self.run_worker(&mut process, worker, start, recorder);
+            // Closing, unlike dropping, does not log the busy errors left on stderr.
+            let closed = process.close();
+            outcome.and_then(|outcome| closed.map(|_| outcome))
+        }))
+    }
+
+    /// Runs every worker on its own thread and rusqlite connection.
+    fn run_threads(
+        &self,
+        db_path: &Path,
+        start: Instant,
+        recorder: Option<&Recorder>,
+    ) -> Result<Vec<Result<WorkerOutcome, String>>, String> {
+        let mut connections = Vec::with_capacity(self.workers);
+        for worker in 0..self.workers {
+            let conn = Connection::open(db_path).map_err(|e| format!("worker {worker}: {e}"))?;
+            if let Some(ref mode) = self.journal_mode {
+                set_journal_mode(&conn, mode, "main")
+                    .map_err(|e| format!("worker {worker}: {e}"))?;
+            }
+            connections.push(conn);
+        }
+
+        Ok(self.spawn_workers(connections, |worker, conn| {
+            // The busy handler keeps its state in thread-locals.
+            self.strategy(worker).apply_to_connection(&conn)?;
+            self.run_worker(&conn, worker, start, recorder)
+        }))
+    }
+
+    /// Runs `work` for each connection on its own thread, starting them together.
+    fn spawn_workers<C, F>(
+        &self,
+        connections: Vec<C>,
+        work: F,
+    ) -> Vec<Result<WorkerOutcome, String>>
+    where
+        C: Send,
+        F: Fn(usize, C) -> Result<WorkerOutcome, String> + Sync,
+    {
+        let ready = sync::Barrier::new(connections.len());
+        let work = &work;
+        thread::scope(|scope| {
+            let handles: Vec<_> = connections
+                .into_iter()
+                .enumerate()
+                .map(|(worker, conn)| {
+                    let ready = &ready;
+                    scope.spawn(move || {
+                        ready.wait();
+                        work(worker, conn)
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|_| Err("worker panicked".to_string()))
+                })
+                .collect()
+        })
+    }
+
+    /// Returns the busy strategy of worker `worker`.
+    fn strategy(&self, worker: usize) -> BusyStrategy {
+        self.worker_busy_strategies
+            .get(&worker)
+            .copied()
+            .unwrap_or(self.busy_strategy)
+    }
+
+    /// Wraps the recorded attempts with the settings needed to replay them.
+    fn recording(&self, mut ops: Vec<RecordedOp>) -> WorkloadRecording {
+        ops.sort_by_key(|op| op.started);
+        WorkloadRecording {
+            seed: self.seed,
+            workers: self.workers,
+            initial_rows: self.initial_rows,
+            word_count: self.word_count,
+            in_process: self.in_process,
+            journal_mode: self.journal_mode.clone(),
+            ops,
+        }
+    }
+
+    /// Runs one worker's operations on `conn` until its limit is reached.
+    fn run_worker<E>(
+        &self,
+        mut conn: E,
+        worker: usize,
+        start: Instant,
+        recorder: Option<&Recorder>,
+    ) -> Result<WorkerOutcome, String>
+    where
+        E: Executor,
+        E::Error: fmt::Display,
+    {
+        let mut rng = fastrand::Rng::with_seed(
+            self.seed ^ (worker as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15),
+        );
+        let mut ids: Vec<i64> = (1..=self.initial_rows as i64).collect();
+        let mut outcome = WorkerOutcome::default();
+
+        for done in 0.. {
+            let finished = match self.limit {
+                Limit::Operations(ops) => done >= ops,
+                Limit::Duration(duration) => start.elapsed() >= duration,
+            };
+            if finished {
+                break;
+            }
+            let (kind, id, text) = self.next_operation(&mut rng, &ids);
+            let (sql, params) = statement(kind, id, &text);
+            let stats = outcome.stats.entry(kind).or_default();
+            let first_attempt = Instant::now();
+            let mut attempt = 0;
+            busy::take_handler_wait();
+            let (committed, started, last_attempt) = loop {
+                let last_attempt = Instant::now();
+                let started = start.elapsed();
+                let result =
+                    run_statement(&mut conn, kind, sql, &params).map_err(|e| e.to_string());
+                // Before locking, so waiting on other workers does not count.
+                let finished = start.elapsed();
+                if let Some(recorder) = recorder {
+                    recorder.lock().unwrap().push(RecordedOp {
+                        worker,
+                        kind,
+                        sql: sql.to_string(),
+                        params: params.clone(),
+                        result: result.clone(),
+                        started,
+                        finished,
+                    });
+                }
+                match result {
+                    Ok(output) => break (Some(output), started, last_attempt),
+                    Err(e) if !e.contains("database is locked") => {
+                        return Err(format!("{kind} of row {id} failed: {e}"))
+                    }
+                    Err(_) => {
+                        stats.busy += 1;
+                        if attempt == self.max_retries {
+                            break (None, started, last_attempt);
+                        }
+                        attempt += 1;
+                        stats.retries += 1;
+                        let backoff = self.max_backoff.as_micros() as u64;
+                        thread::sleep(Duration::from_micros(fastrand::u64(..=backoff)));
+                    }
+                }
+            };
+            let Some(output) = committed else {
+                stats.failed += 1;
+                continue;
+            };
+            let (id, text) = match kind {
+                OpKind::Insert => (
+                    output
+                        .parse()
+                        .map_err(|_| format!("insert returned row ID {output:?}"))?,
+                    text,
+                ),
+                OpKind::Update => (id, text),
+                OpKind::Read => (id, output),
+            };
+            stats.ok += 1;
+            stats.latency.record(first_attempt.elapsed());
+            // Time lost to failed attempts, plus waiting inside SQLite.
+            stats
+                .lock_wait
+                .record(last_attempt - first_attempt + busy::take_handler_wait());
+            if kind == OpKind::Insert {
+                ids.push(id);
+            }
+            outcome.log.push(CommittedOp {
+                worker,
+                kind,
+                id,
+                text,
+                started,
+                finished: start.elapsed(),
+            });
+        }
+        Ok(outcome)
+    }
+
+    /// Draws the next operation: its kind, target row and text to write.
+    fn next_operation(&self, rng: &mut fastrand::Rng, ids: &[i64]) -> (OpKind, i64, String) {
+        let total: u32 = self.mix.iter().map(|(_, weight)| weight).sum();
+        let mut pick = rng.u32(..total);
+        let mut kind = OpKind::Insert;
+        for (candidate, weight) in self.mix {
+            if pick < weight {
+                kind = candidate;
+                break;
+            }
+            pick -= weight;
+        }
+        if ids.is_empty() {
+            kind = OpKind::Insert;
+        }
+        let id = if ids.is_empty() {
+            0
+        } else {
+            ids[rng.usize(..ids.len())]
+        };
+        // `create_note` draws from the thread's generator; seed it from ours so
+        // the note does not depend on how many retries came before.
+        fastrand::seed(rng.u64(..));
+        (kind, id, create_note(self.word_count))
+    }
+}
+
+/// What one worker did.
+#[derive(Default)]
+struct WorkerOutcome {
+    stats: BTreeMap<OpKind, OpStats>,
+    log: Vec<CommittedOp>,
+}
+
+/// Statement attempts shared by the workers of a recorded run.
+type Recorder = Mutex<Vec<RecordedOp>>;
+
+/// Returns the statement and parameters of an operation.
+fn statement(kind: OpKind, id: i64, text: &str) -> (&'static str, Vec<Value>) {
+    match kind {
+        OpKind::Insert => (
+            "INSERT INTO main.notes (text) VALUES (?1)",
+            vec![Value::Text(text.to_string())],
+        ),
+        OpKind::Update => (
+            "UPDATE main.notes SET text = ?1 WHERE id = ?2",
+            vec![Value::Text(text.to_string()), Value::Integer(id)],
+        ),
+        OpKind::Read => (
+            "SELECT text FROM main.notes WHERE id = ?1",
+            vec![Value::Integer(id)],
+        ),
+    }
+}
+
+/// Runs one attempt of an operation's statement.
+///
+/// # Returns
+///
+/// The new row ID of an insert, nothing for an update, or the text read,
+/// empty if the row does not exist.
+///
+fn run_statement<E: Executor>(
+    conn: &mut E,
+    kind: OpKind,
+    sql: &str,
+    params: &[Value],
+) -> Result<String, E::Error> {
+    match kind {
+        OpKind::Insert => {
+            conn.exec(sql, params)?;
+            Ok(conn.last_insert_rowid()?.to_string())
+        }
+        OpKind::Update => {
+            conn.exec(sql, params)?;
+            Ok(String::new())
+        }
+        OpKind::Read => Ok(conn.query_text(sql, params)?.unwrap_or_default()),
+    }
+}
+
+/// Reads every note by ID.
+fn read_notes(conn: &Connection) -> Result<BTreeMap<i64, String>, String> {
+    let mut statement = conn
+        .prepare("SELECT id, text FROM main.notes")
+        .map_err(|e| e.to_string())?;
+    let rows = statement
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(|e| e.to_string())?;
+    rows.collect::<rusqlite::Result<_>>()
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_contended_run_is_consistent() {
+        let dir = tempdir().unwrap();
+        let report = Workload::new(7)
+            .workers(4)
+            .ops_per_worker(40)
+            .initial_rows(20)
+            .run(&dir.path().join("test.db"))
+            .unwrap();
+
+        let total = report.total();
+        assert_eq!(total.ok + total.failed, 160, "{report}");
+        assert_eq!(total.busy, total.retries + total.failed);
+        assert_eq!(report.log().len() as u64, total.ok);
+        assert!(report.throughput() > 0.0);
+        assert!(report
+            .log()
+            .windows(2)
+            .all(|pair| pair[0].finished <= pair[1].finished));
+        let rendered = report.to_string();
+        assert!(
+            rendered.starts_with("--- workload: 4 processes"),
+            "{rendered}"
+        );
+        assert!(rendered.contains("\nupdate: "), "{rendered}");
+    }
+
+    fn sleepy_handler(count: i32) -> bool {
+        thread::sleep(Duration::from_millis(1));
+        count < 1000
+    }
+
+    #[test]
+    fn test_threads_with_busy_strategies() {
+        let dir = tempdir().unwrap();
+        let report = Workload::new(11)
+            .threads()
+            .workers(3)
+            .ops_per_worker(40)
+            .initial_rows(20)
+            .worker_busy_strategy(1, BusyStrategy::Timeout(Duration::from_millis(100)))
+            .worker_busy_strategy(2, BusyStrategy::Handler(sleepy_handler))
+            .run(&dir.path().join("test.db"))
+            .unwrap();
+
+        let total = report.total();
+        assert_eq!(total.ok + total.failed, 120, "{report}");
+        assert_eq!(total.lock_wait.count(), total.ok);
+        assert!(report.to_string().starts_with("--- workload: 3 threads"));
+
+        let error = Workload::new(11)
+            .workers(1)
+            .busy_strategy(BusyStrategy::Handler(sleepy_handler))
+            .run(&dir.path().join("processes.db"))
+            .unwrap_err();
+        assert!(
+            error.contains("only available to in-process workers"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn test_same_seed_same_operations() {
+        let operations = |seed| {
+            let workload = Workload::new(seed);
+            let mut rng = fastrand::Rng::with_seed(seed);
+            (0..20)
+                .map(|_| workload.next_operation(&mut rng, &[1, 2, 3]))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(operations(1), operations(1));
+        assert_ne!(operations(1), operations(2));
+
+        let dir = tempdir().unwrap();
+        let report = Workload::new(3)
+            .workers(1)
+            .duration(Duration::from_millis(200))
+            .initial_rows(0)
+            .mix(0, 1, 1)
+            .journal_mode("wal")
+            .run(&dir.path().join("test.db"))
+            .unwrap();
+        // With no rows to update or read, the first operation is an insert.
+        assert_eq!(report.log()[0].kind, OpKind::Insert);
+        assert!(report.total().ok > 1);
+        assert_eq!(report.total().busy, 0);
+    }
+}
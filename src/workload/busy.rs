@@ -0,0 +1,158 @@
+//! Busy handling strategies for workload workers, and measuring lock waits.
+
+use std::cell::Cell;
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rusqlite::Connection;
+
+use crate::Sqlite3Process;
+
+/// How a [`Workload`](super::Workload) worker waits when the database is locked.
+///
+/// Whatever the strategy, an operation that still fails with `SQLITE_BUSY` is
+/// retried after a random backoff, up to the workload's retry limit.
+#[derive(Clone, Copy)]
+pub enum BusyStrategy {
+    /// Fail at once and rely on retries.
+    Retry,
+    /// Let SQLite retry for up to the given time (`busy_timeout`).
+    Timeout(Duration),
+    /// Call the function with the number of earlier calls for this lock, and
+    /// keep waiting while it returns `true`. The function should sleep before
+    /// returning. Only available to in-process workers.
+    Handler(fn(i32) -> bool),
+}
+
+impl fmt::Debug for BusyStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BusyStrategy::Retry => write!(f, "Retry"),
+            BusyStrategy::Timeout(timeout) => write!(f, "Timeout({timeout:?})"),
+            BusyStrategy::Handler(_) => write!(f, "Handler(..)"),
+        }
+    }
+}
+
+thread_local! {
+    /// The strategy run by [`timed_handler`] on this thread.
+    static HANDLER: Cell<fn(i32) -> bool> = const { Cell::new(timeout_handler) };
+    /// How long this thread's `BusyStrategy::Timeout` waits per lock.
+    static TIMEOUT: Cell<Duration> = const { Cell::new(Duration::ZERO) };
+    /// When the lock this thread is waiting for was first found busy.
+    static WAITING_SINCE: Cell<Option<Instant>> = const { Cell::new(None) };
+    /// Time this thread has spent in its busy handler since the last `take_handler_wait`.
+    static HANDLER_WAIT: Cell<Duration> = const { Cell::new(Duration::ZERO) };
+}
+
+impl BusyStrategy {
+    /// Applies the strategy to a worker process.
+    pub(super) fn apply_to_process(self, process: &mut Sqlite3Process) -> Result<(), String> {
+        let timeout = match self {
+            BusyStrategy::Retry => Duration::ZERO,
+            BusyStrategy::Timeout(timeout) => timeout,
+            BusyStrategy::Handler(_) => {
+                return Err("busy handlers are only available to in-process workers".to_string())
+            }
+        };
+        process.execute_checked(&format!(".timeout {}", timeout.as_millis()))?;
+        Ok(())
+    }
+
+    /// Applies the strategy to a worker connection; call on the worker's thread.
+    pub(super) fn apply_to_connection(self, conn: &Connection) -> Result<(), String> {
+        let handler: fn(i32) -> bool = match self {
+            BusyStrategy::Retry => {
+                return conn.busy_timeout(Duration::ZERO).map_err(|e| e.to_string());
+            }
+            BusyStrategy::Timeout(timeout) => {
+                TIMEOUT.set(timeout);
+                timeout_handler
+            }
+            BusyStrategy::Handler(handler) => handler,
+        };
+        HANDLER.set(handler);
+        conn.busy_handler(Some(timed_handler))
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Returns the time this thread spent in busy handlers since the last call.
+///
+/// Always zero for process workers, whose waiting happens in the child.
+pub(super) fn take_handler_wait() -> Duration {
+    HANDLER_WAIT.replace(Duration::ZERO)
+}
+
+/// Runs this thread's handler, adding the time it takes to the lock wait.
+fn timed_handler(count: i32) -> bool {
+    let started = Instant::now();
+    let wait = HANDLER.get()(count);
+    HANDLER_WAIT.set(HANDLER_WAIT.get() + started.elapsed());
+    wait
+}
+
+/// Sleeps in short steps until this thread's timeout since the first call.
+fn timeout_handler(count: i32) -> bool {
+    let now = Instant::now();
+    let since = match WAITING_SINCE.get() {
+        Some(since) if count > 0 => since,
+        _ => {
+            WAITING_SINCE.set(Some(now));
+            now
+        }
+    };
+    let Some(remaining) = TIMEOUT.get().checked_sub(now - since) else {
+        return false;
+    };
+    if remaining.is_zero() {
+        return false;
+    }
+    thread::sleep(remaining.min(Duration::from_millis(1)));
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn give_up(_count: i32) -> bool {
+        thread::sleep(Duration::from_millis(5));
+        false
+    }
+
+    #[test]
+    fn test_handlers_measure_lock_waits() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+        let holder = Connection::open(&db_path).unwrap();
+        holder
+            .execute_batch("CREATE TABLE t (x); BEGIN IMMEDIATE;")
+            .unwrap();
+        let waiter = Connection::open(&db_path).unwrap();
+        let insert = || waiter.execute("INSERT INTO t VALUES (1)", []);
+        take_handler_wait();
+
+        BusyStrategy::Retry.apply_to_connection(&waiter).unwrap();
+        assert!(insert().is_err());
+        assert_eq!(take_handler_wait(), Duration::ZERO);
+
+        BusyStrategy::Timeout(Duration::from_millis(50))
+            .apply_to_connection(&waiter)
+            .unwrap();
+        assert!(insert().is_err());
+        let waited = take_handler_wait();
+        assert!(waited >= Duration::from_millis(45), "{waited:?}");
+
+        BusyStrategy::Handler(give_up)
+            .apply_to_connection(&waiter)
+            .unwrap();
+        assert!(insert().is_err());
+        assert!(take_handler_wait() >= Duration::from_millis(5));
+
+        holder.execute_batch("COMMIT;").unwrap();
+        insert().unwrap();
+    }
+}
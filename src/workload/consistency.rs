@@ -0,0 +1,156 @@
+//! Checks reads and the final table against the log of committed operations.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+use super::{CommittedOp, OpKind};
+
+/// Stops listing problems after this many.
+const MAX_PROBLEMS: usize = 20;
+
+/// A committed write to one row, with the window in which it took effect.
+struct Write<'a> {
+    started: Duration,
+    finished: Duration,
+    text: &'a str,
+}
+
+/// Checks the committed operations against the rows before and after the run.
+///
+/// Every write took effect at some point between its start and finish, so a
+/// read, or the final table, may return any write that was not certainly
+/// overwritten: one that started before the read finished, with no other
+/// write starting after it finished and finishing before the read started.
+/// The initial rows count as writes finished at time zero, and the final
+/// table as a read at `end`.
+pub(super) fn check(
+    log: &[CommittedOp],
+    initial: &BTreeMap<i64, String>,
+    final_rows: &BTreeMap<i64, String>,
+    end: Duration,
+) -> Result<(), String> {
+    let mut writes: BTreeMap<i64, Vec<Write<'_>>> = BTreeMap::new();
+    for (id, text) in initial {
+        writes.entry(*id).or_default().push(Write {
+            started: Duration::ZERO,
+            finished: Duration::ZERO,
+            text,
+        });
+    }
+    for op in log.iter().filter(|op| op.kind != OpKind::Read) {
+        writes.entry(op.id).or_default().push(Write {
+            started: op.started,
+            finished: op.finished,
+            text: &op.text,
+        });
+    }
+
+    let mut problems = Vec::new();
+    for op in log.iter().filter(|op| op.kind == OpKind::Read) {
+        if !could_read(writes.get(&op.id), &op.text, op.started, op.finished) {
+            problems.push(format!(
+                "worker {} read {:?} from row {} at {:?}, which no committed write explains",
+                op.worker, op.text, op.id, op.finished
+            ));
+        }
+    }
+
+    let expected: BTreeSet<i64> = writes.keys().copied().collect();
+    let actual: BTreeSet<i64> = final_rows.keys().copied().collect();
+    for id in expected.difference(&actual) {
+        problems.push(format!("row {id} was committed but is missing"));
+    }
+    for id in actual.difference(&expected) {
+        problems.push(format!("row {id} exists but was never committed"));
+    }
+    for (id, text) in final_rows {
+        if writes.contains_key(id) && !could_read(writes.get(id), text, end, end) {
+            problems.push(format!(
+                "row {id} ends as {text:?}, which is not its last committed write"
+            ));
+        }
+    }
+
+    if problems.is_empty() {
+        return Ok(());
+    }
+    let count = problems.len();
+    problems.truncate(MAX_PROBLEMS);
+    Err(format!(
+        "{count} consistency problems:\n{}",
+        problems.join("\n")
+    ))
+}
+
+/// Returns `true` if a read of `text` in `started..finished` is explained by `writes`.
+fn could_read(
+    writes: Option<&Vec<Write<'_>>>,
+    text: &str,
+    started: Duration,
+    finished: Duration,
+) -> bool {
+    let writes = writes.map(Vec::as_slice).unwrap_or_default();
+    writes.iter().any(|write| {
+        write.text == text
+            && write.started <= finished
+            && !writes
+                .iter()
+                .any(|later| later.started > write.finished && later.finished < started)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn op(kind: OpKind, id: i64, text: &str, started: u64, finished: u64) -> CommittedOp {
+        CommittedOp {
+            worker: 0,
+            kind,
+            id,
+            text: text.to_string(),
+            started: Duration::from_millis(started),
+            finished: Duration::from_millis(finished),
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let initial = BTreeMap::from([(1, "a".to_string())]);
+        let log = [
+            op(OpKind::Update, 1, "b", 10, 20),
+            // Overlaps the update, so either value is fine.
+            op(OpKind::Read, 1, "a", 15, 25),
+            op(OpKind::Insert, 2, "c", 30, 40),
+            op(OpKind::Update, 1, "d", 50, 60),
+            op(OpKind::Update, 1, "e", 55, 65),
+        ];
+        let end = Duration::from_millis(100);
+        let final_rows = |texts: [&str; 2]| {
+            BTreeMap::from([(1, texts[0].to_string()), (2, texts[1].to_string())])
+        };
+
+        // The last two updates overlap, so either may have won.
+        assert!(check(&log, &initial, &final_rows(["d", "c"]), end).is_ok());
+        assert!(check(&log, &initial, &final_rows(["e", "c"]), end).is_ok());
+
+        let error = check(&log, &initial, &final_rows(["b", "c"]), end).unwrap_err();
+        assert!(error.contains("row 1 ends as \"b\""), "{error}");
+
+        let mut stale = log.to_vec();
+        stale.push(op(OpKind::Read, 1, "a", 30, 35));
+        let error = check(&stale, &initial, &final_rows(["e", "c"]), end).unwrap_err();
+        assert!(error.contains("read \"a\" from row 1"), "{error}");
+
+        let error = check(
+            &log,
+            &initial,
+            &BTreeMap::from([(1, "e".to_string()), (3, String::new())]),
+            end,
+        )
+        .unwrap_err();
+        assert!(error.starts_with("2 consistency problems"), "{error}");
+        assert!(error.contains("row 2 was committed but is missing"));
+        assert!(error.contains("row 3 exists but was never committed"));
+    }
+}
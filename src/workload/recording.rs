@@ -0,0 +1,742 @@
+//! Recording of every statement a workload ran, and replaying it.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Write as _};
+use std::path::Path;
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rusqlite::types::Value;
+use rusqlite::Connection;
+
+use super::{run_statement, OpKind};
+use crate::{init_test_db, set_journal_mode, Executor, ProcessPool};
+
+/// How long a replaying actor waits for a lock before its statement fails.
+const REPLAY_BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Stops listing divergences after this many.
+const MAX_DIVERGENCES: usize = 20;
+
+/// One statement attempt made by a workload worker, whether it succeeded or not.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedOp {
+    /// Index of the worker that ran it.
+    pub worker: usize,
+    /// The operation it was part of.
+    pub kind: OpKind,
+    /// The statement, with numbered placeholders.
+    pub sql: String,
+    /// The values bound to the placeholders.
+    pub params: Vec<Value>,
+    /// The new row ID of an insert, nothing for an update or the text read,
+    /// or the error.
+    pub result: Result<String, String>,
+    /// When it was sent, from the start of the run.
+    pub started: Duration,
+    /// When it returned, from the start of the run.
+    pub finished: Duration,
+}
+
+impl RecordedOp {
+    /// Returns `true` if it failed because the database was locked.
+    pub(super) fn is_busy(&self) -> bool {
+        matches!(self.result, Err(ref e) if e.contains("database is locked"))
+    }
+}
+
+/// How [`WorkloadRecording::replay`] orders the recorded statements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayMode {
+    /// One statement at a time, in the order they returned.
+    Serial,
+    /// Concurrently, but a statement does not start before every statement
+    /// that returned before it was sent in the recording. Statements that
+    /// overlapped still race.
+    Interleaved,
+}
+
+/// Every statement attempt of a [`Workload`](super::Workload) run, with the
+/// settings needed to run them again.
+///
+/// Written by [`Workload::record`](super::Workload::record) in a line-based
+/// text format, which [`parse`](Self::parse) and [`from_file`](Self::from_file)
+/// read back and [`Display`](fmt::Display) produces.
+///
+/// # Example
+///
+/// ```rust
+/// use sqlite_test_utils::{ReplayMode, Workload, WorkloadRecording};
+///
+/// let dir = tempfile::tempdir().unwrap();
+/// let recording = dir.path().join("workload.log");
+/// Workload::new(42)
+///     .workers(2)
+///     .ops_per_worker(10)
+///     .record(&recording)
+///     .run(&dir.path().join("test.db"))
+///     .unwrap();
+///
+/// let recording = WorkloadRecording::from_file(&recording).unwrap();
+/// recording
+///     .replay(&dir.path().join("replay.db"), ReplayMode::Serial)
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkloadRecording {
+    pub(super) seed: u64,
+    pub(super) workers: usize,
+    pub(super) initial_rows: usize,
+    pub(super) word_count: usize,
+    pub(super) in_process: bool,
+    pub(super) journal_mode: Option<String>,
+    pub(super) ops: Vec<RecordedOp>,
+}
+
+impl WorkloadRecording {
+    /// Returns the seed the database was initialized with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Returns the number of workers.
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    /// Returns every recorded attempt, in the order they were sent.
+    pub fn ops(&self) -> &[RecordedOp] {
+        &self.ops
+    }
+
+    /// Parses a recording in the format written by [`Display`](fmt::Display).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the first line that cannot be parsed.
+    ///
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut recording = WorkloadRecording {
+            seed: 0,
+            workers: 0,
+            initial_rows: 0,
+            word_count: 0,
+            in_process: false,
+            journal_mode: None,
+            ops: Vec::new(),
+        };
+        for (index, line) in text.lines().enumerate() {
+            parse_line(&mut recording, line).map_err(|e| format!("line {}: {e}", index + 1))?;
+        }
+        Ok(recording)
+    }
+
+    /// Reads and parses a recording file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or parsed.
+    ///
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        Self::parse(&text).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    /// Writes the recording to `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    ///
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        std::fs::write(path, self.to_string())
+            .map_err(|e| format!("Failed to write {}: {e}", path.display()))
+    }
+
+    /// Runs the recorded statements again on a fresh database at `db_path`.
+    ///
+    /// The database is initialized with [`init_test_db`] from the recorded
+    /// seed, and every worker gets its own sqlite3 process or connection, as
+    /// in the recorded run, with a busy timeout instead of retries. Attempts
+    /// that failed with `SQLITE_BUSY` changed nothing and are skipped; every
+    /// other attempt is run and its result compared with the recorded one.
+    ///
+    /// # Returns
+    ///
+    /// The replayed attempts, in the order they returned, with their new
+    /// results and times.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database or a worker cannot be set up, or
+    /// listing the statements whose result differs from the recording.
+    ///
+    pub fn replay(&self, db_path: &Path, mode: ReplayMode) -> Result<Vec<RecordedOp>, String> {
+        let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+        init_test_db(&conn, "main", self.seed, self.initial_rows, self.word_count)
+            .map_err(|e| format!("Failed to initialize replay database: {e}"))?;
+
+        let mut ops: Vec<&RecordedOp> = self.ops.iter().filter(|op| !op.is_busy()).collect();
+        ops.sort_by_key(|op| op.finished);
+        if let Some(op) = ops.iter().find(|op| op.worker >= self.workers) {
+            return Err(format!(
+                "recorded worker {} of only {}",
+                op.worker, self.workers
+            ));
+        }
+        let schedule = Schedule::new(&ops, mode);
+        let start = Instant::now();
+        let replayed = if self.in_process {
+            self.replay_threads(db_path, &ops, &schedule, start)?
+        } else {
+            self.replay_processes(db_path, &ops, &schedule, start)?
+        };
+
+        let mut divergences = Vec::new();
+        for (recorded, replayed) in ops.iter().zip(&replayed) {
+            let same = match (&recorded.result, &replayed.result) {
+                (Ok(recorded), Ok(replayed)) => recorded == replayed,
+                // Error messages carry line numbers and the like.
+                (Err(_), Err(_)) => true,
+                _ => false,
+            };
+            if !same {
+                divergences.push(format!(
+                    "worker {} {} at {:?}: recorded {:?}, replayed {:?}",
+                    recorded.worker,
+                    recorded.kind,
+                    recorded.finished,
+                    recorded.result,
+                    replayed.result
+                ));
+            }
+        }
+        if divergences.is_empty() {
+            return Ok(replayed);
+        }
+        let count = divergences.len();
+        divergences.truncate(MAX_DIVERGENCES);
+        Err(format!(
+            "{count} of {} replayed statements diverged from the recording:\n{}",
+            ops.len(),
+            divergences.join("\n")
+        ))
+    }
+
+    /// Replays every worker's statements on its own sqlite3 process.
+    fn replay_processes(
+        &self,
+        db_path: &Path,
+        ops: &[&RecordedOp],
+        schedule: &Schedule,
+        start: Instant,
+    ) -> Result<Vec<RecordedOp>, String> {
+        let mut pool = ProcessPool::builder()
+            .size(self.workers)
+            .busy_timeout(REPLAY_BUSY_TIMEOUT);
+        if let Some(ref mode) = self.journal_mode {
+            pool = pool.journal_mode(mode);
+        }
+        let processes = pool.spawn(db_path)?.into_processes();
+        Ok(replay_actors(
+            processes,
+            ops,
+            schedule,
+            |worker, mut process| {
+                let replayed = replay_actor(&mut process, worker, ops, schedule, start);
+                // Closing, unlike dropping, does not log the errors left on stderr.
+                let _ = process.close();
+                replayed
+            },
+        ))
+    }
+
+    /// Replays every worker's statements on its own thread and connection.
+    fn replay_threads(
+        &self,
+        db_path: &Path,
+        ops: &[&RecordedOp],
+        schedule: &Schedule,
+        start: Instant,
+    ) -> Result<Vec<RecordedOp>, String> {
+        let mut connections = Vec::with_capacity(self.workers);
+        for worker in 0..self.workers {
+            let conn = Connection::open(db_path).map_err(|e| format!("worker {worker}: {e}"))?;
+            conn.busy_timeout(REPLAY_BUSY_TIMEOUT)
+                .map_err(|e| format!("worker {worker}: {e}"))?;
+            if let Some(ref mode) = self.journal_mode {
+                set_journal_mode(&conn, mode, "main")
+                    .map_err(|e| format!("worker {worker}: {e}"))?;
+            }
+            connections.push(conn);
+        }
+        Ok(replay_actors(connections, ops, schedule, |worker, conn| {
+            replay_actor(&conn, worker, ops, schedule, start)
+        }))
+    }
+}
+
+/// Runs `replay` for each worker on its own thread, returning every replayed
+/// attempt in the order of `ops`.
+fn replay_actors<C, F>(
+    connections: Vec<C>,
+    ops: &[&RecordedOp],
+    schedule: &Schedule,
+    replay: F,
+) -> Vec<RecordedOp>
+where
+    C: Send,
+    F: Fn(usize, C) -> Vec<(usize, RecordedOp)> + Sync,
+{
+    let replay = &replay;
+    let mut replayed: Vec<Option<RecordedOp>> = vec![None; ops.len()];
+    thread::scope(|scope| {
+        let handles: Vec<_> = connections
+            .into_iter()
+            .enumerate()
+            .map(|(worker, conn)| {
+                scope.spawn(move || {
+                    let _guard = AbandonOnPanic(schedule);
+                    replay(worker, conn)
+                })
+            })
+            .collect();
+        for handle in handles {
+            match handle.join() {
+                Ok(ops) => {
+                    for (index, op) in ops {
+                        replayed[index] = Some(op);
+                    }
+                }
+                Err(panic) => std::panic::resume_unwind(panic),
+            }
+        }
+    });
+    replayed
+        .into_iter()
+        .map(|op| op.expect("every statement is replayed"))
+        .collect()
+}
+
+/// Replays the statements of `worker` on `conn`, as `schedule` allows.
+fn replay_actor<E>(
+    mut conn: E,
+    worker: usize,
+    ops: &[&RecordedOp],
+    schedule: &Schedule,
+    start: Instant,
+) -> Vec<(usize, RecordedOp)>
+where
+    E: Executor,
+    E::Error: fmt::Display,
+{
+    let mut replayed = Vec::new();
+    for (index, op) in ops.iter().enumerate() {
+        if op.worker != worker {
+            continue;
+        }
+        schedule.wait_for(index);
+        let started = start.elapsed();
+        let result =
+            run_statement(&mut conn, op.kind, &op.sql, &op.params).map_err(|e| e.to_string());
+        replayed.push((
+            index,
+            RecordedOp {
+                result,
+                started,
+                finished: start.elapsed(),
+                ..(*op).clone()
+            },
+        ));
+        schedule.finish(index);
+    }
+    replayed
+}
+
+/// Which replayed statements each statement waits for.
+struct Schedule {
+    /// For each statement, the statements that must have finished first.
+    after: Vec<Vec<usize>>,
+    finished: Mutex<Vec<bool>>,
+    changed: Condvar,
+}
+
+impl Schedule {
+    /// Orders `ops`, sorted by when they returned, for `mode`.
+    fn new(ops: &[&RecordedOp], mode: ReplayMode) -> Self {
+        let after = match mode {
+            ReplayMode::Serial => (0..ops.len())
+                .map(|index| index.checked_sub(1).into_iter().collect())
+                .collect(),
+            // A worker's statements run in order, so waiting for the last
+            // statement of each worker that returned before this one was sent
+            // covers all of them.
+            ReplayMode::Interleaved => (0..ops.len())
+                .map(|index| {
+                    let mut last = BTreeMap::new();
+                    for (earlier, op) in ops[..index].iter().enumerate() {
+                        if op.finished < ops[index].started {
+                            last.insert(op.worker, earlier);
+                        }
+                    }
+                    last.into_values().collect()
+                })
+                .collect(),
+        };
+        Schedule {
+            after,
+            finished: Mutex::new(vec![false; ops.len()]),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Blocks until statement `index` may start.
+    fn wait_for(&self, index: usize) {
+        let finished = self.finished.lock().unwrap();
+        let _finished = self
+            .changed
+            .wait_while(finished, |finished| {
+                self.after[index].iter().any(|&before| !finished[before])
+            })
+            .unwrap();
+    }
+
+    /// Marks statement `index` as finished.
+    fn finish(&self, index: usize) {
+        self.finished.lock().unwrap()[index] = true;
+        self.changed.notify_all();
+    }
+
+    /// Lets every statement start, after an actor died.
+    fn abandon(&self) {
+        self.finished
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .fill(true);
+        self.changed.notify_all();
+    }
+}
+
+/// Abandons the schedule if the actor holding it panics, so that actors
+/// waiting on its statements do not block forever.
+struct AbandonOnPanic<'a>(&'a Schedule);
+
+impl Drop for AbandonOnPanic<'_> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.0.abandon();
+        }
+    }
+}
+
+impl fmt::Display for WorkloadRecording {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "workers {}", self.workers)?;
+        writeln!(f, "initial_rows {}", self.initial_rows)?;
+        writeln!(f, "word_count {}", self.word_count)?;
+        writeln!(
+            f,
+            "backend {}",
+            if self.in_process {
+                "threads"
+            } else {
+                "processes"
+            }
+        )?;
+        if let Some(ref mode) = self.journal_mode {
+            writeln!(f, "journal_mode {mode}")?;
+        }
+        for op in &self.ops {
+            writeln!(
+                f,
+                "\nop {} {} {} {}",
+                op.worker,
+                op.started.as_nanos(),
+                op.finished.as_nanos(),
+                op.kind
+            )?;
+            writeln!(f, "sql {}", escape(&op.sql))?;
+            for param in &op.params {
+                writeln!(f, "param {}", format_param(param))?;
+            }
+            let (keyword, text) = match op.result {
+                Ok(ref output) => ("ok", output),
+                Err(ref error) => ("error", error),
+            };
+            if text.is_empty() {
+                writeln!(f, "{keyword}")?;
+            } else {
+                writeln!(f, "{keyword} {}", escape(text))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses one line into `recording`.
+fn parse_line(recording: &mut WorkloadRecording, line: &str) -> Result<(), String> {
+    if line.trim().is_empty() || line.starts_with('#') {
+        return Ok(());
+    }
+    let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+    let number = |value: &str| {
+        value
+            .parse()
+            .map_err(|_| format!("invalid {keyword} {value:?}"))
+    };
+    match keyword {
+        "seed" => recording.seed = rest.parse().map_err(|_| format!("invalid seed {rest:?}"))?,
+        "workers" => recording.workers = number(rest)?,
+        "initial_rows" => recording.initial_rows = number(rest)?,
+        "word_count" => recording.word_count = number(rest)?,
+        "backend" => {
+            recording.in_process = match rest {
+                "threads" => true,
+                "processes" => false,
+                _ => return Err(format!("unknown backend {rest:?}")),
+            }
+        }
+        "journal_mode" => recording.journal_mode = Some(rest.to_string()),
+        "op" => {
+            let fields: Vec<&str> = rest.split(' ').collect();
+            let [worker, started, finished, kind] = fields[..] else {
+                return Err("expected `op WORKER STARTED_NS FINISHED_NS KIND`".to_string());
+            };
+            let nanos = |value: &str| {
+                value
+                    .parse()
+                    .map(Duration::from_nanos)
+                    .map_err(|_| format!("invalid time {value:?}"))
+            };
+            recording.ops.push(RecordedOp {
+                worker: number(worker)?,
+                kind: match kind {
+                    "insert" => OpKind::Insert,
+                    "update" => OpKind::Update,
+                    "read" => OpKind::Read,
+                    _ => return Err(format!("unknown operation {kind:?}")),
+                },
+                sql: String::new(),
+                params: Vec::new(),
+                result: Ok(String::new()),
+                started: nanos(started)?,
+                finished: nanos(finished)?,
+            });
+        }
+        "sql" | "param" | "ok" | "error" => {
+            let op = recording
+                .ops
+                .last_mut()
+                .ok_or_else(|| format!("`{keyword}` before the first `op`"))?;
+            match keyword {
+                "sql" => op.sql = unescape(rest)?,
+                "param" => op.params.push(parse_param(rest)?),
+                "ok" => op.result = Ok(unescape(rest)?),
+                _ => op.result = Err(unescape(rest)?),
+            }
+        }
+        _ => return Err(format!("unknown keyword {keyword:?}")),
+    }
+    Ok(())
+}
+
+/// Formats a parameter as its type and value.
+fn format_param(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Integer(integer) => format!("integer {integer}"),
+        // Debug formatting is the shortest representation that round-trips.
+        Value::Real(real) => format!("real {real:?}"),
+        Value::Text(text) => format!("text {}", escape(text)),
+        Value::Blob(blob) => {
+            let mut hex = String::from("blob ");
+            for byte in blob {
+                let _ = write!(hex, "{byte:02X}");
+            }
+            hex
+        }
+    }
+}
+
+/// Parses a parameter formatted by [`format_param`].
+fn parse_param(text: &str) -> Result<Value, String> {
+    let (kind, value) = text.split_once(' ').unwrap_or((text, ""));
+    let invalid = || format!("invalid {kind} parameter {value:?}");
+    match kind {
+        "null" => Ok(Value::Null),
+        "integer" => value.parse().map(Value::Integer).map_err(|_| invalid()),
+        "real" => value.parse().map(Value::Real).map_err(|_| invalid()),
+        "text" => unescape(value).map(Value::Text),
+        "blob" => {
+            if value.len() % 2 != 0 || !value.is_ascii() {
+                return Err(invalid());
+            }
+            (0..value.len())
+                .step_by(2)
+                .map(|at| u8::from_str_radix(&value[at..at + 2], 16).map_err(|_| invalid()))
+                .collect::<Result<_, _>>()
+                .map(Value::Blob)
+        }
+        _ => Err(format!("unknown parameter type {kind:?}")),
+    }
+}
+
+/// Escapes backslashes and line breaks so that `text` fits on one line.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// Reverses [`escape`].
+fn unescape(text: &str) -> Result<String, String> {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => unescaped.push('\\'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            other => return Err(format!("invalid escape \\{}", other.unwrap_or(' '))),
+        }
+    }
+    Ok(unescaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Workload;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_panicking_actor_unblocks_waiting_ones() {
+        let op = RecordedOp {
+            worker: 0,
+            kind: OpKind::Read,
+            sql: String::new(),
+            params: Vec::new(),
+            result: Ok(String::new()),
+            started: Duration::ZERO,
+            finished: Duration::ZERO,
+        };
+        // Worker 0's statement waits for worker 1's, which panics first.
+        let schedule = Schedule {
+            after: vec![vec![1], Vec::new()],
+            finished: Mutex::new(vec![false; 2]),
+            changed: Condvar::new(),
+        };
+        let (tx, rx) = std::sync::mpsc::channel();
+        thread::spawn(move || {
+            let result = std::panic::catch_unwind(|| {
+                replay_actors(vec![0, 1], &[&op, &op], &schedule, |worker, _| {
+                    if worker == 1 {
+                        panic!("actor died");
+                    }
+                    schedule.wait_for(0);
+                    Vec::new()
+                })
+            });
+            tx.send(result.is_err()).unwrap();
+        });
+        assert!(rx.recv_timeout(Duration::from_secs(10)).unwrap());
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("workload.log");
+        Workload::new(5)
+            .threads()
+            .workers(3)
+            .ops_per_worker(30)
+            .initial_rows(10)
+            .record(&path)
+            .run(&dir.path().join("test.db"))
+            .unwrap();
+
+        let recording = WorkloadRecording::from_file(&path).unwrap();
+        assert_eq!((recording.seed(), recording.workers()), (5, 3));
+        assert!(recording.ops().len() >= 90);
+        assert!(recording
+            .ops()
+            .windows(2)
+            .all(|pair| pair[0].started <= pair[1].started));
+
+        let replayed = recording
+            .replay(&dir.path().join("serial.db"), ReplayMode::Serial)
+            .unwrap();
+        assert_eq!(
+            replayed.len(),
+            recording.ops().iter().filter(|op| !op.is_busy()).count()
+        );
+
+        let mut tampered = recording.clone();
+        let read = tampered
+            .ops
+            .iter_mut()
+            .find(|op| op.kind == OpKind::Read && op.result.is_ok())
+            .unwrap();
+        read.result = Ok("not what was read".to_string());
+        let error = tampered
+            .replay(&dir.path().join("tampered.db"), ReplayMode::Serial)
+            .unwrap_err();
+        assert!(error.starts_with("1 of "), "{error}");
+        assert!(
+            error.contains("recorded Ok(\"not what was read\")"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn test_interleaved_replay_keeps_order() {
+        // Worker 1 reads the row worker 0 inserted, so it must wait for the
+        // insert even though its own thread is free to run first.
+        let text = "\
+seed 1
+workers 2
+initial_rows 0
+word_count 3
+backend processes
+
+op 0 0 1000000 insert
+sql INSERT INTO main.notes (text) VALUES (?1)
+param text first
+ok 1
+
+op 1 2000000 3000000 read
+sql SELECT text FROM main.notes WHERE id = ?1
+param integer 1
+ok first
+
+op 1 4000000 5000000 update
+sql UPDATE main.notes SET text = ?1 WHERE id = ?2
+param text two\\nlines
+param integer 1
+error database is locked
+";
+        let recording = WorkloadRecording::parse(text).unwrap();
+        assert_eq!(
+            recording.ops()[2].params[0],
+            Value::Text("two\nlines".into())
+        );
+        assert_eq!(recording.to_string(), text);
+
+        let dir = tempdir().unwrap();
+        let replayed = recording
+            .replay(&dir.path().join("test.db"), ReplayMode::Interleaved)
+            .unwrap();
+        assert_eq!(replayed.len(), 2);
+        assert!(replayed[0].finished <= replayed[1].started);
+
+        let error = WorkloadRecording::parse("op 0 1 2 delete").unwrap_err();
+        assert_eq!(error, "line 1: unknown operation \"delete\"");
+    }
+}
@@ -0,0 +1,391 @@
+//! Shrinking a failing workload recording to a minimal reproducer.
+
+use std::fmt::Write as _;
+
+use rusqlite::types::Value;
+
+use super::{OpKind, RecordedOp, WorkloadRecording};
+use crate::{sql_literal, Expect, Scenario};
+
+impl WorkloadRecording {
+    /// Removes operations and workers for as long as `fails` still holds.
+    ///
+    /// Workers are removed whole first, renumbering the rest, then the
+    /// operations are cut down by delta debugging: chunks of operations are
+    /// removed, halving the chunk size whenever no chunk can go. The two
+    /// alternate until neither removes anything, so removing any single
+    /// operation or worker from the result makes `fails` return `false`.
+    ///
+    /// `fails` usually replays the candidate on a fresh database. Removing
+    /// operations changes the row IDs and texts that later ones see, so it
+    /// should look for the specific failure rather than any divergence.
+    ///
+    /// # Arguments
+    ///
+    /// * `fails` - Returns `true` if a candidate recording still fails
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `fails` does not hold for the recording itself.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use std::path::Path;
+    /// use sqlite_test_utils::{ReplayMode, WorkloadRecording};
+    ///
+    /// let recording = WorkloadRecording::from_file(Path::new("workload.log")).unwrap();
+    /// let dir = tempfile::tempdir().unwrap();
+    /// let mut attempt = 0;
+    /// let shrunk = recording
+    ///     .shrink(|candidate| {
+    ///         attempt += 1;
+    ///         let db_path = dir.path().join(format!("{attempt}.db"));
+    ///         candidate
+    ///             .replay(&db_path, ReplayMode::Serial)
+    ///             .is_err_and(|e| e.contains("worker 1 read"))
+    ///     })
+    ///     .unwrap();
+    /// shrunk.save(Path::new("shrunk.log")).unwrap();
+    /// println!("{}", shrunk.to_rust_test("shrunk_workload"));
+    /// ```
+    pub fn shrink<F>(&self, mut fails: F) -> Result<WorkloadRecording, String>
+    where
+        F: FnMut(&WorkloadRecording) -> bool,
+    {
+        if !fails(self) {
+            return Err("The recording does not fail to begin with".to_string());
+        }
+        let mut smallest = self.clone();
+        loop {
+            let size = (smallest.workers, smallest.ops.len());
+            smallest = remove_workers(smallest, &mut fails);
+            smallest = remove_ops(smallest, &mut fails);
+            if (smallest.workers, smallest.ops.len()) == size {
+                return Ok(smallest);
+            }
+        }
+    }
+
+    /// Builds a [`Scenario`] running the statements one at a time, in the
+    /// order they returned, like [`ReplayMode::Serial`](super::ReplayMode::Serial).
+    ///
+    /// Each worker becomes an actor named `workerN`, a sqlite3 process or an
+    /// in-process connection as in the recorded run. Parameters are inlined
+    /// as literals and every statement is expected to have its recorded
+    /// result. Attempts that failed with `SQLITE_BUSY` are left out. Run it on
+    /// a database initialized with [`init_test_db`](crate::init_test_db) from
+    /// the recorded seed, as [`to_rust_test`](Self::to_rust_test) does.
+    pub fn to_scenario(&self) -> Scenario {
+        let mut scenario = Scenario::new("replayed workload");
+        for worker in 0..self.workers {
+            let name = actor_name(worker);
+            scenario = if self.in_process {
+                scenario.connection(&name)
+            } else {
+                scenario.process(&name)
+            };
+        }
+        for (actor, sql, expect) in self.scenario_steps() {
+            scenario = scenario.step(&actor, &sql, expect);
+        }
+        scenario
+    }
+
+    /// Renders [`to_scenario`](Self::to_scenario) as the source of a test
+    /// function named `name`, including the database setup.
+    pub fn to_rust_test(&self, name: &str) -> String {
+        let mut code = String::new();
+        let _ = writeln!(code, "#[test]");
+        let _ = writeln!(code, "fn {name}() {{");
+        let _ = writeln!(
+            code,
+            "    use sqlite_test_utils::{{init_test_db, Expect, Scenario}};\n"
+        );
+        let _ = writeln!(code, "    let dir = tempfile::tempdir().unwrap();");
+        let _ = writeln!(code, "    let db_path = dir.path().join(\"test.db\");");
+        let _ = writeln!(
+            code,
+            "    let conn = rusqlite::Connection::open(&db_path).unwrap();"
+        );
+        let _ = writeln!(
+            code,
+            "    init_test_db(&conn, \"main\", {}, {}, {}).unwrap();",
+            self.seed, self.initial_rows, self.word_count
+        );
+        let _ = writeln!(code, "    drop(conn);\n");
+        let _ = writeln!(code, "    Scenario::new({name:?})");
+        for worker in 0..self.workers {
+            let kind = if self.in_process {
+                "connection"
+            } else {
+                "process"
+            };
+            let _ = writeln!(code, "        .{kind}({:?})", actor_name(worker));
+        }
+        for (actor, sql, expect) in self.scenario_steps() {
+            let _ = writeln!(
+                code,
+                "        .step({actor:?}, {sql:?}, {})",
+                expect_code(&expect)
+            );
+        }
+        let _ = writeln!(code, "        .run(&db_path)");
+        let _ = writeln!(code, "        .unwrap();");
+        code.push('}');
+        code
+    }
+
+    /// Returns the scenario steps as (actor, SQL, expectation).
+    fn scenario_steps(&self) -> Vec<(String, String, Expect)> {
+        let mut steps = Vec::new();
+        if let Some(ref mode) = self.journal_mode {
+            for worker in 0..self.workers {
+                steps.push((
+                    actor_name(worker),
+                    format!("PRAGMA journal_mode = {mode};"),
+                    Expect::rows([mode.to_lowercase()]),
+                ));
+            }
+        }
+        let mut ops: Vec<&RecordedOp> = self.ops.iter().filter(|op| !op.is_busy()).collect();
+        ops.sort_by_key(|op| op.finished);
+        for op in ops {
+            let mut sql = inline_params(&op.sql, &op.params);
+            sql.push(';');
+            let expect = match (&op.result, op.kind) {
+                // Error messages differ between processes and connections.
+                (Err(_), _) => Expect::Error(String::new()),
+                (Ok(rowid), OpKind::Insert) => {
+                    sql.push_str(" SELECT last_insert_rowid();");
+                    Expect::rows([rowid.as_str()])
+                }
+                (Ok(_), OpKind::Update) => Expect::Ok,
+                // Workers only read rows they know exist, so empty text is an
+                // empty note rather than a missing row.
+                (Ok(text), OpKind::Read) => Expect::rows([text.as_str()]),
+            };
+            steps.push((actor_name(op.worker), sql, expect));
+        }
+        steps
+    }
+}
+
+/// Names the scenario actor of `worker`.
+fn actor_name(worker: usize) -> String {
+    format!("worker{worker}")
+}
+
+/// Replaces the numbered placeholders in `sql` with literals of `params`.
+///
+/// Works in one pass over the original statement, so placeholder-like text
+/// inside a substituted literal or a string in `sql` stays as it is.
+fn inline_params(sql: &str, params: &[Value]) -> String {
+    let mut inlined = String::with_capacity(sql.len());
+    let mut chars = sql.char_indices().peekable();
+    let mut in_string = false;
+    while let Some((at, c)) = chars.next() {
+        if c == '\'' {
+            in_string = !in_string;
+        }
+        if c != '?' || in_string {
+            inlined.push(c);
+            continue;
+        }
+        let mut end = at + 1;
+        while let Some(&(next, digit)) = chars.peek() {
+            if !digit.is_ascii_digit() {
+                break;
+            }
+            end = next + 1;
+            chars.next();
+        }
+        match sql[at + 1..end]
+            .parse::<usize>()
+            .ok()
+            .and_then(|number| params.get(number.checked_sub(1)?))
+        {
+            Some(param) => inlined.push_str(&sql_literal(param)),
+            None => inlined.push_str(&sql[at..end]),
+        }
+    }
+    inlined
+}
+
+/// Renders an expectation as Rust code.
+fn expect_code(expect: &Expect) -> String {
+    match expect {
+        Expect::Ok => "Expect::Ok".to_string(),
+        Expect::Rows(rows) if rows.is_empty() => "Expect::Rows(Vec::new())".to_string(),
+        Expect::Rows(rows) => format!("Expect::rows({rows:?})"),
+        Expect::Busy => "Expect::Busy".to_string(),
+        Expect::Error(text) => format!("Expect::Error({text:?}.to_string())"),
+        Expect::BlocksUntil(step, then) => {
+            format!("Expect::blocks_until({step}, {})", expect_code(then))
+        }
+    }
+}
+
+/// Removes whole workers one at a time while `fails` holds.
+fn remove_workers<F>(mut smallest: WorkloadRecording, fails: &mut F) -> WorkloadRecording
+where
+    F: FnMut(&WorkloadRecording) -> bool,
+{
+    let mut worker = 0;
+    while worker < smallest.workers && smallest.workers > 1 {
+        let mut candidate = smallest.clone();
+        candidate.workers -= 1;
+        candidate.ops.retain(|op| op.worker != worker);
+        for op in &mut candidate.ops {
+            if op.worker > worker {
+                op.worker -= 1;
+            }
+        }
+        if fails(&candidate) {
+            smallest = candidate;
+        } else {
+            worker += 1;
+        }
+    }
+    smallest
+}
+
+/// Removes chunks of operations while `fails` holds, down to single ones.
+fn remove_ops<F>(mut smallest: WorkloadRecording, fails: &mut F) -> WorkloadRecording
+where
+    F: FnMut(&WorkloadRecording) -> bool,
+{
+    let mut chunks = 2;
+    while !smallest.ops.is_empty() {
+        let len = smallest.ops.len();
+        let size = len.div_ceil(chunks);
+        let mut removed = false;
+        for start in (0..len).step_by(size) {
+            let mut candidate = smallest.clone();
+            candidate.ops.drain(start..(start + size).min(len));
+            if fails(&candidate) {
+                smallest = candidate;
+                chunks = (chunks - 1).max(2);
+                removed = true;
+                break;
+            }
+        }
+        if !removed {
+            if chunks >= len {
+                break;
+            }
+            chunks = (chunks * 2).min(len);
+        }
+    }
+    smallest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{init_test_db, ReplayMode, Workload};
+    use rusqlite::Connection;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    fn record(dir: &Path) -> WorkloadRecording {
+        let path = dir.join("workload.log");
+        Workload::new(3)
+            .threads()
+            .workers(3)
+            .ops_per_worker(40)
+            .initial_rows(5)
+            .record(&path)
+            .run(&dir.join("test.db"))
+            .unwrap();
+        WorkloadRecording::from_file(&path).unwrap()
+    }
+
+    #[test]
+    fn test_inline_params_in_one_pass() {
+        let params: Vec<Value> = (1..=10)
+            .map(|n| Value::Text(format!("?1 and ?{n}")))
+            .collect();
+        assert_eq!(
+            inline_params("SELECT ?10, '?2', ?1, ?11", &params),
+            "SELECT '?1 and ?10', '?2', '?1 and ?1', ?11"
+        );
+    }
+
+    #[test]
+    fn test_shrink_to_one_minimal() {
+        let dir = tempdir().unwrap();
+        let recording = record(dir.path());
+
+        // Some worker updates a row that another worker reads.
+        let fails = |candidate: &WorkloadRecording| {
+            let ops = || candidate.ops.iter().filter(|op| op.result.is_ok());
+            ops().any(|update| {
+                update.kind == OpKind::Update
+                    && ops().any(|read| {
+                        read.kind == OpKind::Read
+                            && read.worker != update.worker
+                            && read.params == update.params[1..]
+                    })
+            })
+        };
+        let shrunk = recording.shrink(fails).unwrap();
+        assert_eq!(shrunk.workers(), 2);
+        assert_eq!(shrunk.ops().len(), 2);
+        assert!(fails(&shrunk));
+
+        assert!(recording.shrink(|_| false).is_err());
+    }
+
+    #[test]
+    fn test_shrink_to_scenario() {
+        let dir = tempdir().unwrap();
+        let recording = record(dir.path());
+        let init = |name: &str| {
+            let db_path = dir.path().join(name);
+            let conn = Connection::open(&db_path).unwrap();
+            init_test_db(&conn, "main", 3, 5, 10).unwrap();
+            db_path
+        };
+        recording.to_scenario().run(&init("scenario.db")).unwrap();
+
+        let mut tampered = recording.clone();
+        let read = tampered
+            .ops
+            .iter_mut()
+            .rev()
+            .find(|op| op.kind == OpKind::Read && op.result.is_ok())
+            .unwrap();
+        read.result = Ok("not what was read".to_string());
+        let mut attempt = 0;
+        let shrunk = tampered
+            .shrink(|candidate| {
+                attempt += 1;
+                candidate
+                    .replay(
+                        &dir.path().join(format!("{attempt}.db")),
+                        ReplayMode::Serial,
+                    )
+                    .is_err_and(|e| e.contains("not what was read"))
+            })
+            .unwrap();
+        assert_eq!((shrunk.workers(), shrunk.ops().len()), (1, 1));
+
+        let error = shrunk.to_scenario().run(&init("shrunk.db")).unwrap_err();
+        assert!(error.contains("not what was read"), "{error}");
+        let code = shrunk.to_rust_test("shrunk_read");
+        assert!(code.starts_with("#[test]\nfn shrunk_read() {"), "{code}");
+        assert!(
+            code.contains("init_test_db(&conn, \"main\", 3, 5, 10)"),
+            "{code}"
+        );
+        assert!(
+            code.contains(".step(\"worker0\", \"SELECT text FROM main.notes WHERE id = "),
+            "{code}"
+        );
+        assert!(
+            code.contains("Expect::rows([\"not what was read\"])"),
+            "{code}"
+        );
+    }
+}
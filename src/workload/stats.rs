@@ -0,0 +1,279 @@
+//! Latency histograms and per-operation counters of a workload run.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+use super::{CommittedOp, OpKind};
+
+/// Number of power-of-two microsecond buckets; the last one holds everything
+/// from 2^38 µs, about 76 hours, up.
+const BUCKETS: usize = 40;
+
+/// Latencies counted in power-of-two microsecond buckets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    counts: [u64; BUCKETS],
+    total: Duration,
+    max: Duration,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        LatencyHistogram {
+            counts: [0; BUCKETS],
+            total: Duration::ZERO,
+            max: Duration::ZERO,
+        }
+    }
+}
+
+impl LatencyHistogram {
+    /// Creates an empty histogram.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts one latency.
+    pub fn record(&mut self, latency: Duration) {
+        let micros = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+        let bucket = (u64::BITS - micros.leading_zeros()) as usize;
+        self.counts[bucket.min(BUCKETS - 1)] += 1;
+        self.total += latency;
+        self.max = self.max.max(latency);
+    }
+
+    /// Adds every latency counted by `other`.
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        for (count, other) in self.counts.iter_mut().zip(other.counts) {
+            *count += other;
+        }
+        self.total += other.total;
+        self.max = self.max.max(other.max);
+    }
+
+    /// Returns the number of latencies counted.
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Returns the mean latency, or zero if nothing was counted.
+    pub fn mean(&self) -> Duration {
+        match u32::try_from(self.count()) {
+            Ok(0) => Duration::ZERO,
+            Ok(count) => self.total / count,
+            Err(_) => self.total.div_f64(self.count() as f64),
+        }
+    }
+
+    /// Returns the largest latency counted.
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// Returns an upper bound for the `percentile` (0 to 100) latency.
+    ///
+    /// The bound is the top of the bucket holding that latency, capped at
+    /// [`max`](Self::max).
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        let rank = (self.count() as f64 * percentile / 100.0).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (upper, count) in self.buckets() {
+            seen += count;
+            if seen >= rank {
+                return upper.min(self.max);
+            }
+        }
+        self.max
+    }
+
+    /// Iterates over the non-empty buckets as (upper bound, count).
+    pub fn buckets(&self) -> impl Iterator<Item = (Duration, u64)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(bucket, count)| (bucket_upper_bound(bucket), *count))
+    }
+}
+
+/// Returns the exclusive upper bound of `bucket`.
+fn bucket_upper_bound(bucket: usize) -> Duration {
+    Duration::from_micros(1 << bucket)
+}
+
+impl LatencyHistogram {
+    /// Renders the percentiles, maximum and mean on one line.
+    fn summary(&self) -> String {
+        format!(
+            "p50 {:?}, p90 {:?}, p99 {:?}, max {:?}, mean {:?}",
+            self.percentile(50.0),
+            self.percentile(90.0),
+            self.percentile(99.0),
+            self.max,
+            self.mean()
+        )
+    }
+}
+
+impl fmt::Display for LatencyHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.summary())?;
+        let widest = self.buckets().map(|(_, count)| count).max().unwrap_or(0);
+        for (upper, count) in self.buckets() {
+            let bar = (count * 40).div_ceil(widest) as usize;
+            write!(f, "\n  < {:>10?} {count:>7} {}", upper, "#".repeat(bar))?;
+        }
+        Ok(())
+    }
+}
+
+/// Counters for one kind of operation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OpStats {
+    /// Operations that succeeded, possibly after retries.
+    pub ok: u64,
+    /// Operations abandoned after running out of retries.
+    pub failed: u64,
+    /// `SQLITE_BUSY` errors seen, whether retried or not.
+    pub busy: u64,
+    /// Attempts made after a first attempt failed.
+    pub retries: u64,
+    /// Time from the first attempt until success, of successful operations.
+    pub latency: LatencyHistogram,
+    /// Time successful operations spent waiting for locks: retrying after
+    /// `SQLITE_BUSY`, plus time in the busy handler of in-process workers.
+    /// Process workers wait inside the child, which cannot be measured.
+    pub lock_wait: LatencyHistogram,
+}
+
+impl OpStats {
+    fn merge(&mut self, other: &OpStats) {
+        self.ok += other.ok;
+        self.failed += other.failed;
+        self.busy += other.busy;
+        self.retries += other.retries;
+        self.latency.merge(&other.latency);
+        self.lock_wait.merge(&other.lock_wait);
+    }
+}
+
+/// Statistics and the committed-operation log of a workload run.
+///
+/// Rendered with [`Display`](fmt::Display) as a summary per operation kind.
+#[derive(Debug, Clone)]
+pub struct WorkloadReport {
+    pub(super) workers: usize,
+    pub(super) in_process: bool,
+    pub(super) elapsed: Duration,
+    pub(super) stats: BTreeMap<OpKind, OpStats>,
+    pub(super) log: Vec<CommittedOp>,
+}
+
+impl WorkloadReport {
+    /// Returns how long the workers ran.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Returns the counters of each operation kind that was run.
+    pub fn stats(&self) -> &BTreeMap<OpKind, OpStats> {
+        &self.stats
+    }
+
+    /// Returns the counters summed over every operation kind.
+    pub fn total(&self) -> OpStats {
+        let mut total = OpStats::default();
+        for stats in self.stats.values() {
+            total.merge(stats);
+        }
+        total
+    }
+
+    /// Returns the successful operations per second.
+    pub fn throughput(&self) -> f64 {
+        self.total().ok as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// Returns every successful operation, in the order they finished.
+    pub fn log(&self) -> &[CommittedOp] {
+        &self.log
+    }
+
+    /// Adds a worker's counters to the report.
+    pub(super) fn add(&mut self, stats: &BTreeMap<OpKind, OpStats>) {
+        for (kind, stats) in stats {
+            self.stats.entry(*kind).or_default().merge(stats);
+        }
+    }
+}
+
+impl fmt::Display for WorkloadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.total();
+        write!(
+            f,
+            "--- workload: {} {}, {} ok, {} failed in {:.3}s ({:.1} ops/s) ---",
+            self.workers,
+            if self.in_process {
+                "threads"
+            } else {
+                "processes"
+            },
+            total.ok,
+            total.failed,
+            self.elapsed.as_secs_f64(),
+            self.throughput()
+        )?;
+        for (kind, stats) in &self.stats {
+            write!(
+                f,
+                "\n{kind}: {} ok, {} failed, {} busy, {} retries\n  lock wait {}\n  latency {}",
+                stats.ok,
+                stats.failed,
+                stats.busy,
+                stats.retries,
+                stats.lock_wait.summary(),
+                stats.latency
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = LatencyHistogram::new();
+        assert_eq!(histogram.percentile(50.0), Duration::ZERO);
+        assert_eq!(histogram.mean(), Duration::ZERO);
+
+        for micros in [0, 3, 3, 3, 700, 1500] {
+            histogram.record(Duration::from_micros(micros));
+        }
+        assert_eq!(histogram.count(), 6);
+        assert_eq!(histogram.percentile(50.0), Duration::from_micros(4));
+        assert_eq!(histogram.percentile(80.0), Duration::from_micros(1024));
+        assert_eq!(histogram.percentile(100.0), Duration::from_micros(1500));
+        assert_eq!(histogram.mean(), Duration::from_nanos(368_166));
+        assert_eq!(
+            histogram.buckets().collect::<Vec<_>>(),
+            [
+                (Duration::from_micros(1), 1),
+                (Duration::from_micros(4), 3),
+                (Duration::from_micros(1024), 1),
+                (Duration::from_micros(2048), 1),
+            ]
+        );
+
+        let mut merged = LatencyHistogram::new();
+        merged.record(Duration::from_secs(2));
+        merged.merge(&histogram);
+        assert_eq!(merged.count(), 7);
+        assert_eq!(merged.max(), Duration::from_secs(2));
+        assert!(merged.to_string().contains("\n  <        4µs       3 "));
+    }
+}